@@ -5,4 +5,7 @@ where cargo >nul 2>nul || (echo Rust/Cargo not found. Install from https://rustu
 call npm i || exit /b 1
 call npm run build || exit /b 1
 call npx tauri build --bundles nsis || exit /b 1
+rem Console build of the CLI, for scripts and MCP clients.
+call cargo build --release --bin promptforge-cli || exit /b 1
 echo Build artifacts under: src-tauri\target\release\bundle\
+echo CLI: target\release\promptforge-cli.exe
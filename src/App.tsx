@@ -31,7 +31,7 @@ const resolveUseOllamaStreamHook = (): typeof useOllamaStream =>
 
 const resolveInvokeFn = (): typeof invoke => selectAppMocks().invoke ?? invoke
 
-export type ComposeResult = { final_prompt: string; sha256: string; model: string }
+export type ComposeResult = { final_prompt: string; sha256: string; model: string; params?: Record<string, unknown> }
 type InvokeFunction = (cmd: string, args?: Record<string, unknown>) => Promise<unknown>
 
 type DocExcerpt = {
@@ -188,7 +188,7 @@ export default function App() {
   const useSetupCheckHook = resolveUseSetupCheckHook()
   const useOllamaStreamHook = resolveUseOllamaStreamHook()
   const { status: setupStatus, guidance: setupGuidance, retry: retrySetupCheck } = useSetupCheckHook(ollamaModel)
-  const showSetupBanner = setupStatus === 'offline' || setupStatus === 'missing-model'
+  const showSetupBanner = setupStatus !== 'ok'
   const invokeFn = resolveInvokeFn()
 
   // パラメータ
@@ -409,14 +409,16 @@ export default function App() {
       await startStream({
         model: ollamaModel,
         systemText: sys,
-        userText: user
+        userText: user,
+        recipePath,
+        params: c.params
       })
     } catch (error) {
       console.error('run ollama stream failed', error)
       setRunning(false)
       setOllamaError(describeOllamaError(error))
     }
-  }, [isStreaming, composed, doCompose, startStream, ollamaModel, clearStreamedResponse, resetOllamaError])
+  }, [isStreaming, composed, doCompose, startStream, ollamaModel, recipePath, clearStreamedResponse, resetOllamaError])
 
   // 右→左 反映（プレビュー付き）
   const diffFlow = useMemo(
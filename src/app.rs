@@ -0,0 +1,436 @@
+//! Tauri command wrappers over the core modules. Each command only unpacks
+//! Tauri state (window, managed `StreamState`, app handle) and forwards to a
+//! plain function; the logic lives in the module it calls.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tauri::{AppHandle, Emitter, Manager, Runtime, State, Window};
+
+use crate::attachments::load_images;
+use crate::batch::{self, BatchRequest};
+use crate::chat::{await_outcome, run_chat, start_chat_stream, ChatOutput, ChatStreamJob};
+use crate::compose::{self, ComposeResult};
+use crate::corpus_index::{self, CorpusSearch, IndexReport};
+use crate::files::{self, FileContent, ProjectEntry, PromptFileEntry};
+use crate::llm_backend::{self, BackendStatus};
+use crate::ollama_models::{self, ModelDetails, ModelSummary, RunningModel};
+use crate::ollama_stream::{
+    EventSink, GenerationStats, StreamInfo, StreamKind, StreamOutcome, StreamState,
+};
+use crate::profiles::resolve_profile;
+use crate::run_compare::{self, DiffGranularity, RunComparison};
+use crate::run_export::{self, ExportRequest, RunExport};
+use crate::run_gc::{self, GcReport, RetentionPolicy};
+use crate::run_index::{self, RunPage, RunQuery, RunRecord, RunSearchHit, RunSummary};
+use crate::run_replay::{self, ReplayOverrides, ReplayReport};
+use crate::runs::{self, StreamRunRequest};
+use crate::setup_check::{self, Locale, SetupCheckOutcome};
+use crate::sweep::{self, SweepReport, SweepRequest};
+use crate::txt_excerpt::{self, TxtExcerpt};
+use crate::workspace::{self, Workspace, WORKSPACE_FILE};
+
+impl<R: Runtime> EventSink for Window<R> {
+    fn emit_event(&self, event: &str, payload: serde_json::Value) {
+        let _ = self.emit(event, payload);
+    }
+}
+
+#[tauri::command]
+fn compose_prompt(
+    recipe_path: String,
+    inline_params: serde_json::Value,
+) -> Result<ComposeResult, String> {
+    compose::compose_prompt(&recipe_path, Some(inline_params)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn check_ollama_setup(
+    base_url: Option<String>,
+    model: Option<String>,
+    profile: Option<String>,
+    probe: Option<bool>,
+    locale: Option<Locale>,
+) -> Result<SetupCheckOutcome, String> {
+    setup_check::check_ollama_setup(base_url, model, profile, probe, locale).await
+}
+
+#[tauri::command]
+async fn backend_status(profile: String) -> Result<BackendStatus, String> {
+    llm_backend::backend_status(profile).await
+}
+
+#[tauri::command]
+async fn run_ollama_chat(
+    model: String,
+    system_text: String,
+    user_text: String,
+    images: Option<Vec<String>>,
+    output_schema: Option<serde_json::Value>,
+) -> Result<ChatOutput, String> {
+    let profile = resolve_profile(&model).map_err(|e| e.to_string())?;
+    let images = load_images(&images.unwrap_or_default())?;
+    run_chat(&profile, system_text, user_text, &images, output_schema).await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn run_ollama_stream<R: Runtime>(
+    window: Window<R>,
+    state: State<'_, StreamState>,
+    model: String,
+    system_text: String,
+    user_text: String,
+    recipe_path: Option<String>,
+    images: Option<Vec<String>>,
+    output_schema: Option<serde_json::Value>,
+    params: Option<serde_json::Value>,
+) -> Result<usize, String> {
+    let profile = resolve_profile(&model).map_err(|e| e.to_string())?;
+    // Validate attachments before taking a stream slot.
+    let images = load_images(&images.unwrap_or_default())?;
+    let job = ChatStreamJob::for_ui_prompt(
+        &compose::data_sandbox(),
+        &profile,
+        system_text,
+        user_text,
+        recipe_path,
+        images,
+        output_schema,
+        params,
+    );
+    start_chat_stream(Arc::new(window), state.inner(), job).await
+}
+
+/// Aborts one stream and returns its partial text and run id once the run
+/// record is written; `None` if the stream was not running.
+#[tauri::command]
+async fn abort_stream(
+    state: State<'_, StreamState>,
+    stream_id: usize,
+) -> Result<Option<StreamOutcome>, String> {
+    await_outcome(state.inner().abort(stream_id).await).await
+}
+
+#[tauri::command]
+async fn abort_current_stream(
+    state: State<'_, StreamState>,
+) -> Result<Option<StreamOutcome>, String> {
+    await_outcome(state.inner().abort_latest(StreamKind::Chat).await).await
+}
+
+#[tauri::command]
+async fn list_streams(state: State<'_, StreamState>) -> Result<Vec<StreamInfo>, String> {
+    Ok(state.inner().list().await)
+}
+
+#[tauri::command]
+fn set_stream_limit(state: State<'_, StreamState>, limit: usize) -> Result<usize, String> {
+    if limit == 0 {
+        return Err("stream limit must be at least 1".into());
+    }
+    state.inner().set_limit(limit);
+    Ok(state.inner().limit())
+}
+
+#[tauri::command]
+async fn list_ollama_models() -> Result<Vec<ModelSummary>, String> {
+    ollama_models::list_ollama_models().await
+}
+
+#[tauri::command]
+async fn list_running_ollama_models() -> Result<Vec<RunningModel>, String> {
+    ollama_models::list_running_ollama_models().await
+}
+
+#[tauri::command]
+async fn show_ollama_model(model: String) -> Result<ModelDetails, String> {
+    ollama_models::show_ollama_model(model).await
+}
+
+#[tauri::command]
+async fn pull_ollama_model<R: Runtime>(
+    window: Window<R>,
+    state: State<'_, StreamState>,
+    model: String,
+) -> Result<usize, String> {
+    ollama_models::start_model_pull(Arc::new(window), state.inner(), model).await
+}
+
+#[tauri::command]
+async fn delete_ollama_model(model: String) -> Result<(), String> {
+    ollama_models::delete_ollama_model(model).await
+}
+
+/// `model` and `params` are optional; with them `run.json` records the
+/// resolved profile and the fragments the prompt came from.
+#[tauri::command]
+fn save_run(
+    recipe_path: String,
+    final_prompt: String,
+    response_text: String,
+    stats: Option<GenerationStats>,
+    model: Option<String>,
+    params: Option<serde_json::Value>,
+) -> Result<String, String> {
+    let request = StreamRunRequest::for_saved_prompt(
+        &compose::data_sandbox(),
+        &recipe_path,
+        &final_prompt,
+        model,
+        params,
+    );
+    let dir = runs::save_run(&runs::runs_root(), &request, &response_text, stats.as_ref())
+        .map_err(|e| e.to_string())?;
+    Ok(dir.display().to_string())
+}
+
+/// Saved runs matching `query`, newest first; see [`RunQuery`] for filters.
+#[tauri::command]
+fn list_runs(query: Option<RunQuery>) -> Result<RunPage<RunSummary>, String> {
+    run_index::list_runs(&runs::runs_root(), &query.unwrap_or_default())
+}
+
+#[tauri::command]
+fn read_run(run_id: String) -> Result<RunRecord, String> {
+    run_index::read_run(&runs::runs_root(), &run_id).map_err(|e| e.to_string())
+}
+
+/// Full-text search over run prompts and responses, narrowed by `query`.
+#[tauri::command]
+fn search_runs(text: String, query: Option<RunQuery>) -> Result<RunPage<RunSearchHit>, String> {
+    run_index::search_runs(&runs::runs_root(), &text, &query.unwrap_or_default())
+}
+
+/// Diffs run `right` against the baseline `left`; the response is diffed by
+/// line unless `granularity` is `word`.
+#[tauri::command]
+fn compare_runs(
+    left: String,
+    right: String,
+    granularity: Option<DiffGranularity>,
+) -> Result<RunComparison, String> {
+    run_compare::compare_runs(
+        &runs::runs_root(),
+        &left,
+        &right,
+        granularity.unwrap_or_default(),
+    )
+}
+
+/// Re-composes and re-runs a saved run with its recorded settings, optionally
+/// with another model or params; streams like `run_ollama_stream` and resolves
+/// once the new run is saved.
+#[tauri::command]
+async fn replay_run<R: Runtime>(
+    window: Window<R>,
+    state: State<'_, StreamState>,
+    run_id: String,
+    overrides: Option<ReplayOverrides>,
+) -> Result<ReplayReport, String> {
+    run_replay::replay_run(
+        Arc::new(window),
+        state.inner(),
+        &compose::data_sandbox(),
+        runs::runs_root(),
+        &run_id,
+        &overrides.unwrap_or_default(),
+    )
+    .await
+}
+
+/// Renders the selected runs as a report or JSONL dataset with secrets masked;
+/// the caller saves `content`.
+#[tauri::command]
+fn export_runs(request: ExportRequest) -> Result<RunExport, String> {
+    run_export::export_runs(&runs::runs_root(), &request)
+}
+
+/// Pins or unpins a run; pinned runs are kept by [`gc_runs`].
+#[tauri::command]
+fn pin_run(run_id: String, pinned: bool) -> Result<RunSummary, String> {
+    let root = runs::runs_root();
+    let meta = runs::set_run_pinned(&root, &run_id, pinned).map_err(|e| e.to_string())?;
+    run_index::reindex_run(&root, &run_id).map_err(|e| e.to_string())?;
+    Ok(RunSummary::from(&meta))
+}
+
+#[tauri::command]
+fn read_retention_policy() -> Result<RetentionPolicy, String> {
+    run_gc::read_retention_policy(&runs::runs_root())
+}
+
+#[tauri::command]
+fn write_retention_policy(policy: RetentionPolicy) -> Result<(), String> {
+    run_gc::write_retention_policy(&runs::runs_root(), &policy)
+}
+
+/// Applies `policy` (default: the saved one) to the runs folder; with
+/// `dry_run` only reports what would be deleted.
+#[tauri::command]
+fn gc_runs(policy: Option<RetentionPolicy>, dry_run: bool) -> Result<GcReport, String> {
+    let root = runs::runs_root();
+    let policy = match policy {
+        Some(policy) => policy,
+        None => run_gc::read_retention_policy(&root)?,
+    };
+    run_gc::gc_runs(&root, &policy, dry_run)
+}
+
+/// Checks and plans the batch before returning its id, so bad input fails the
+/// call; the rows then run in the background and report as `batch:progress`
+/// and `batch:end`.
+#[tauri::command]
+async fn run_batch<R: Runtime>(window: Window<R>, request: BatchRequest) -> Result<String, String> {
+    batch::check_sandboxed(&request)?;
+    let prepared = batch::prepare_batch(&request)?;
+    let batch_id = prepared.batch_id.clone();
+    tokio::spawn(batch::run_batch(Arc::new(window), prepared));
+    Ok(batch_id)
+}
+
+/// Resolves once the sweep (and its runs, if requested) is saved; runs report
+/// as `batch:progress` with the group id as batch id.
+#[tauri::command]
+async fn compose_sweep<R: Runtime>(
+    window: Window<R>,
+    request: SweepRequest,
+) -> Result<SweepReport, String> {
+    sweep::compose_sweep(Arc::new(window), request).await
+}
+
+#[tauri::command]
+fn list_prompt_files(kind: String) -> Result<Vec<PromptFileEntry>, String> {
+    files::list_prompt_files(kind)
+}
+
+#[tauri::command]
+fn read_prompt_file(rel_path: String) -> Result<FileContent, String> {
+    files::read_prompt_file(rel_path)
+}
+
+#[tauri::command]
+fn list_project_files(exts: Option<Vec<String>>) -> Result<Vec<ProjectEntry>, String> {
+    files::list_project_files(exts)
+}
+
+#[tauri::command]
+fn read_project_file(rel_path: String) -> Result<FileContent, String> {
+    files::read_project_file(rel_path)
+}
+
+#[tauri::command]
+fn write_project_file(rel_path: String, content: String) -> Result<String, String> {
+    files::write_project_file(rel_path, content)
+}
+
+#[tauri::command]
+fn load_txt_excerpt(path: String, max_bytes: Option<u64>) -> Result<TxtExcerpt, String> {
+    txt_excerpt::load_txt_excerpt(&path, max_bytes)
+}
+
+#[tauri::command]
+async fn build_corpus_index(model: Option<String>) -> Result<IndexReport, String> {
+    corpus_index::build_corpus_index(model).await
+}
+
+#[tauri::command]
+async fn search_corpus(
+    query: String,
+    top_k: Option<usize>,
+    model: Option<String>,
+) -> Result<CorpusSearch, String> {
+    corpus_index::search_corpus(query, top_k, model).await
+}
+
+/// `workspace.json` in the app data dir, or the working directory when the
+/// platform has none.
+pub fn workspace_path<R: Runtime>(app: &AppHandle<R>) -> PathBuf {
+    match app.path().app_data_dir() {
+        Ok(dir) => dir.join(WORKSPACE_FILE),
+        Err(_) => PathBuf::from(WORKSPACE_FILE),
+    }
+}
+
+#[tauri::command]
+fn read_workspace<R: Runtime>(app: AppHandle<R>) -> Result<Option<Workspace>, String> {
+    workspace::read_workspace(&workspace_path(&app))
+}
+
+#[tauri::command]
+pub fn write_workspace<R: Runtime>(app: AppHandle<R>, ws: Workspace) -> Result<String, String> {
+    workspace::write_workspace(&workspace_path(&app), &ws)
+}
+
+#[doc(hidden)]
+pub mod workspace_test_support {
+    pub use super::{workspace_path, write_workspace};
+    pub use crate::workspace::Workspace;
+}
+
+pub fn configure_builder<R: Runtime>(builder: tauri::Builder<R>) -> tauri::Builder<R> {
+    builder
+        .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .manage(StreamState::default())
+        .setup(|_app| {
+            // Older timestamp-named runs get a `run.json` and the retention
+            // policy is applied if it asks to be; off the main thread so a
+            // large runs folder does not delay the window.
+            std::thread::spawn(|| {
+                let root = runs::runs_root();
+                if let Err(err) = runs::migrate_legacy_runs(&root) {
+                    eprintln!("run migration failed: {}", err);
+                }
+                match run_gc::gc_on_startup(&root) {
+                    Ok(Some(report)) if !report.removed.is_empty() => eprintln!(
+                        "removed {} run(s) under the retention policy",
+                        report.removed.len()
+                    ),
+                    Ok(_) => {}
+                    Err(err) => eprintln!("run retention failed: {}", err),
+                }
+            });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            compose_prompt,
+            check_ollama_setup,
+            backend_status,
+            run_ollama_chat,
+            run_ollama_stream,
+            abort_stream,
+            abort_current_stream,
+            list_streams,
+            set_stream_limit,
+            list_ollama_models,
+            list_running_ollama_models,
+            show_ollama_model,
+            pull_ollama_model,
+            delete_ollama_model,
+            save_run,
+            list_runs,
+            read_run,
+            search_runs,
+            compare_runs,
+            replay_run,
+            export_runs,
+            pin_run,
+            read_retention_policy,
+            write_retention_policy,
+            gc_runs,
+            run_batch,
+            compose_sweep,
+            list_prompt_files,
+            read_prompt_file,
+            list_project_files,
+            read_project_file,
+            write_project_file,
+            load_txt_excerpt,
+            build_corpus_index,
+            search_corpus,
+            read_workspace,
+            write_workspace
+        ])
+}
@@ -0,0 +1,152 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Sandboxes images may be attached from.
+const IMAGE_ROOTS: &[&str] = &["project", "corpus"];
+const IMAGE_ALLOWED_EXTS: &[&str] = &["png", "jpg", "jpeg", "webp"];
+/// Larger files are almost always video frames exported at full resolution;
+/// vision models downscale anyway.
+pub const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// An image read from a sandbox. Only the reference is recorded in runs; the
+/// encoded bytes go to the model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageAttachment {
+    pub path: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+    #[serde(skip)]
+    pub base64: String,
+}
+
+/// The sandbox `path` falls in and the file it names, both under `root`.
+fn resolve_image_path(root: &Path, path: &str) -> Result<(PathBuf, PathBuf), String> {
+    let raw = PathBuf::from(path);
+    let sandbox = match raw.components().next() {
+        Some(Component::Normal(first)) => IMAGE_ROOTS.iter().find(|r| first == **r),
+        _ => None,
+    };
+    match sandbox {
+        Some(sandbox) => Ok((root.join(sandbox), root.join(raw))),
+        None if raw.is_absolute() => {
+            let canonical = raw
+                .canonicalize()
+                .map_err(|_| "file not found".to_string())?;
+            IMAGE_ROOTS
+                .iter()
+                .map(|sandbox| root.join(sandbox))
+                .find(|base| {
+                    base.canonicalize()
+                        .is_ok_and(|base| canonical.starts_with(base))
+                })
+                .map(|base| (base, raw))
+                .ok_or_else(|| "path out of sandbox".to_string())
+        }
+        None => Err("images must live under project/ or corpus/".into()),
+    }
+}
+
+fn assert_allowed_image_ext(path: &Path) -> Result<(), String> {
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    if IMAGE_ALLOWED_EXTS.contains(&ext.as_str()) {
+        Ok(())
+    } else {
+        Err("unsupported image extension".into())
+    }
+}
+
+/// Validates and reads `path` (e.g. `project/refs/frame01.png`).
+pub fn load_image(path: &str) -> Result<ImageAttachment, String> {
+    load_image_in(Path::new(""), path)
+}
+
+/// [`load_image`] with `project/` and `corpus/` under `root` instead of the
+/// working directory.
+pub fn load_image_in(root: &Path, path: &str) -> Result<ImageAttachment, String> {
+    let (base, target) = resolve_image_path(root, path)?;
+    assert_allowed_image_ext(&target)?;
+    if !target.is_file() {
+        return Err("file not found".into());
+    }
+    crate::sandbox::ensure_under(&base, &target).map_err(|e| e.to_string())?;
+
+    let size_bytes = fs::metadata(&target).map_err(|e| e.to_string())?.len();
+    if size_bytes > MAX_IMAGE_BYTES {
+        return Err(format!(
+            "image too large: {} bytes (max {})",
+            size_bytes, MAX_IMAGE_BYTES
+        ));
+    }
+    let bytes = fs::read(&target).map_err(|e| e.to_string())?;
+    let sha256 = hex::encode(Sha256::digest(&bytes));
+    Ok(ImageAttachment {
+        path: path.to_string(),
+        size_bytes,
+        sha256,
+        base64: STANDARD.encode(&bytes),
+    })
+}
+
+pub fn load_images(paths: &[String]) -> Result<Vec<ImageAttachment>, String> {
+    paths.iter().map(|p| load_image(p)).collect()
+}
+
+/// MIME type guessed from the leading bytes of base64 image data.
+pub fn sniff_mime(base64: &str) -> &'static str {
+    if base64.starts_with("iVBOR") {
+        "image/png"
+    } else if base64.starts_with("UklGR") {
+        "image/webp"
+    } else {
+        "image/jpeg"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG_HEADER: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    #[test]
+    fn validates_sandbox_extension_and_size() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let dir = root.join("project").join("refs");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("frame.png"), PNG_HEADER).unwrap();
+        fs::write(dir.join("notes.txt"), "text").unwrap();
+        fs::write(root.join("outside.png"), PNG_HEADER).unwrap();
+
+        let image = load_image_in(root, "project/refs/frame.png").expect("load");
+        assert_eq!(image.path, "project/refs/frame.png");
+        assert_eq!(image.size_bytes, PNG_HEADER.len() as u64);
+        assert_eq!(image.sha256, hex::encode(Sha256::digest(PNG_HEADER)));
+        assert_eq!(sniff_mime(&image.base64), "image/png");
+        let recorded = serde_json::to_value(&image).unwrap();
+        assert!(recorded.get("base64").is_none());
+        let absolute = dir.join("frame.png");
+        assert!(load_image_in(root, absolute.to_str().unwrap()).is_ok());
+
+        assert!(load_image_in(root, "project/refs/notes.txt").is_err());
+        assert!(load_image_in(root, "project/refs/missing.png").is_err());
+        assert!(load_image_in(root, "data/frame.png").is_err());
+        assert!(load_image_in(root, "project/../outside.png").is_err());
+        let outside = root.join("outside.png");
+        assert!(load_image_in(root, outside.to_str().unwrap()).is_err());
+
+        let big = fs::File::create(dir.join("big.png")).unwrap();
+        big.set_len(MAX_IMAGE_BYTES + 1).unwrap();
+        let err = load_image_in(root, "project/refs/big.png").unwrap_err();
+        assert!(err.contains("too large"));
+    }
+}
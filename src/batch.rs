@@ -0,0 +1,566 @@
+//! Batch runs: one recipe applied to many inputs with bounded concurrency.
+//!
+//! Rows come from a JSONL file (`{"id"?, "params"?, "user_input"?}` per line)
+//! or from a glob over `corpus/` or `project/`, one row per file. Each row is
+//! composed and streamed like a normal run, then appended to a results JSONL.
+//! The results file is also the resume point: rows already recorded as
+//! completed with the same prompt sha256 are skipped on the next run.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::Local;
+use futures_util::stream::{self, StreamExt};
+use globset::Glob;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use walkdir::WalkDir;
+
+use crate::chat::{spawn_chat_stream, ChatStreamJob};
+use crate::compose::{compose_prompt_in, data_sandbox, split_final_prompt, ComposeResult};
+use crate::files::read_project_file;
+use crate::ollama_stream::{emit, EventSink, StreamState};
+use crate::profiles::resolve_profile_in;
+use crate::runs::{read_run_meta, runs_root, RunStatus};
+use crate::sandbox::ensure_under;
+use crate::txt_excerpt::load_txt_excerpt;
+
+pub const BATCHES_DIR: &str = "batches";
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 2;
+/// Folders a glob may walk; the first path segment of the pattern picks one.
+const GLOB_ROOTS: &[&str] = &["corpus", "project"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BatchInput {
+    /// One row per non-empty line.
+    Jsonl { path: String },
+    /// One row per matching file, e.g. `corpus/**/*.txt`.
+    Glob { pattern: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchRequest {
+    pub recipe: String,
+    pub input: BatchInput,
+    /// Params shared by every row; row params override them.
+    #[serde(default)]
+    pub params: Map<String, Value>,
+    /// Profile or model overriding the recipe's.
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+    /// Results JSONL; pointing at an existing one resumes that batch.
+    #[serde(default)]
+    pub results: Option<String>,
+    /// Excerpt limit for corpus files (see `load_txt_excerpt`).
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+}
+
+/// One input row; `params` already include `user_input`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchRow {
+    pub id: String,
+    pub params: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonlRow {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    params: Map<String, Value>,
+    #[serde(default)]
+    user_input: Option<String>,
+}
+
+/// One line of the results JSONL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub row_id: String,
+    pub status: RunStatus,
+    /// sha256 of the composed prompt; `None` when composing failed.
+    pub sha256: Option<String>,
+    pub run_id: Option<String>,
+    #[serde(default)]
+    pub response: String,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub params: Map<String, Value>,
+    pub finished_at: String,
+}
+
+/// `batch:progress`, sent after each row is recorded.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchProgress {
+    pub batch_id: String,
+    pub row_id: String,
+    pub status: RunStatus,
+    pub run_id: Option<String>,
+    pub done: usize,
+    pub total: usize,
+}
+
+/// `batch:end`, also returned by [`run_batch`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchSummary {
+    pub batch_id: String,
+    pub results_path: String,
+    pub total: usize,
+    /// Rows already completed in an earlier pass.
+    pub skipped: usize,
+    pub completed: usize,
+    pub failed: usize,
+}
+
+enum Planned {
+    Run(BatchRow, ComposeResult),
+    Failed(BatchResult),
+}
+
+/// A batch with its rows composed and resume state read; see [`prepare_batch`].
+pub struct PreparedBatch {
+    pub batch_id: String,
+    pub results_path: PathBuf,
+    pub recipe: String,
+    pub model: Option<String>,
+    pub concurrency: usize,
+    pub data_dir: PathBuf,
+    pub runs_root: PathBuf,
+    /// Written to each run's `run.json`; set by sweeps.
+    pub group_id: Option<String>,
+    pub skipped: usize,
+    planned: Vec<Planned>,
+}
+
+impl PreparedBatch {
+    /// An empty batch writing runs under [`runs_root`] with the default concurrency;
+    /// rows are added with [`PreparedBatch::push_row`].
+    pub fn new(batch_id: String, results_path: PathBuf, recipe: String, data_dir: PathBuf) -> Self {
+        PreparedBatch {
+            batch_id,
+            results_path,
+            recipe,
+            model: None,
+            concurrency: DEFAULT_BATCH_CONCURRENCY,
+            data_dir,
+            runs_root: runs_root(),
+            group_id: None,
+            skipped: 0,
+            planned: Vec::new(),
+        }
+    }
+
+    /// Queues a row whose prompt was already composed.
+    pub fn push_row(&mut self, row: BatchRow, composed: ComposeResult) {
+        self.planned.push(Planned::Run(row, composed));
+    }
+
+    /// Rows that will be recorded by [`run_batch`] (skipped rows excluded).
+    pub fn pending(&self) -> usize {
+        self.planned.len()
+    }
+}
+
+pub fn parse_jsonl_rows(text: &str) -> Result<Vec<BatchRow>, String> {
+    let mut rows = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: JsonlRow =
+            serde_json::from_str(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        let mut params = row.params;
+        if let Some(user_input) = row.user_input {
+            params.insert("user_input".into(), Value::String(user_input));
+        }
+        rows.push(BatchRow {
+            id: row.id.unwrap_or_else(|| format!("line-{}", index + 1)),
+            params,
+        });
+    }
+    Ok(rows)
+}
+
+/// Files matching `pattern` under `corpus/` or `project/`, read through the same
+/// sandboxed loaders as the app. Each row's `user_input` is the file text and
+/// `source_path` its path.
+pub fn glob_rows(pattern: &str, max_bytes: Option<u64>) -> Result<Vec<BatchRow>, String> {
+    let pattern = pattern.replace('\\', "/");
+    let root = pattern.split('/').next().unwrap_or_default();
+    if !GLOB_ROOTS.contains(&root) {
+        return Err(format!(
+            "glob must start with {}",
+            GLOB_ROOTS
+                .iter()
+                .map(|r| format!("`{}/`", r))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        ));
+    }
+    let matcher = Glob::new(&pattern)
+        .map_err(|e| e.to_string())?
+        .compile_matcher();
+    let mut paths: Vec<String> = WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().to_string_lossy().replace('\\', "/"))
+        .filter(|path| matcher.is_match(path))
+        .collect();
+    paths.sort();
+
+    let mut rows = Vec::with_capacity(paths.len());
+    for path in paths {
+        let rel = &path[root.len() + 1..];
+        let text = if root == "corpus" {
+            load_txt_excerpt(rel, max_bytes)?.excerpt
+        } else {
+            read_project_file(rel.to_string())?.content
+        };
+        let mut params = Map::new();
+        params.insert("user_input".into(), Value::String(text));
+        params.insert("source_path".into(), Value::String(path.clone()));
+        rows.push(BatchRow { id: path, params });
+    }
+    Ok(rows)
+}
+
+/// `(row_id, sha256)` pairs already completed in `results_path`.
+fn completed_rows(results_path: &Path) -> io::Result<HashSet<(String, String)>> {
+    let text = match fs::read_to_string(results_path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str::<BatchResult>(line).ok())
+        .filter(|result| result.status == RunStatus::Completed)
+        .filter_map(|result| Some((result.row_id, result.sha256?)))
+        .collect())
+}
+
+fn failed_row(row: BatchRow, sha256: Option<String>, error: String) -> BatchResult {
+    BatchResult {
+        row_id: row.id,
+        status: RunStatus::Failed,
+        sha256,
+        run_id: None,
+        response: String::new(),
+        error: Some(error),
+        params: row.params,
+        finished_at: Local::now().to_rfc3339(),
+    }
+}
+
+/// Composes every row of `rows` and drops the ones `results_path` already has.
+pub fn plan_batch(
+    request: &BatchRequest,
+    rows: Vec<BatchRow>,
+    data_dir: PathBuf,
+    results_path: PathBuf,
+) -> Result<PreparedBatch, String> {
+    let done = completed_rows(&results_path).map_err(|e| e.to_string())?;
+    let batch_id = results_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut batch = PreparedBatch::new(batch_id, results_path, request.recipe.clone(), data_dir);
+    batch.model = request.model.clone();
+    batch.concurrency = request
+        .concurrency
+        .unwrap_or(DEFAULT_BATCH_CONCURRENCY)
+        .max(1);
+    let mut seen = HashSet::new();
+    for mut row in rows {
+        if !seen.insert(row.id.clone()) {
+            return Err(format!("duplicate row id `{}`", row.id));
+        }
+        let mut params = request.params.clone();
+        params.append(&mut row.params);
+        row.params = params;
+        match compose_prompt_in(
+            &batch.data_dir,
+            &request.recipe,
+            Some(Value::Object(row.params.clone())),
+        ) {
+            Ok(composed) if done.contains(&(row.id.clone(), composed.sha256.clone())) => {
+                batch.skipped += 1;
+            }
+            Ok(composed) => batch.push_row(row, composed),
+            Err(err) => batch
+                .planned
+                .push(Planned::Failed(failed_row(row, None, err.to_string()))),
+        }
+    }
+    Ok(batch)
+}
+
+/// Rules for requests coming from the app: JSONL input must live under
+/// `corpus/` or `project/` and results under `batches/`. The CLI skips this.
+pub fn check_sandboxed(request: &BatchRequest) -> Result<(), String> {
+    if let BatchInput::Jsonl { path } = &request.input {
+        let inside = GLOB_ROOTS
+            .iter()
+            .any(|root| ensure_under(Path::new(root), Path::new(path)).is_ok());
+        if !inside {
+            return Err("path out of sandbox".into());
+        }
+    }
+    if let Some(results) = &request.results {
+        fs::create_dir_all(BATCHES_DIR).map_err(|e| e.to_string())?;
+        ensure_under(Path::new(BATCHES_DIR), Path::new(results)).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Loads the rows of `request` and plans them against the configured data
+/// directory. Without `results`, a new file under `batches/` is named after the
+/// recipe and the current time.
+pub fn prepare_batch(request: &BatchRequest) -> Result<PreparedBatch, String> {
+    let rows = match &request.input {
+        BatchInput::Jsonl { path } => {
+            parse_jsonl_rows(&fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?)?
+        }
+        BatchInput::Glob { pattern } => glob_rows(pattern, request.max_bytes)?,
+    };
+    let results_path = match &request.results {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let stem = Path::new(&request.recipe)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "batch".into());
+            PathBuf::from(BATCHES_DIR).join(format!(
+                "{}-{}.jsonl",
+                stem,
+                Local::now().format("%Y%m%d-%H%M%S")
+            ))
+        }
+    };
+    plan_batch(request, rows, data_sandbox(), results_path)
+}
+
+async fn run_row(
+    batch: &PreparedBatch,
+    sink: Arc<dyn EventSink>,
+    state: &StreamState,
+    row: BatchRow,
+    composed: ComposeResult,
+) -> BatchResult {
+    let sha256 = Some(composed.sha256.clone());
+    let model = batch
+        .model
+        .clone()
+        .unwrap_or_else(|| composed.model.clone());
+    let profile = match resolve_profile_in(&batch.data_dir, &model) {
+        Ok(profile) => profile,
+        Err(err) => return failed_row(row, sha256, err.to_string()),
+    };
+    let (system_text, user_text) = split_final_prompt(&composed.final_prompt);
+    let mut job = ChatStreamJob::new(
+        &profile,
+        system_text,
+        user_text,
+        Some(batch.recipe.clone()),
+        Vec::new(),
+        composed.output_schema.clone(),
+    )
+    .with_composition(&composed);
+    job.runs_root = batch.runs_root.clone();
+    job.run_request.group_id = batch.group_id.clone();
+    let outcome = match spawn_chat_stream(sink, state, job).await {
+        Ok((_, done)) => done.await,
+        Err(err) => return failed_row(row, sha256, err),
+    };
+    let Ok(outcome) = outcome else {
+        return failed_row(row, sha256, "stream ended without an outcome".into());
+    };
+    let error = outcome.save_error.or_else(|| {
+        let run_id = outcome.run_id.as_deref()?;
+        read_run_meta(&batch.runs_root, run_id)
+            .ok()?
+            .error
+            .map(|e| e.message)
+    });
+    BatchResult {
+        row_id: row.id,
+        status: outcome.status,
+        sha256,
+        run_id: outcome.run_id,
+        response: outcome.partial_text,
+        error,
+        params: row.params,
+        finished_at: Local::now().to_rfc3339(),
+    }
+}
+
+/// Runs the planned rows, at most `concurrency` at a time, appending each
+/// result to the results file as it finishes and emitting `batch:progress`;
+/// ends with `batch:end`.
+pub async fn run_batch(
+    sink: Arc<dyn EventSink>,
+    mut batch: PreparedBatch,
+) -> Result<BatchSummary, String> {
+    if let Some(dir) = batch.results_path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let mut results = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&batch.results_path)
+        .map_err(|e| e.to_string())?;
+
+    let planned = std::mem::take(&mut batch.planned);
+    let total = planned.len();
+    let state = StreamState::with_limit(batch.concurrency);
+    let batch = &batch;
+    let state = &state;
+    let mut finished = stream::iter(planned)
+        .map(|planned| {
+            let sink = sink.clone();
+            async move {
+                match planned {
+                    Planned::Run(row, composed) => run_row(batch, sink, state, row, composed).await,
+                    Planned::Failed(result) => result,
+                }
+            }
+        })
+        .buffer_unordered(batch.concurrency);
+
+    let mut summary = BatchSummary {
+        batch_id: batch.batch_id.clone(),
+        results_path: batch.results_path.display().to_string(),
+        total: total + batch.skipped,
+        skipped: batch.skipped,
+        completed: 0,
+        failed: 0,
+    };
+    let mut done = 0;
+    while let Some(result) = finished.next().await {
+        let line = serde_json::to_string(&result).map_err(|e| e.to_string())?;
+        writeln!(results, "{}", line)
+            .and_then(|_| results.flush())
+            .map_err(|e| e.to_string())?;
+        done += 1;
+        if result.status == RunStatus::Completed {
+            summary.completed += 1;
+        } else {
+            summary.failed += 1;
+        }
+        emit(
+            sink.as_ref(),
+            "batch:progress",
+            BatchProgress {
+                batch_id: batch.batch_id.clone(),
+                row_id: result.row_id,
+                status: result.status,
+                run_id: result.run_id,
+                done,
+                total,
+            },
+        );
+    }
+    emit(sink.as_ref(), "batch:end", summary.clone());
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ollama::{MockConfig, MockOllama};
+    use crate::ollama_stream::ChannelSink;
+    use crate::test_support::data_fixture;
+    use serde_json::json;
+    use tempfile::tempdir;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn parses_rows_and_defaults_ids() {
+        let rows = parse_jsonl_rows(
+            "{\"id\":\"a\",\"params\":{\"tone\":\"calm\"},\"user_input\":\"hi\"}\n\n{\"params\":{}}\n",
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].id, "a");
+        assert_eq!(
+            Value::Object(rows[0].params.clone()),
+            json!({"tone": "calm", "user_input": "hi"})
+        );
+        assert_eq!(rows[1].id, "line-3");
+        assert!(parse_jsonl_rows("{oops")
+            .unwrap_err()
+            .starts_with("line 1:"));
+        assert!(glob_rows("../**/*.txt", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn runs_rows_and_resumes_from_results() {
+        let temp = tempdir().unwrap();
+        let base = temp.path();
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        let data = data_fixture(base, &mock.url());
+
+        let request = BatchRequest {
+            recipe: "recipes/echo.yaml".into(),
+            input: BatchInput::Jsonl {
+                path: String::new(),
+            },
+            params: Map::new(),
+            model: None,
+            concurrency: Some(2),
+            results: None,
+            max_bytes: None,
+        };
+        let rows = |text: &str| parse_jsonl_rows(text).unwrap();
+        let results_path = base.join("batches/echo.jsonl");
+        let plan = |text: &str| {
+            let mut batch =
+                plan_batch(&request, rows(text), data.clone(), results_path.clone()).unwrap();
+            batch.runs_root = base.join("runs");
+            batch
+        };
+        let input = "{\"id\":\"a\",\"user_input\":\"one\"}\n{\"id\":\"b\",\"user_input\":\"two\"}\n{\"id\":\"c\",\"params\":{\"tone\":\"dry\"},\"user_input\":\"three\"}\n";
+
+        let (tx, mut events) = mpsc::unbounded_channel();
+        let sink: Arc<dyn EventSink> = Arc::new(ChannelSink(tx));
+        let summary = run_batch(sink.clone(), plan(input)).await.unwrap();
+        assert_eq!(
+            (summary.total, summary.completed, summary.skipped),
+            (3, 3, 0)
+        );
+        let lines: Vec<BatchResult> = fs::read_to_string(&results_path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines
+            .iter()
+            .all(|r| r.run_id.is_some() && r.sha256.is_some()));
+        let mut progress = 0;
+        while let Ok((event, _)) = events.try_recv() {
+            progress += usize::from(event == "batch:progress");
+        }
+        assert_eq!(progress, 3);
+
+        // Only the row whose prompt changed runs again.
+        let chats = mock.requests_to("/api/chat").len();
+        let edited = input.replace("three", "four");
+        let batch = plan(&edited);
+        assert_eq!((batch.skipped, batch.pending()), (2, 1));
+        let summary = run_batch(sink, batch).await.unwrap();
+        assert_eq!((summary.completed, summary.skipped), (1, 2));
+        assert_eq!(mock.requests_to("/api/chat").len(), chats + 1);
+        assert_eq!(
+            fs::read_to_string(&results_path).unwrap().lines().count(),
+            4
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Offline stand-in for a local Ollama server.
+//!
+//! ```text
+//! cargo run --bin mock_ollama -- [--addr 127.0.0.1:11434] [--model NAME]... [--reply TEXT]
+//! ```
+//!
+//! Point the app at it with `PROMPTFORGE_OLLAMA_URL=http://127.0.0.1:11434`.
+
+use std::env;
+use std::process::ExitCode;
+
+use promptforge::mock_ollama::{MockConfig, MockOllama};
+
+const USAGE: &str =
+    "usage: mock_ollama [--addr HOST:PORT] [--model NAME]... [--reply TEXT] [--context-length N]";
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(String, MockConfig), String> {
+    let mut addr = "127.0.0.1:11434".to_string();
+    let mut config = MockConfig::default();
+    let mut models = Vec::new();
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", flag));
+        match flag.as_str() {
+            "--addr" => addr = value()?,
+            "--model" => models.push(value()?),
+            "--reply" => config.reply = value()?,
+            "--context-length" => {
+                config.context_length = value()?
+                    .parse()
+                    .map_err(|_| "--context-length must be a number".to_string())?
+            }
+            "-h" | "--help" => return Err(USAGE.into()),
+            other => return Err(format!("unknown argument `{}`\n{}", other, USAGE)),
+        }
+    }
+    if !models.is_empty() {
+        config.models = models;
+    }
+    Ok((addr, config))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let (addr, config) = match parse_args(env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+    let models = config.models.join(", ");
+    let server = match MockOllama::bind(&addr, config).await {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("failed to bind {}: {}", addr, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!(
+        "mock ollama listening on {} (models: {})",
+        server.url(),
+        models
+    );
+    server.serve_forever().await;
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,18 @@
+//! Console build of the headless CLI.
+//!
+//! ```text
+//! promptforge-cli compose recipes/demo.sora2.yaml -p steps=4
+//! promptforge-cli mcp
+//! ```
+//!
+//! `promptforge` itself is a GUI-subsystem executable in Windows release
+//! builds, so it has no stdin/stdout there; scripts and MCP clients should
+//! launch this binary instead. It never opens a window.
+
+use std::env;
+
+use promptforge::cli;
+
+fn main() {
+    std::process::exit(cli::run(env::args_os().collect()));
+}
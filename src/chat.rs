@@ -0,0 +1,482 @@
+//! Chat runs: one-shot replies and registered streams that report through an
+//! [`EventSink`] and end in a run record.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex, PoisonError};
+use std::time::Duration;
+
+use chrono::Local;
+use futures_util::future::{AbortHandle, Abortable};
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::attachments::ImageAttachment;
+use crate::compose::{matching_composition, ComposeResult};
+use crate::llm_backend::{backend_for, ChatRequest, LlmBackend};
+use crate::llm_replay::{session_backend, SessionMode};
+use crate::ollama_client::{OllamaError, OllamaErrorKind};
+use crate::ollama_stream::{
+    emit, EventSink, FirstTokenTimer, OllamaEvent, StreamCapture, StreamChunkPayload,
+    StreamEndPayload, StreamErrorPayload, StreamKind, StreamOutcome, StreamState,
+};
+use crate::profiles::Profile;
+use crate::runs::{runs_root, write_stream_run_with, RunStatus, StreamRunRequest};
+use crate::structured_output::{validate_with_repair, StructuredOutput};
+
+/// How long a finished run waits for the server version before writing its
+/// manifest without it.
+const VERSION_WAIT: Duration = Duration::from_secs(2);
+
+/// Reply of `run_ollama_chat`; `structured` is set when a schema was requested.
+#[derive(Debug, Serialize)]
+pub struct ChatOutput {
+    pub text: String,
+    pub structured: Option<StructuredOutput>,
+}
+
+/// Runs one non-streaming chat against `profile`'s backend. It writes no run,
+/// so it always talks to the server: record/replay only covers
+/// [`ChatStreamJob`] runs.
+pub async fn run_chat(
+    profile: &Profile,
+    system_text: String,
+    user_text: String,
+    images: &[ImageAttachment],
+    output_schema: Option<serde_json::Value>,
+) -> Result<ChatOutput, String> {
+    let request = ChatRequest::from_profile(profile, system_text, user_text)
+        .with_images(images)
+        .with_format(output_schema);
+    let backend = backend_for(profile);
+    let text = backend.chat(&request).await.map_err(|e| e.to_string())?;
+    let structured = match &request.format {
+        Some(schema) => Some(
+            validate_with_repair(backend.as_ref(), &request, schema, text.clone())
+                .await
+                .map_err(|e| e.to_string())?,
+        ),
+        None => None,
+    };
+    Ok(ChatOutput { text, structured })
+}
+
+/// Validates a finished schema-constrained stream, re-prompting if needed.
+async fn validate_stream_reply(
+    backend: &dyn LlmBackend,
+    request: &ChatRequest,
+    capture: &StdMutex<StreamCapture>,
+) -> Result<(), OllamaError> {
+    let Some(schema) = &request.format else {
+        return Ok(());
+    };
+    let text = {
+        let capture = capture.lock().unwrap_or_else(PoisonError::into_inner);
+        if capture.error.is_some() {
+            return Ok(());
+        }
+        capture.text.clone()
+    };
+    let structured = validate_with_repair(backend, request, schema, text).await?;
+    capture
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .structured = Some(structured);
+    Ok(())
+}
+
+/// Emits one parsed event for `stream_id` and records it in `capture`; returns
+/// `true` once the stream is finished.
+fn emit_ollama_event(
+    sink: &dyn EventSink,
+    stream_id: usize,
+    event: OllamaEvent,
+    timer: &mut FirstTokenTimer,
+    capture: &mut StreamCapture,
+) -> bool {
+    match event {
+        OllamaEvent::Chunk(text) => {
+            timer.mark();
+            capture.text.push_str(&text);
+            emit(sink, "ollama:chunk", StreamChunkPayload { stream_id, text });
+            false
+        }
+        OllamaEvent::Done(mut stats) => {
+            stats.time_to_first_token_ms = timer.first_token_ms();
+            capture.stats = Some(stats.clone());
+            emit(sink, "ollama:end", StreamEndPayload { stream_id, stats });
+            true
+        }
+        OllamaEvent::Error(message) => {
+            let error = OllamaError::new(OllamaErrorKind::Model, message);
+            capture.error = Some(error.clone());
+            emit(
+                sink,
+                "ollama:error",
+                StreamErrorPayload::new(stream_id, error),
+            );
+            true
+        }
+    }
+}
+
+fn emit_ollama_line(
+    sink: &dyn EventSink,
+    stream_id: usize,
+    line: &str,
+    events: Vec<OllamaEvent>,
+    timer: &mut FirstTokenTimer,
+    capture: &StdMutex<StreamCapture>,
+) -> bool {
+    let mut capture = capture.lock().unwrap_or_else(PoisonError::into_inner);
+    capture.raw.push_str(line);
+    capture.raw.push('\n');
+    for event in events {
+        if emit_ollama_event(sink, stream_id, event, timer, &mut capture) {
+            return true;
+        }
+    }
+    false
+}
+
+/// A validated chat stream, ready to be registered and spawned.
+pub struct ChatStreamJob {
+    /// `None` builds the `mode` backend for the run's profile once the job
+    /// is spawned, so replay reads the final `runs_root`.
+    pub backend: Option<Box<dyn LlmBackend>>,
+    pub mode: SessionMode,
+    pub request: ChatRequest,
+    pub run_request: StreamRunRequest,
+    pub runs_root: PathBuf,
+}
+
+impl ChatStreamJob {
+    /// A stream of `profile` saved under [`runs_root`], honouring the
+    /// record/replay mode.
+    pub fn new(
+        profile: &Profile,
+        system_text: String,
+        user_text: String,
+        recipe_path: Option<String>,
+        images: Vec<ImageAttachment>,
+        output_schema: Option<serde_json::Value>,
+    ) -> Self {
+        let run_request = StreamRunRequest {
+            model: profile.model.clone(),
+            recipe_path,
+            system_text: system_text.clone(),
+            user_text: user_text.clone(),
+            images: images.clone(),
+            output_schema: output_schema
+                .clone()
+                .or_else(|| profile.output_schema.clone()),
+            started_at: Local::now().to_rfc3339(),
+            group_id: None,
+            profile: Some(profile.clone()),
+            params: serde_json::Value::Null,
+            fragments: Vec::new(),
+            ollama_version: None,
+            replay_of: None,
+        };
+        Self {
+            backend: None,
+            mode: SessionMode::from_env(),
+            request: ChatRequest::from_profile(profile, system_text, user_text)
+                .with_images(&images)
+                .with_format(output_schema),
+            run_request,
+            runs_root: runs_root(),
+        }
+    }
+
+    /// Records the params and fragments `composed` was built from in the run
+    /// manifest.
+    pub fn with_composition(mut self, composed: &ComposeResult) -> Self {
+        self.run_request.params = composed.params.clone();
+        self.run_request.fragments = composed.fragments.clone();
+        self
+    }
+
+    /// A job for a prompt the UI composed from `recipe_path` with `params`
+    /// (sandboxed in `sandbox`). When re-composing reproduces the prompt, the
+    /// run records its params and fragments and the recipe's output schema
+    /// applies unless `output_schema` is given.
+    #[allow(clippy::too_many_arguments)]
+    pub fn for_ui_prompt(
+        sandbox: &Path,
+        profile: &Profile,
+        system_text: String,
+        user_text: String,
+        recipe_path: Option<String>,
+        images: Vec<ImageAttachment>,
+        output_schema: Option<serde_json::Value>,
+        params: Option<serde_json::Value>,
+    ) -> Self {
+        let composed = match (&recipe_path, params) {
+            (Some(recipe), Some(params)) => matching_composition(
+                sandbox,
+                recipe,
+                params,
+                &format!("{}{}", system_text, user_text),
+            ),
+            _ => None,
+        };
+        let output_schema =
+            output_schema.or_else(|| composed.as_ref().and_then(|c| c.output_schema.clone()));
+        let job = Self::new(
+            profile,
+            system_text,
+            user_text,
+            recipe_path,
+            images,
+            output_schema,
+        );
+        match &composed {
+            Some(composed) => job.with_composition(composed),
+            None => job,
+        }
+    }
+}
+
+/// Registers `job` in `state`, streams it in the background and reports
+/// through `sink`; resolves to the stream id as soon as the slot is taken.
+pub async fn start_chat_stream(
+    sink: Arc<dyn EventSink>,
+    state: &StreamState,
+    job: ChatStreamJob,
+) -> Result<usize, String> {
+    let (stream_id, _) = spawn_chat_stream(sink, state, job).await?;
+    Ok(stream_id)
+}
+
+/// Like [`start_chat_stream`], plus a receiver that resolves once the run
+/// record is written, for callers that wait on each stream (batches, CLI).
+pub async fn spawn_chat_stream(
+    sink: Arc<dyn EventSink>,
+    state: &StreamState,
+    job: ChatStreamJob,
+) -> Result<(usize, oneshot::Receiver<StreamOutcome>), String> {
+    let ChatStreamJob {
+        backend,
+        mode,
+        request,
+        mut run_request,
+        runs_root,
+    } = job;
+    let (backend, recorder) = match backend {
+        Some(backend) => (backend, None),
+        None => {
+            let profile = run_request
+                .profile
+                .clone()
+                .unwrap_or_else(|| Profile::for_model(&request.model));
+            session_backend(&profile, &runs_root, mode)
+        }
+    };
+    let backend: Arc<dyn LlmBackend> = Arc::from(backend);
+    let (handle, registration) = AbortHandle::new_pair();
+    let (stream_id, outcome_tx) = state
+        .register(handle, StreamKind::Chat, &request.model)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let (done_tx, done_rx) = oneshot::channel();
+    let state_for_cleanup = state.clone();
+    let sink_for_task = sink.clone();
+    let capture = Arc::new(StdMutex::new(StreamCapture::default()));
+    let capture_for_task = capture.clone();
+    // Asked alongside the stream so the manifest does not wait on it.
+    let version = tokio::spawn({
+        let backend = backend.clone();
+        async move { backend.version().await.ok().flatten() }
+    });
+
+    let task = async move {
+        let mut timer = FirstTokenTimer::start();
+        let send_result = backend
+            .stream(&request, &mut |line, events| {
+                emit_ollama_line(
+                    sink_for_task.as_ref(),
+                    stream_id,
+                    line,
+                    events,
+                    &mut timer,
+                    &capture_for_task,
+                )
+            })
+            .await;
+        let send_result = match send_result {
+            Ok(()) => validate_stream_reply(backend.as_ref(), &request, &capture_for_task).await,
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = send_result {
+            capture_for_task
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .error = Some(error.clone());
+            emit(
+                sink_for_task.as_ref(),
+                "ollama:error",
+                StreamErrorPayload::new(stream_id, error),
+            );
+        }
+    };
+
+    // The record is written here rather than inside `task` so that aborted
+    // streams still persist whatever text had arrived.
+    let abortable = Abortable::new(task, registration);
+    tokio::spawn(async move {
+        let aborted = abortable.await.is_err();
+        state_for_cleanup.remove(stream_id).await;
+        let capture = capture
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let status = if aborted {
+            RunStatus::Aborted
+        } else {
+            capture.status()
+        };
+        run_request.ollama_version = tokio::time::timeout(VERSION_WAIT, version)
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .flatten();
+        let saved = write_stream_run_with(&runs_root, &run_request, &capture, status, |dir| {
+            if let Some(recorder) = &recorder {
+                recorder.save(dir)?;
+            }
+            Ok(())
+        });
+        let (run_id, save_error) = match saved {
+            Ok((run_id, _)) => (Some(run_id), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        let outcome = StreamOutcome {
+            stream_id,
+            status,
+            run_id,
+            partial_text: capture.text,
+            save_error,
+            structured: capture.structured,
+        };
+        emit(sink.as_ref(), "ollama:saved", outcome.clone());
+        let _ = done_tx.send(outcome.clone());
+        let _ = outcome_tx.send(outcome);
+    });
+
+    Ok((stream_id, done_rx))
+}
+
+/// Waits for an aborted stream to report how it ended.
+pub async fn await_outcome(
+    receiver: Option<oneshot::Receiver<StreamOutcome>>,
+) -> Result<Option<StreamOutcome>, String> {
+    match receiver {
+        Some(receiver) => Ok(receiver.await.ok()),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compose::{compose_prompt_in, split_final_prompt};
+    use crate::llm_replay::RECORDINGS_DIR;
+    use crate::mock_ollama::{MockConfig, MockOllama, MockReply};
+    use crate::ollama_stream::ChannelSink;
+    use crate::test_support::write;
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn ui_prompts_use_the_recipe_output_schema() {
+        let temp = tempfile::tempdir().unwrap();
+        let data = temp.path();
+        write(
+            data,
+            "fragments/task/list.yaml",
+            "id: task.list\nkind: task\ncontent: \"List {{count}} shots.\"\n",
+        );
+        write(
+            data,
+            "recipes/list.yaml",
+            "profile: llama3:8b\nfragments:\n  - task.list\nparams:\n  count: 2\n\
+             output_schema:\n  type: array\n",
+        );
+        let composed = compose_prompt_in(data, "recipes/list.yaml", None).unwrap();
+        let (system_text, user_text) = split_final_prompt(&composed.final_prompt);
+        let profile = Profile::for_model("llama3:8b");
+        let job = |output_schema: Option<serde_json::Value>| {
+            ChatStreamJob::for_ui_prompt(
+                data,
+                &profile,
+                system_text.clone(),
+                user_text.clone(),
+                Some("recipes/list.yaml".into()),
+                Vec::new(),
+                output_schema,
+                Some(composed.params.clone()),
+            )
+        };
+
+        let from_recipe = job(None);
+        assert_eq!(from_recipe.request.format, Some(json!({"type": "array"})));
+        assert_eq!(
+            from_recipe.run_request.output_schema,
+            Some(json!({"type": "array"}))
+        );
+        assert_eq!(from_recipe.run_request.params["count"], 2);
+
+        let explicit = job(Some(json!({"type": "object"})));
+        assert_eq!(explicit.request.format, Some(json!({"type": "object"})));
+    }
+
+    #[tokio::test]
+    async fn streams_record_and_replay_under_the_runs_root_set_after_new() {
+        let temp = tempfile::tempdir().unwrap();
+        let runs_root = temp.path().join("runs");
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        mock.push("/api/chat", MockReply::tokens(["Hel", "lo"]));
+        let profile = Profile {
+            base_url: Some(mock.url()),
+            ..Profile::for_model("llama3:8b")
+        };
+        let state = StreamState::default();
+        let (tx, _events) = mpsc::unbounded_channel();
+        let sink: Arc<dyn EventSink> = Arc::new(ChannelSink(tx));
+        let run = |mode: SessionMode| {
+            let mut job =
+                ChatStreamJob::new(&profile, String::new(), "hi".into(), None, Vec::new(), None);
+            job.mode = mode;
+            job.runs_root = runs_root.clone();
+            spawn_chat_stream(sink.clone(), &state, job)
+        };
+
+        let (_, done) = run(SessionMode::Record).await.unwrap();
+        let recorded = done.await.unwrap();
+        let run_id = recorded.run_id.unwrap();
+        assert!(runs_root.join(&run_id).join(RECORDINGS_DIR).is_dir());
+        drop(mock);
+
+        let (_, done) = run(SessionMode::Replay).await.unwrap();
+        let replayed = done.await.unwrap();
+        assert_eq!(replayed.status, RunStatus::Completed);
+        assert_eq!(replayed.partial_text, "Hello");
+    }
+
+    #[tokio::test]
+    async fn one_shot_chats_always_reach_the_server() {
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        mock.push("/api/chat", MockReply::tokens(["Hi"]));
+        let profile = Profile {
+            base_url: Some(mock.url()),
+            ..Profile::for_model("llama3:8b")
+        };
+        let output = run_chat(&profile, String::new(), "hi".into(), &[], None)
+            .await
+            .unwrap();
+        assert_eq!(output.text, "Hi");
+        assert_eq!(mock.requests_to("/api/chat").len(), 1);
+    }
+}
@@ -0,0 +1,1020 @@
+//! Headless entry point: `promptforge <command>` runs the same composition,
+//! streaming and run-storage code as the app without opening a window, for
+//! shell scripts and cron.
+
+use std::ffi::OsString;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use tokio::sync::mpsc;
+
+use crate::attachments::load_images;
+use crate::batch::{prepare_batch, run_batch, BatchInput, BatchRequest, DEFAULT_BATCH_CONCURRENCY};
+use crate::chat::{start_chat_stream, ChatStreamJob};
+use crate::compose::{
+    compose_prompt, data_sandbox, split_final_prompt, ComposeResult, FragmentSource,
+};
+use crate::mcp_server::serve_stdio;
+use crate::ollama_stream::{ChannelSink, StreamState};
+use crate::openai_proxy::{ProxyConfig, ProxyServer, DEFAULT_PROXY_ADDR};
+use crate::profiles::resolve_profile;
+use crate::recipe_lint::{lint_recipe, LintLevel};
+use crate::run_compare::{compare_runs, ChangeKind, Delta, DiffGranularity, RunComparison};
+use crate::run_export::{export_runs, ExportFormat, ExportRequest};
+use crate::run_gc::{gc_runs, read_retention_policy, write_retention_policy, GcReport};
+use crate::run_index::{list_runs, read_run, reindex_run, search_runs, RunQuery, RunSummary};
+use crate::run_replay::{prepare_replay, ReplayOverrides};
+use crate::runs::{
+    import_legacy_runs, migrate_legacy_runs, runs_root, set_run_pinned, RunStatus, RUNS_DIR,
+};
+use crate::setup_check::{check_ollama_setup, Locale, SetupStatus};
+use crate::sweep::{compose_sweep, SweepRequest};
+use crate::txt_excerpt::load_txt_excerpt;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "promptforge",
+    version,
+    about = "Compose and run prompt recipes without the GUI"
+)]
+struct Cli {
+    /// Print JSON instead of plain text.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compose a recipe into its final prompt.
+    Compose(ComposeArgs),
+    /// Compose a recipe and stream it through its profile's backend.
+    Run(RunArgs),
+    /// Run a recipe over every row of a JSONL file or every file matching a glob.
+    Batch(BatchArgs),
+    /// Compose (and optionally run) every combination of a few param values.
+    Sweep(SweepArgs),
+    /// Serve an OpenAI-compatible API on localhost; `recipe:<name>` models
+    /// compose that recipe.
+    Serve {
+        /// Loopback address to listen on.
+        #[arg(long, default_value = DEFAULT_PROXY_ADDR)]
+        addr: String,
+    },
+    /// Serve recipes, compose/run tools and project/corpus files over MCP on
+    /// stdin/stdout.
+    Mcp,
+    /// Inspect saved runs.
+    #[command(subcommand)]
+    Runs(RunsCommand),
+    /// Check recipes for missing fragments and param mismatches.
+    Lint {
+        #[arg(required = true)]
+        recipes: Vec<String>,
+    },
+    /// Print the head/tail excerpt of a text file under corpus/.
+    Excerpt {
+        path: String,
+        #[arg(long)]
+        max_bytes: Option<u64>,
+    },
+    /// Check that the Ollama server and model are ready.
+    SetupCheck {
+        #[arg(long)]
+        base_url: Option<String>,
+        #[arg(long)]
+        model: Option<String>,
+        #[arg(long)]
+        profile: Option<String>,
+        /// Also generate one token to measure latency.
+        #[arg(long)]
+        probe: bool,
+        #[arg(long, value_parser = ["ja", "en"])]
+        locale: Option<String>,
+    },
+}
+
+#[derive(Debug, Args)]
+struct ComposeArgs {
+    /// Recipe path, relative to the data directory.
+    recipe: String,
+    /// Overrides a recipe param; the value is parsed as JSON when possible.
+    #[arg(short = 'p', long = "param", value_name = "KEY=VALUE")]
+    params: Vec<String>,
+    /// Text for the USER_INPUT block; `-` reads stdin.
+    #[arg(short, long)]
+    input: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct RunArgs {
+    #[command(flatten)]
+    compose: ComposeArgs,
+    /// Model or profile to use instead of the recipe's.
+    #[arg(long)]
+    model: Option<String>,
+    /// Image under project/ or corpus/ to attach.
+    #[arg(long = "image", value_name = "PATH")]
+    images: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+struct BatchArgs {
+    /// Recipe path, relative to the data directory.
+    recipe: String,
+    /// JSONL with one `{"id", "params", "user_input"}` row per line.
+    #[arg(long, required_unless_present = "glob", conflicts_with = "glob")]
+    jsonl: Option<String>,
+    /// Files under corpus/ or project/, one row each (e.g. `corpus/**/*.txt`).
+    #[arg(long)]
+    glob: Option<String>,
+    /// Param shared by every row; the value is parsed as JSON when possible.
+    #[arg(short = 'p', long = "param", value_name = "KEY=VALUE")]
+    params: Vec<String>,
+    /// Model or profile to use instead of the recipe's.
+    #[arg(long)]
+    model: Option<String>,
+    #[arg(long, default_value_t = DEFAULT_BATCH_CONCURRENCY)]
+    concurrency: usize,
+    /// Results JSONL; pass an existing one to resume that batch.
+    #[arg(long)]
+    results: Option<String>,
+    /// Excerpt limit for corpus files.
+    #[arg(long)]
+    max_bytes: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+struct SweepArgs {
+    /// Recipe path, relative to the data directory.
+    recipe: String,
+    /// Values to try for one param: `tone=calm,dry` or a JSON array.
+    #[arg(long = "axis", value_name = "KEY=VALUES", required = true)]
+    axes: Vec<String>,
+    /// Param shared by every variant; the value is parsed as JSON when possible.
+    #[arg(short = 'p', long = "param", value_name = "KEY=VALUE")]
+    params: Vec<String>,
+    /// Compose only this many combinations, picked at random.
+    #[arg(long)]
+    sample: Option<usize>,
+    /// Seed for --sample, to pick the same combinations again.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Also run every distinct variant.
+    #[arg(long)]
+    run: bool,
+    /// Model or profile to use instead of the recipe's.
+    #[arg(long)]
+    model: Option<String>,
+    #[arg(long, default_value_t = DEFAULT_BATCH_CONCURRENCY)]
+    concurrency: usize,
+}
+
+#[derive(Debug, Subcommand)]
+enum RunsCommand {
+    /// List runs, newest first.
+    List(RunFilterArgs),
+    /// Find runs whose prompt or response contains every word of TEXT.
+    Search {
+        text: String,
+        #[command(flatten)]
+        filter: RunFilterArgs,
+    },
+    /// Show a run's metadata and response.
+    Show { run_id: String },
+    /// Diff RIGHT against the baseline LEFT: prompt, fragments, params,
+    /// response and timing/token deltas.
+    Compare {
+        left: String,
+        right: String,
+        /// Diff the responses word by word instead of by line.
+        #[arg(long)]
+        words: bool,
+    },
+    /// Re-compose and re-run a run with its recorded model, options and seed.
+    Replay {
+        run_id: String,
+        /// Model or profile to use instead; the recorded options are kept.
+        #[arg(long)]
+        model: Option<String>,
+        /// Overrides a recorded param; the value is parsed as JSON when possible.
+        #[arg(short = 'p', long = "param", value_name = "KEY=VALUE")]
+        params: Vec<String>,
+    },
+    /// Export runs as a Markdown or HTML report, or a JSONL dataset, with
+    /// secrets masked. Without RUN_IDS, exports every run matching the filters.
+    Export {
+        run_ids: Vec<String>,
+        #[command(flatten)]
+        filter: RunFilterArgs,
+        /// markdown, html or jsonl.
+        #[arg(long, default_value = "markdown", value_parser = parse_export_format)]
+        format: ExportFormat,
+        /// File to write instead of stdout.
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Pin a run so the retention policy never removes it.
+    Pin {
+        run_id: String,
+        /// Unpin it instead.
+        #[arg(long)]
+        unpin: bool,
+    },
+    /// Remove runs beyond the retention policy saved in `retention.json`;
+    /// the limit flags override it for this call.
+    Gc(GcArgs),
+    /// Move timestamp-named runs and sweep groups saved by older versions
+    /// into the runs folder.
+    Import {
+        /// Folder the runs were saved in.
+        #[arg(long, default_value = RUNS_DIR)]
+        from: PathBuf,
+    },
+}
+
+#[derive(Debug, Args)]
+struct GcArgs {
+    #[arg(long)]
+    max_age_days: Option<u64>,
+    #[arg(long)]
+    max_runs: Option<usize>,
+    #[arg(long)]
+    max_total_mb: Option<u64>,
+    /// List what would be removed without deleting anything.
+    #[arg(long)]
+    dry_run: bool,
+    /// Save the resulting policy as the default.
+    #[arg(long)]
+    save: bool,
+    /// With --save: also apply the policy when the app starts.
+    #[arg(long, requires = "save")]
+    on_startup: bool,
+}
+
+#[derive(Debug, Args)]
+struct RunFilterArgs {
+    /// Part of the recipe path.
+    #[arg(long)]
+    recipe: Option<String>,
+    #[arg(long)]
+    model: Option<String>,
+    /// completed, aborted, failed or schema_invalid.
+    #[arg(long, value_parser = parse_status)]
+    status: Option<RunStatus>,
+    /// YYYY-MM-DD or RFC 3339.
+    #[arg(long)]
+    since: Option<String>,
+    /// YYYY-MM-DD (inclusive) or RFC 3339.
+    #[arg(long)]
+    until: Option<String>,
+    #[arg(long, default_value_t = 0)]
+    offset: usize,
+    /// At most this many runs [default: 20, or all for export].
+    #[arg(long)]
+    limit: Option<usize>,
+}
+
+impl RunFilterArgs {
+    fn query(self) -> RunQuery {
+        RunQuery {
+            limit: self.limit.or(Some(20)),
+            ..self.all()
+        }
+    }
+
+    /// The query without the default page size.
+    fn all(self) -> RunQuery {
+        RunQuery {
+            recipe: self.recipe,
+            model: self.model,
+            status: self.status,
+            since: self.since,
+            until: self.until,
+            offset: self.offset,
+            limit: self.limit,
+        }
+    }
+}
+
+/// Whether `args` (including the program name) ask for the CLI rather than
+/// the window.
+pub fn is_cli_invocation(args: &[OsString]) -> bool {
+    let Some(first) = args.get(1).and_then(|a| a.to_str()) else {
+        return false;
+    };
+    matches!(
+        first,
+        "-h" | "--help" | "-V" | "--version" | "help" | "--json"
+    ) || Cli::command()
+        .get_subcommands()
+        .any(|c| c.get_name() == first)
+}
+
+/// Parses `args`, runs the command and returns the process exit code.
+pub fn run(args: Vec<OsString>) -> i32 {
+    let cli = match Cli::try_parse_from(args) {
+        Ok(cli) => cli,
+        Err(err) => {
+            let _ = err.print();
+            return err.exit_code();
+        }
+    };
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return 1;
+        }
+    };
+    match runtime.block_on(dispatch(cli.command, cli.json)) {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            1
+        }
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn parse_param(raw: &str) -> Result<(String, Value), String> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("param `{}` must look like KEY=VALUE", raw))?;
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.into()));
+    Ok((key.trim().to_string(), value))
+}
+
+/// `KEY=[..]` takes a JSON array; otherwise the values are split on commas and
+/// each one is parsed like a param value.
+fn parse_axis(raw: &str) -> Result<(String, Vec<Value>), String> {
+    let (key, values) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("axis `{}` must look like KEY=VALUES", raw))?;
+    let values = match serde_json::from_str(values) {
+        Ok(Value::Array(values)) => values,
+        _ => values
+            .split(',')
+            .map(|v| {
+                let v = v.trim();
+                serde_json::from_str(v).unwrap_or_else(|_| Value::String(v.into()))
+            })
+            .collect(),
+    };
+    Ok((key.trim().to_string(), values))
+}
+
+fn inline_params(args: &ComposeArgs) -> Result<Value, String> {
+    let mut params = Map::new();
+    for raw in &args.params {
+        let (key, value) = parse_param(raw)?;
+        params.insert(key, value);
+    }
+    if let Some(input) = &args.input {
+        let text = if input == "-" {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| e.to_string())?;
+            text
+        } else {
+            input.clone()
+        };
+        params.insert("user_input".into(), Value::String(text));
+    }
+    Ok(Value::Object(params))
+}
+
+fn compose(args: &ComposeArgs) -> Result<ComposeResult, String> {
+    compose_prompt(&args.recipe, Some(inline_params(args)?)).map_err(|e| e.to_string())
+}
+
+async fn dispatch(command: Command, json: bool) -> Result<i32, String> {
+    match command {
+        Command::Compose(args) => {
+            let composed = compose(&args)?;
+            if json {
+                print_json(&composed)?;
+            } else {
+                println!("{}", composed.final_prompt);
+            }
+            Ok(0)
+        }
+        Command::Run(args) => run_recipe(args, json).await,
+        Command::Batch(args) => run_batch_rows(args, json).await,
+        Command::Sweep(args) => run_sweep(args, json).await,
+        Command::Mcp => {
+            serve_stdio().await.map_err(|e| e.to_string())?;
+            Ok(0)
+        }
+        Command::Serve { addr } => {
+            let server = ProxyServer::bind(&addr, ProxyConfig::default())
+                .await
+                .map_err(|e| format!("{}: {}", addr, e))?;
+            eprintln!("listening on {}/v1", server.url());
+            server.serve_forever().await;
+            Ok(0)
+        }
+        Command::Runs(RunsCommand::List(filter)) => {
+            let page = list_runs(&runs_root(), &filter.query())?;
+            if json {
+                print_json(&page.items)?;
+            } else {
+                for run in &page.items {
+                    print_run_line(run);
+                }
+            }
+            Ok(0)
+        }
+        Command::Runs(RunsCommand::Search { text, filter }) => {
+            let page = search_runs(&runs_root(), &text, &filter.query())?;
+            if json {
+                print_json(&page.items)?;
+            } else {
+                for hit in &page.items {
+                    print_run_line(&hit.run);
+                    println!("\t{}: {}", hit.field, hit.snippet);
+                }
+            }
+            Ok(0)
+        }
+        Command::Runs(RunsCommand::Show { run_id }) => {
+            let record = read_run(&runs_root(), &run_id).map_err(|e| e.to_string())?;
+            if json {
+                print_json(&record)?;
+            } else {
+                let meta = &record.meta;
+                println!(
+                    "{}  {}  {}  {}",
+                    meta.run_id,
+                    label(&meta.status),
+                    meta.model,
+                    meta.started_at
+                );
+                if let Some(error) = &meta.error {
+                    println!("error: {}", error);
+                }
+                println!("\n{}", record.response);
+            }
+            Ok(0)
+        }
+        Command::Runs(RunsCommand::Compare { left, right, words }) => {
+            let granularity = if words {
+                DiffGranularity::Word
+            } else {
+                DiffGranularity::Line
+            };
+            let comparison = compare_runs(&runs_root(), &left, &right, granularity)?;
+            if json {
+                print_json(&comparison)?;
+            } else {
+                print_comparison(&comparison);
+            }
+            Ok(0)
+        }
+        Command::Runs(RunsCommand::Replay {
+            run_id,
+            model,
+            params,
+        }) => {
+            let mut overrides = ReplayOverrides {
+                model,
+                ..ReplayOverrides::default()
+            };
+            for raw in &params {
+                let (key, value) = parse_param(raw)?;
+                overrides.params.insert(key, value);
+            }
+            let prepared = prepare_replay(&data_sandbox(), &runs_root(), &run_id, &overrides)?;
+            for warning in &prepared.warnings {
+                eprintln!("warning: {}", warning);
+            }
+            stream_job(prepared.job, json).await
+        }
+        Command::Runs(RunsCommand::Export {
+            run_ids,
+            filter,
+            format,
+            output,
+        }) => {
+            let request = ExportRequest {
+                run_ids,
+                query: filter.all(),
+                format,
+            };
+            let export = export_runs(&runs_root(), &request)?;
+            if !export.masked_types.is_empty() {
+                eprintln!("masked: {}", export.masked_types.join(", "));
+            }
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, &export.content)
+                        .map_err(|e| format!("{}: {}", path, e))?;
+                    eprintln!("exported {} run(s) to {}", export.runs, path);
+                }
+                None => print!("{}", export.content),
+            }
+            Ok(0)
+        }
+        Command::Runs(RunsCommand::Pin { run_id, unpin }) => {
+            let root = runs_root();
+            let meta = set_run_pinned(&root, &run_id, !unpin).map_err(|e| e.to_string())?;
+            reindex_run(&root, &run_id).map_err(|e| e.to_string())?;
+            if json {
+                print_json(&RunSummary::from(&meta))?;
+            } else {
+                println!("{} {}", if unpin { "unpinned" } else { "pinned" }, run_id);
+            }
+            Ok(0)
+        }
+        Command::Runs(RunsCommand::Gc(args)) => {
+            let root = runs_root();
+            let mut policy = read_retention_policy(&root)?;
+            policy.max_age_days = args.max_age_days.or(policy.max_age_days);
+            policy.max_runs = args.max_runs.or(policy.max_runs);
+            policy.max_total_mb = args.max_total_mb.or(policy.max_total_mb);
+            if args.save {
+                policy.gc_on_startup = args.on_startup;
+                write_retention_policy(&root, &policy)?;
+            }
+            if policy.is_unlimited() {
+                return Err(
+                    "no retention limits set; pass --max-age-days, --max-runs or --max-total-mb"
+                        .into(),
+                );
+            }
+            let report = gc_runs(&root, &policy, args.dry_run)?;
+            if json {
+                print_json(&report)?;
+            } else {
+                print_gc_report(&report);
+            }
+            Ok(if report.errors.is_empty() { 0 } else { 1 })
+        }
+        Command::Runs(RunsCommand::Import { from }) => {
+            let root = runs_root();
+            let moved = import_legacy_runs(&from, &root).map_err(|e| e.to_string())?;
+            migrate_legacy_runs(&root).map_err(|e| e.to_string())?;
+            if json {
+                print_json(&moved)?;
+            } else {
+                for run_id in &moved {
+                    println!("{}", run_id);
+                }
+                println!("moved {} run(s) to {}", moved.len(), root.display());
+            }
+            Ok(0)
+        }
+        Command::Lint { recipes } => {
+            let sandbox = data_sandbox();
+            let mut failed = false;
+            let mut report = Vec::new();
+            for recipe in recipes {
+                let issues = lint_recipe(&sandbox, &recipe);
+                failed |= issues.iter().any(|i| i.level == LintLevel::Error);
+                if !json {
+                    for issue in &issues {
+                        println!("{}: {}: {}", recipe, label(&issue.level), issue.message);
+                    }
+                }
+                report.push(json!({ "recipe": recipe, "issues": issues }));
+            }
+            if json {
+                print_json(&report)?;
+            }
+            Ok(if failed { 1 } else { 0 })
+        }
+        Command::Excerpt { path, max_bytes } => {
+            let excerpt = load_txt_excerpt(&path, max_bytes)?;
+            if json {
+                print_json(&excerpt)?;
+            } else {
+                println!("{}", excerpt.excerpt);
+            }
+            Ok(0)
+        }
+        Command::SetupCheck {
+            base_url,
+            model,
+            profile,
+            probe,
+            locale,
+        } => {
+            let locale = match locale.as_deref() {
+                Some("en") => Locale::En,
+                _ => Locale::Ja,
+            };
+            let outcome =
+                check_ollama_setup(base_url, model, profile, Some(probe), Some(locale)).await?;
+            if json {
+                print_json(&outcome)?;
+            } else {
+                println!("{}", label(&outcome.status));
+                if !outcome.guidance.is_empty() {
+                    println!("{}", outcome.guidance);
+                }
+            }
+            Ok(if outcome.status == SetupStatus::Ready {
+                0
+            } else {
+                1
+            })
+        }
+    }
+}
+
+fn parse_status(raw: &str) -> Result<RunStatus, String> {
+    serde_json::from_value(Value::String(raw.to_string()))
+        .map_err(|_| format!("unknown status `{}`", raw))
+}
+
+fn parse_export_format(raw: &str) -> Result<ExportFormat, String> {
+    serde_json::from_value(Value::String(raw.to_string()))
+        .map_err(|_| format!("unknown format `{}`", raw))
+}
+
+fn print_run_line(run: &RunSummary) {
+    println!(
+        "{}\t{}\t{}\t{}\t{}",
+        run.run_id,
+        run.started_at,
+        label(&run.status),
+        run.model,
+        run.recipe_path.as_deref().unwrap_or_default()
+    );
+}
+
+fn print_gc_report(report: &GcReport) {
+    let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    for run in &report.removed {
+        println!(
+            "{}\t{}\t{:.2} MB\t{}",
+            run.run_id,
+            run.started_at,
+            mb(run.bytes),
+            label(&run.reason)
+        );
+    }
+    for artifact in report.groups.iter().chain(&report.batches) {
+        println!(
+            "{}\t{:.2} MB\t{}",
+            artifact.path,
+            mb(artifact.bytes),
+            label(&artifact.reason)
+        );
+    }
+    for name in &report.stale_partials {
+        println!("{}\tunfinished save", name);
+    }
+    for error in &report.errors {
+        eprintln!("error: {}", error);
+    }
+    println!(
+        "{} {} run(s), {} group(s) and {} batch file(s), {:.2} MB; kept {} run(s), {:.2} MB, and {} pinned",
+        if report.dry_run {
+            "would remove"
+        } else {
+            "removed"
+        },
+        report.removed.len(),
+        report.groups.len(),
+        report.batches.len(),
+        mb(report.freed_bytes),
+        report.kept,
+        mb(report.kept_bytes),
+        report.pinned
+    );
+}
+
+fn print_comparison(cmp: &RunComparison) {
+    let section = |title: &str, empty: bool| {
+        println!("\n== {}{}", title, if empty { " (unchanged)" } else { "" });
+    };
+    print!("left:  ");
+    print_run_line(&cmp.left);
+    print!("right: ");
+    print_run_line(&cmp.right);
+
+    section("prompt", cmp.prompt_diff.is_empty());
+    print!("{}", cmp.prompt_diff);
+    section("fragments", cmp.fragments.is_empty());
+    for fragment in &cmp.fragments {
+        let sha = |f: &Option<FragmentSource>| {
+            f.as_ref()
+                .map(|f| f.sha256.chars().take(12).collect())
+                .unwrap_or_else(|| "-".to_string())
+        };
+        println!(
+            "{}\t{}\t{} -> {}",
+            label(&fragment.change),
+            fragment.id,
+            sha(&fragment.left),
+            sha(&fragment.right)
+        );
+    }
+    for (title, changes) in [("params", &cmp.params), ("options", &cmp.options)] {
+        section(title, changes.is_empty());
+        for change in changes {
+            let show = |v: &Value| {
+                if v.is_null() {
+                    "-".to_string()
+                } else {
+                    v.to_string()
+                }
+            };
+            let mark = match change.change {
+                ChangeKind::Added => "+",
+                ChangeKind::Removed => "-",
+                ChangeKind::Changed => "~",
+            };
+            println!(
+                "{} {}: {} -> {}",
+                mark,
+                change.key,
+                show(&change.left),
+                show(&change.right)
+            );
+        }
+    }
+    let unchanged = cmp.response.is_unchanged();
+    section("response", unchanged);
+    if !unchanged {
+        print!("{}", cmp.response.text);
+        if !cmp.response.text.ends_with('\n') {
+            println!();
+        }
+    }
+
+    fn delta<T: std::fmt::Display + Copy>(name: &str, d: &Delta<T>) {
+        let show = |v: Option<T>| v.map(|v| v.to_string()).unwrap_or_else(|| "-".into());
+        let diff = d.delta.map(|v| format!(" ({:+})", v)).unwrap_or_default();
+        println!("{}: {} -> {}{}", name, show(d.left), show(d.right), diff);
+    }
+    section("deltas", false);
+    let d = &cmp.deltas;
+    delta("duration_ms", &d.duration_ms);
+    delta("time_to_first_token_ms", &d.time_to_first_token_ms);
+    delta("prompt_tokens", &d.prompt_tokens);
+    delta("completion_tokens", &d.completion_tokens);
+    delta("tokens_per_second", &d.tokens_per_second);
+}
+
+/// The serialized (snake_case) name of a unit enum variant.
+fn label<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+async fn run_recipe(args: RunArgs, json: bool) -> Result<i32, String> {
+    let composed = compose(&args.compose)?;
+    let model = args.model.unwrap_or_else(|| composed.model.clone());
+    let profile = resolve_profile(&model).map_err(|e| e.to_string())?;
+    let images = load_images(&args.images)?;
+    let (system_text, user_text) = split_final_prompt(&composed.final_prompt);
+    let job = ChatStreamJob::new(
+        &profile,
+        system_text,
+        user_text,
+        Some(args.compose.recipe.clone()),
+        images,
+        composed.output_schema.clone(),
+    )
+    .with_composition(&composed);
+    stream_job(job, json).await
+}
+
+/// Streams `job` to stdout (or prints its outcome as JSON) and exits non-zero
+/// unless the run completed.
+async fn stream_job(job: ChatStreamJob, json: bool) -> Result<i32, String> {
+    let (tx, mut events) = mpsc::unbounded_channel();
+    let state = StreamState::with_limit(1);
+    start_chat_stream(Arc::new(ChannelSink(tx)), &state, job).await?;
+    let mut outcome = Value::Null;
+    while let Some((event, payload)) = events.recv().await {
+        match event.as_str() {
+            "ollama:chunk" if !json => {
+                print!("{}", payload["text"].as_str().unwrap_or(""));
+                let _ = io::stdout().flush();
+            }
+            "ollama:error" if !json => {
+                eprintln!("\nerror: {}", payload["message"].as_str().unwrap_or(""));
+            }
+            "ollama:saved" => {
+                outcome = payload;
+                break;
+            }
+            _ => {}
+        }
+    }
+    if json {
+        print_json(&outcome)?;
+    } else {
+        println!();
+        eprintln!(
+            "run {} ({})",
+            outcome["run_id"].as_str().unwrap_or("-"),
+            outcome["status"].as_str().unwrap_or("unknown")
+        );
+        if let Some(error) = outcome["save_error"].as_str() {
+            eprintln!("run record not saved: {}", error);
+        }
+    }
+    Ok(if outcome["status"] == "completed" {
+        0
+    } else {
+        1
+    })
+}
+
+fn print_progress(payload: &Value) {
+    eprintln!(
+        "[{}/{}] {} {} {}",
+        payload["done"],
+        payload["total"],
+        payload["row_id"].as_str().unwrap_or(""),
+        payload["status"].as_str().unwrap_or(""),
+        payload["run_id"].as_str().unwrap_or("-")
+    );
+}
+
+async fn run_batch_rows(args: BatchArgs, json: bool) -> Result<i32, String> {
+    let input = match (args.jsonl, args.glob) {
+        (Some(path), _) => BatchInput::Jsonl { path },
+        (None, Some(pattern)) => BatchInput::Glob { pattern },
+        (None, None) => return Err("either --jsonl or --glob is required".into()),
+    };
+    let mut params = Map::new();
+    for raw in &args.params {
+        let (key, value) = parse_param(raw)?;
+        params.insert(key, value);
+    }
+    let request = BatchRequest {
+        recipe: args.recipe,
+        input,
+        params,
+        model: args.model,
+        concurrency: Some(args.concurrency),
+        results: args.results,
+        max_bytes: args.max_bytes,
+    };
+    let batch = prepare_batch(&request)?;
+    eprintln!(
+        "batch {}: {} row(s) to run, {} already done",
+        batch.batch_id,
+        batch.pending(),
+        batch.skipped
+    );
+
+    let (tx, mut events) = mpsc::unbounded_channel();
+    let task = tokio::spawn(run_batch(Arc::new(ChannelSink(tx)), batch));
+    while let Some((event, payload)) = events.recv().await {
+        match event.as_str() {
+            "batch:progress" => print_progress(&payload),
+            "batch:end" => break,
+            _ => {}
+        }
+    }
+    let summary = task.await.map_err(|e| e.to_string())??;
+    if json {
+        print_json(&summary)?;
+    } else {
+        println!(
+            "{}: {} completed, {} failed, {} skipped",
+            summary.results_path, summary.completed, summary.failed, summary.skipped
+        );
+    }
+    Ok(if summary.failed == 0 { 0 } else { 1 })
+}
+
+async fn run_sweep(args: SweepArgs, json: bool) -> Result<i32, String> {
+    let mut request = SweepRequest {
+        recipe: args.recipe,
+        axes: Default::default(),
+        params: Map::new(),
+        sample: args.sample,
+        seed: args.seed,
+        run: args.run,
+        model: args.model,
+        concurrency: Some(args.concurrency),
+    };
+    for raw in &args.axes {
+        let (key, values) = parse_axis(raw)?;
+        request.axes.insert(key, values);
+    }
+    for raw in &args.params {
+        let (key, value) = parse_param(raw)?;
+        request.params.insert(key, value);
+    }
+
+    let (tx, mut events) = mpsc::unbounded_channel();
+    let task = tokio::spawn(compose_sweep(Arc::new(ChannelSink(tx)), request));
+    while let Some((event, payload)) = events.recv().await {
+        if event == "batch:progress" {
+            print_progress(&payload);
+        }
+    }
+    let report = task.await.map_err(|e| e.to_string())??;
+    if json {
+        print_json(&report)?;
+    } else {
+        let table = std::fs::read_to_string(Path::new(&report.group_dir).join("table.md"))
+            .map_err(|e| e.to_string())?;
+        println!("{}", table);
+        eprintln!(
+            "{}: {} variant(s) from {} of {} combination(s){}",
+            report.group_dir,
+            report.variants.len(),
+            report.composed,
+            report.combinations,
+            report
+                .seed
+                .map(|seed| format!(", seed {}", seed))
+                .unwrap_or_default()
+        );
+    }
+    let failed = report
+        .variants
+        .iter()
+        .any(|v| v.status.is_some_and(|s| s != RunStatus::Completed));
+    Ok(if failed { 1 } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<OsString> {
+        list.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn recognises_subcommands_only() {
+        assert!(is_cli_invocation(&args(&[
+            "promptforge",
+            "compose",
+            "r.yaml"
+        ])));
+        assert!(is_cli_invocation(&args(&["promptforge", "setup-check"])));
+        assert!(is_cli_invocation(&args(&[
+            "promptforge",
+            "--json",
+            "runs",
+            "list"
+        ])));
+        assert!(!is_cli_invocation(&args(&["promptforge"])));
+        assert!(!is_cli_invocation(&args(&["promptforge", "-psn_0_12345"])));
+    }
+
+    #[test]
+    fn parses_params_as_json_when_possible() {
+        assert_eq!(parse_param("steps=6").unwrap(), ("steps".into(), json!(6)));
+        assert_eq!(
+            parse_param("tone=calm, precise").unwrap(),
+            ("tone".into(), json!("calm, precise"))
+        );
+        assert!(parse_param("no-equals").is_err());
+        assert_eq!(
+            parse_axis("tone=calm, dry").unwrap(),
+            ("tone".into(), vec![json!("calm"), json!("dry")])
+        );
+        assert_eq!(
+            parse_axis("steps=[3, \"a,b\"]").unwrap(),
+            ("steps".into(), vec![json!(3), json!("a,b")])
+        );
+
+        let cli = Cli::try_parse_from(args(&[
+            "promptforge",
+            "run",
+            "recipes/demo.sora2.yaml",
+            "-p",
+            "steps=4",
+            "--input",
+            "duel",
+            "--model",
+            "phi3",
+            "--json",
+        ]))
+        .unwrap();
+        assert!(cli.json);
+        let Command::Run(run) = cli.command else {
+            panic!("expected run");
+        };
+        assert_eq!(
+            inline_params(&run.compose).unwrap(),
+            json!({"steps": 4, "user_input": "duel"})
+        );
+        assert_eq!(run.model.as_deref(), Some("phi3"));
+    }
+}
@@ -0,0 +1,244 @@
+//! Recipe composition: recipes and fragments under the data sandbox rendered
+//! into one prompt with a USER_INPUT delimiter.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::sandbox::ensure_under;
+
+pub const USER_INPUT_DELIMITER: &str = "\n---\nUSER_INPUT";
+
+#[derive(Debug, Deserialize)]
+pub struct Recipe {
+    pub profile: String,
+    pub fragments: Vec<String>,
+    #[serde(default)]
+    pub params: serde_json::Value,
+    /// JSON schema the reply must follow; overrides the profile's.
+    #[serde(default)]
+    pub output_schema: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Fragment {
+    pub id: String,
+    pub kind: String,
+    #[serde(default)]
+    pub trust: Option<String>,
+    #[serde(default)]
+    pub merge_strategy: Option<String>,
+    pub content: String,
+}
+
+/// Which fragment file went into a prompt, and its content hash at the time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FragmentSource {
+    pub id: String,
+    /// Relative to the data directory.
+    pub path: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComposeResult {
+    pub final_prompt: String,
+    pub sha256: String,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<serde_json::Value>,
+    /// Recipe params after inline overrides.
+    pub params: serde_json::Value,
+    pub fragments: Vec<FragmentSource>,
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    hex::encode(hasher.finalize())
+}
+
+pub fn read_yaml<T: for<'de> Deserialize<'de>>(p: &Path) -> Result<T> {
+    let s = fs::read_to_string(p)?;
+    let v = serde_yaml::from_str::<T>(&s)?;
+    Ok(v)
+}
+
+pub fn render_placeholders(s: &str, params: &serde_json::Value) -> String {
+    let mut out = s.to_string();
+    if let Some(obj) = params.as_object() {
+        for (k, v) in obj.iter() {
+            let key = format!("{{{{{}}}}}", k);
+            let val = if v.is_string() {
+                v.as_str().unwrap().to_string()
+            } else {
+                v.to_string()
+            };
+            out = out.replace(&key, &val);
+        }
+    }
+    out
+}
+
+/// Data directory holding recipes, fragments and profiles (`PROMPTFORGE_DATA_DIR`).
+pub fn data_sandbox() -> PathBuf {
+    env::var_os("PROMPTFORGE_DATA_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("data"))
+}
+
+/// Resolves a recipe path relative to the data sandbox and checks it stays inside.
+pub fn recipe_file(sandbox: &Path, recipe_path: &str) -> Result<PathBuf> {
+    let raw = PathBuf::from(recipe_path);
+    let path = if raw.is_absolute() || raw.starts_with(sandbox) {
+        raw
+    } else {
+        sandbox.join(raw)
+    };
+    ensure_under(sandbox, &path)?;
+    Ok(path)
+}
+
+/// Recipes under `sandbox/recipes` by name (`demo.sora2` for
+/// `recipes/demo.sora2.yaml`), sorted.
+pub fn recipe_names(sandbox: &Path) -> Vec<String> {
+    let root = sandbox.join("recipes");
+    let mut names: Vec<String> = walkdir::WalkDir::new(&root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let rel = e.path().strip_prefix(&root).ok()?;
+            let rel = rel.to_string_lossy().replace('\\', "/");
+            Some(rel.strip_suffix(".yaml")?.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// The recipe path [`recipe_names`] lists as `name`.
+pub fn named_recipe_path(name: &str) -> String {
+    format!("recipes/{}.yaml", name)
+}
+
+/// `style.concise` lives at `fragments/style/concise.yaml`. Ids are dotted, so
+/// path separators in one are treated as an escape attempt.
+pub fn fragment_file(sandbox: &Path, fragment_id: &str) -> Result<PathBuf> {
+    if fragment_id.contains(['/', '\\']) {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "path out of sandbox").into());
+    }
+    let path = sandbox
+        .join("fragments")
+        .join(format!("{}.yaml", fragment_id.replace('.', "/")));
+    ensure_under(sandbox, &path)?;
+    Ok(path)
+}
+
+/// Splits a composed prompt at its USER_INPUT delimiter into the system and
+/// user messages, the same way the UI does before running it.
+pub fn split_final_prompt(final_prompt: &str) -> (String, String) {
+    match final_prompt.find(USER_INPUT_DELIMITER) {
+        Some(at) => (
+            final_prompt[..at].to_string(),
+            final_prompt[at..].to_string(),
+        ),
+        None => (final_prompt.to_string(), String::new()),
+    }
+}
+
+/// Composes `recipe_path` from the data sandbox; `inline_params` override the
+/// recipe's own.
+pub fn compose_prompt(
+    recipe_path: &str,
+    inline_params: Option<serde_json::Value>,
+) -> Result<ComposeResult> {
+    compose_prompt_in(&data_sandbox(), recipe_path, inline_params)
+}
+
+/// Re-composes `recipe_path` with `params` and returns the result only if it
+/// reproduces `final_prompt`, so provenance is never attached to a prompt the
+/// user edited after composing.
+pub fn matching_composition(
+    sandbox: &Path,
+    recipe_path: &str,
+    params: serde_json::Value,
+    final_prompt: &str,
+) -> Option<ComposeResult> {
+    compose_prompt_in(sandbox, recipe_path, Some(params))
+        .ok()
+        .filter(|composed| composed.final_prompt == final_prompt)
+}
+
+/// [`compose_prompt`] against an explicit data directory.
+pub fn compose_prompt_in(
+    sandbox: &Path,
+    recipe_path: &str,
+    inline_params: Option<serde_json::Value>,
+) -> Result<ComposeResult> {
+    let rp = recipe_file(sandbox, recipe_path)?;
+    let recipe: Recipe = read_yaml(&rp)?;
+
+    // merge params (inline override recipe.params)
+    let mut params = recipe.params.clone();
+    if let (Some(mut obj), Some(inline)) = (params.as_object().cloned(), inline_params) {
+        if let Some(inline_obj) = inline.as_object() {
+            for (k, v) in inline_obj.iter() {
+                obj.insert(k.clone(), v.clone());
+            }
+        }
+        params = serde_json::Value::Object(obj);
+    }
+
+    // load fragments
+    let mut blocks: Vec<String> = vec![];
+    let mut fragments = vec![];
+    for frag_id in recipe.fragments.iter() {
+        let frag_path = fragment_file(sandbox, frag_id)?;
+        let raw = fs::read(&frag_path)
+            .with_context(|| format!("Failed to read fragment: {}", frag_path.display()))?;
+        let frag: Fragment = serde_yaml::from_slice(&raw)
+            .with_context(|| format!("Failed to read fragment: {}", frag_path.display()))?;
+        let rendered = render_placeholders(&frag.content, &params);
+        blocks.push(rendered);
+        fragments.push(FragmentSource {
+            id: frag_id.clone(),
+            path: frag_path
+                .strip_prefix(sandbox)
+                .unwrap_or(&frag_path)
+                .to_string_lossy()
+                .replace('\\', "/"),
+            sha256: sha256_hex(&raw),
+        });
+    }
+
+    let user_input = params
+        .get("user_input")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string();
+
+    // final prompt with delimiter for user input
+    let final_prompt = format!(
+        "{}{} (verbatim):\n```text\n{}\n```",
+        blocks.join("\n\n"),
+        USER_INPUT_DELIMITER,
+        user_input
+    );
+
+    let sha256 = sha256_hex(final_prompt.as_bytes());
+
+    Ok(ComposeResult {
+        final_prompt,
+        sha256,
+        model: recipe.profile,
+        output_schema: recipe.output_schema,
+        params,
+        fragments,
+    })
+}
@@ -0,0 +1,439 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::ollama_client::{request_text, OllamaClientConfig, OllamaError, OllamaErrorKind};
+
+pub const INDEX_PATH: &str = "index/embeddings.json";
+pub const DEFAULT_EMBED_MODEL: &str = "nomic-embed-text";
+const EMBED_MODEL_ENV: &str = "PROMPTFORGE_EMBED_MODEL";
+const INDEX_VERSION: u32 = 1;
+
+/// Target chunk size; paragraphs are packed up to this many characters.
+const CHUNK_CHARS: usize = 1200;
+const DEFAULT_TOP_K: usize = 5;
+
+/// Directories that are indexed and the extensions read from each.
+pub const SOURCE_ROOTS: &[(&str, &[&str])] = &[
+    ("corpus", &["txt", "md"]),
+    ("project", crate::files::PROJECT_ALLOWED_EXTS),
+];
+
+/// Turns text into a vector. Ollama in the app, a deterministic stand-in in tests.
+pub trait Embedder: Send + Sync {
+    fn model(&self) -> &str;
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, OllamaError>>;
+}
+
+pub struct OllamaEmbedder {
+    config: OllamaClientConfig,
+    model: String,
+}
+
+impl OllamaEmbedder {
+    pub fn new(config: OllamaClientConfig, model: impl Into<String>) -> Self {
+        Self {
+            config,
+            model: model.into(),
+        }
+    }
+
+    /// `model` or `PROMPTFORGE_EMBED_MODEL`, falling back to nomic-embed-text.
+    pub fn from_env(model: Option<String>) -> Self {
+        let model = model
+            .or_else(|| env::var(EMBED_MODEL_ENV).ok())
+            .filter(|m| !m.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_EMBED_MODEL.to_string());
+        Self::new(OllamaClientConfig::from_env(), model)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingBody {
+    embedding: Vec<f32>,
+}
+
+impl Embedder for OllamaEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, OllamaError>> {
+        Box::pin(async move {
+            let client = self.config.build_client()?;
+            let url = self.config.endpoint("/api/embeddings");
+            let payload = json!({ "model": self.model, "prompt": text });
+            let body = request_text(&client, &self.config, |c| c.post(&url).json(&payload)).await?;
+            let parsed: EmbeddingBody = serde_json::from_str(&body)
+                .map_err(|err| OllamaError::new(OllamaErrorKind::Protocol, err.to_string()))?;
+            Ok(parsed.embedding)
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedChunk {
+    /// 1-based line the chunk starts on.
+    pub line: usize,
+    pub text: String,
+    pub vector: Vec<f32>,
+}
+
+/// On-disk index. Chunks are stored per content sha256 so renamed or copied
+/// files are not embedded twice and unchanged files are skipped on refresh.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CorpusIndex {
+    pub version: u32,
+    pub model: String,
+    /// Relative path (`corpus/notes.txt`) to content sha256.
+    pub paths: BTreeMap<String, String>,
+    pub contents: BTreeMap<String, Vec<IndexedChunk>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IndexReport {
+    pub files: usize,
+    pub chunks: usize,
+    /// Files whose content had to be embedded in this refresh.
+    pub embedded_files: usize,
+    pub reused_files: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    pub path: String,
+    pub line: usize,
+    pub sha256: String,
+    pub score: f32,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CorpusSearch {
+    pub query: String,
+    pub hits: Vec<SearchHit>,
+    /// Hits joined with source markers, ready to pass as a recipe param.
+    pub context: String,
+}
+
+/// Splits `text` into paragraph-aligned chunks of roughly [`CHUNK_CHARS`].
+/// Oversized paragraphs are cut on character boundaries.
+pub fn chunk_text(text: &str, max_chars: usize) -> Vec<(usize, String)> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_line = 1;
+    let mut line = 1;
+    for paragraph in text.split("\n\n") {
+        let trimmed = paragraph.trim();
+        let leading = &paragraph[..paragraph.len() - paragraph.trim_start().len()];
+        let para_line = line + leading.matches('\n').count();
+        line += paragraph.matches('\n').count() + 2;
+        if trimmed.is_empty() {
+            continue;
+        }
+        if !current.is_empty() && current.chars().count() + trimmed.chars().count() + 2 > max_chars
+        {
+            chunks.push((current_line, std::mem::take(&mut current)));
+        }
+        if current.is_empty() {
+            current_line = para_line;
+        } else {
+            current.push_str("\n\n");
+        }
+        current.push_str(trimmed);
+        while current.chars().count() > max_chars {
+            let split = current
+                .char_indices()
+                .nth(max_chars)
+                .map(|(i, _)| i)
+                .unwrap_or(current.len());
+            let rest = current.split_off(split);
+            let lines_in_head = current.matches('\n').count();
+            chunks.push((current_line, std::mem::replace(&mut current, rest)));
+            current_line += lines_in_head;
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push((current_line, current));
+    }
+    chunks
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let (mut dot, mut na, mut nb) = (0.0_f32, 0.0_f32, 0.0_f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    if na == 0.0 || nb == 0.0 {
+        0.0
+    } else {
+        dot / (na.sqrt() * nb.sqrt())
+    }
+}
+
+/// Index contents for `model`; a missing, unreadable or other-model index starts empty.
+pub fn load_index(path: &Path, model: &str) -> CorpusIndex {
+    let loaded = fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str::<CorpusIndex>(&s).ok())
+        .filter(|index| index.version == INDEX_VERSION && index.model == model);
+    loaded.unwrap_or_else(|| CorpusIndex {
+        version: INDEX_VERSION,
+        model: model.to_string(),
+        ..CorpusIndex::default()
+    })
+}
+
+/// Writes through a temporary file so an interrupted save never leaves a torn index.
+pub fn save_index(path: &Path, index: &CorpusIndex) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec(index).map_err(io::Error::other)?)?;
+    fs::rename(&tmp, path)
+}
+
+/// `(relative path, content)` for every indexable file under `base/<root>`.
+fn collect_sources(base: &Path) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    for (root, exts) in SOURCE_ROOTS {
+        let dir = base.join(root);
+        for entry in walkdir::WalkDir::new(&dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = entry.path();
+            let ext = path
+                .extension()
+                .and_then(|s| s.to_str())
+                .unwrap_or("")
+                .to_lowercase();
+            if !exts.contains(&ext.as_str()) {
+                continue;
+            }
+            // Binary or non-UTF-8 files are not searchable text.
+            let Ok(content) = fs::read_to_string(path) else {
+                continue;
+            };
+            let rel = path.strip_prefix(base).unwrap_or(path);
+            let rel = rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            out.push((rel, content));
+        }
+    }
+    out.sort();
+    out
+}
+
+/// Brings `index` in line with the files under `base`, embedding only content
+/// whose sha256 is not indexed yet and dropping content no file refers to.
+pub async fn refresh_index(
+    embedder: &dyn Embedder,
+    base: &Path,
+    index: &mut CorpusIndex,
+) -> Result<IndexReport, OllamaError> {
+    let mut report = IndexReport::default();
+    let mut paths = BTreeMap::new();
+    for (rel, content) in collect_sources(base) {
+        let sha256 = hex::encode(Sha256::digest(content.as_bytes()));
+        report.files += 1;
+        if index.contents.contains_key(&sha256) {
+            report.reused_files += 1;
+        } else {
+            let mut chunks = Vec::new();
+            for (line, text) in chunk_text(&content, CHUNK_CHARS) {
+                let vector = embedder.embed(&text).await?;
+                chunks.push(IndexedChunk { line, text, vector });
+            }
+            index.contents.insert(sha256.clone(), chunks);
+            report.embedded_files += 1;
+        }
+        paths.insert(rel, sha256);
+    }
+    let live: HashSet<&String> = paths.values().collect();
+    index.contents.retain(|sha, _| live.contains(sha));
+    report.chunks = index.contents.values().map(Vec::len).sum();
+    index.paths = paths;
+    Ok(report)
+}
+
+/// The `top_k` chunks most similar to `query`.
+pub async fn search(
+    embedder: &dyn Embedder,
+    index: &CorpusIndex,
+    query: &str,
+    top_k: usize,
+) -> Result<Vec<SearchHit>, OllamaError> {
+    let query_vector = embedder.embed(query).await?;
+    // The same content can live at several paths; report it under the first.
+    let mut path_for: HashMap<&String, &String> = HashMap::new();
+    for (path, sha) in &index.paths {
+        path_for.entry(sha).or_insert(path);
+    }
+    let mut hits: Vec<SearchHit> = index
+        .contents
+        .iter()
+        .filter_map(|(sha, chunks)| path_for.get(sha).map(|path| (sha, *path, chunks)))
+        .flat_map(|(sha, path, chunks)| {
+            let query_vector = &query_vector;
+            chunks.iter().map(move |chunk| SearchHit {
+                path: path.clone(),
+                line: chunk.line,
+                sha256: sha.clone(),
+                score: cosine(query_vector, &chunk.vector),
+                text: chunk.text.clone(),
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(top_k);
+    Ok(hits)
+}
+
+pub fn format_context(hits: &[SearchHit]) -> String {
+    hits.iter()
+        .map(|hit| format!("[{}:{}]\n{}", hit.path, hit.line, hit.text))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+pub async fn build_corpus_index(model: Option<String>) -> Result<IndexReport, String> {
+    let embedder = OllamaEmbedder::from_env(model);
+    let path = PathBuf::from(INDEX_PATH);
+    let mut index = load_index(&path, embedder.model());
+    let report = refresh_index(&embedder, Path::new("."), &mut index)
+        .await
+        .map_err(|e| e.to_string())?;
+    save_index(&path, &index).map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+/// Refreshes the index (cheap when nothing changed) and returns the best passages.
+pub async fn search_corpus(
+    query: String,
+    top_k: Option<usize>,
+    model: Option<String>,
+) -> Result<CorpusSearch, String> {
+    let embedder = OllamaEmbedder::from_env(model);
+    let path = PathBuf::from(INDEX_PATH);
+    let mut index = load_index(&path, embedder.model());
+    refresh_index(&embedder, Path::new("."), &mut index)
+        .await
+        .map_err(|e| e.to_string())?;
+    save_index(&path, &index).map_err(|e| e.to_string())?;
+    let hits = search(&embedder, &index, &query, top_k.unwrap_or(DEFAULT_TOP_K))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(CorpusSearch {
+        context: format_context(&hits),
+        query,
+        hits,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Bag-of-words vectors over a fixed vocabulary; counts calls.
+    struct MockEmbedder {
+        calls: AtomicUsize,
+    }
+
+    const VOCAB: &[&str] = &["sword", "duel", "rain", "city", "neon", "forest"];
+
+    impl Embedder for MockEmbedder {
+        fn model(&self) -> &str {
+            "mock"
+        }
+
+        fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, OllamaError>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let lower = text.to_lowercase();
+            let vector = VOCAB
+                .iter()
+                .map(|word| lower.matches(word).count() as f32)
+                .collect();
+            Box::pin(async move { Ok(vector) })
+        }
+    }
+
+    #[test]
+    fn chunks_follow_paragraphs() {
+        let text = "first para\nline two\n\nsecond para\n\n\nthird";
+        let chunks = chunk_text(text, 25);
+        assert_eq!(
+            chunks,
+            vec![
+                (1, "first para\nline two".to_string()),
+                (4, "second para\n\nthird".to_string()),
+            ]
+        );
+        let long = "x".repeat(30);
+        let chunks = chunk_text(&long, 12);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|(_, c)| c.chars().count() <= 12));
+    }
+
+    #[tokio::test]
+    async fn indexes_by_content_hash_and_searches() {
+        let temp = tempfile::tempdir().unwrap();
+        let base = temp.path();
+        fs::create_dir_all(base.join("corpus/scenes")).unwrap();
+        fs::create_dir_all(base.join("project")).unwrap();
+        fs::write(
+            base.join("corpus/scenes/duel.txt"),
+            "A sword duel in the rain.",
+        )
+        .unwrap();
+        fs::write(base.join("corpus/city.md"), "Neon city at night.").unwrap();
+        fs::write(base.join("project/copy.txt"), "Neon city at night.").unwrap();
+        fs::write(base.join("project/image.png"), "sword sword").unwrap();
+
+        let embedder = MockEmbedder {
+            calls: AtomicUsize::new(0),
+        };
+        let mut index = load_index(&base.join(INDEX_PATH), "mock");
+        let report = refresh_index(&embedder, base, &mut index).await.unwrap();
+        assert_eq!(report.files, 3);
+        assert_eq!(report.embedded_files, 2);
+        assert_eq!(report.reused_files, 1);
+        assert_eq!(embedder.calls.load(Ordering::SeqCst), 2);
+
+        save_index(&base.join(INDEX_PATH), &index).unwrap();
+        let mut reloaded = load_index(&base.join(INDEX_PATH), "mock");
+        assert_eq!(reloaded, index);
+        assert!(load_index(&base.join(INDEX_PATH), "other").paths.is_empty());
+
+        let report = refresh_index(&embedder, base, &mut reloaded).await.unwrap();
+        assert_eq!(report.embedded_files, 0);
+        assert_eq!(embedder.calls.load(Ordering::SeqCst), 2);
+
+        let hits = search(&embedder, &reloaded, "rainy duel", 1).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].path, "corpus/scenes/duel.txt");
+        assert_eq!(hits[0].line, 1);
+        assert!(format_context(&hits).starts_with("[corpus/scenes/duel.txt:1]\nA sword duel"));
+
+        fs::remove_file(base.join("corpus/scenes/duel.txt")).unwrap();
+        refresh_index(&embedder, base, &mut reloaded).await.unwrap();
+        assert_eq!(reloaded.contents.len(), 1);
+    }
+}
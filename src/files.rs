@@ -0,0 +1,172 @@
+//! Prompt templates under `prompts/` and user files under `project/`, both
+//! confined to their folder.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::sandbox::ensure_under;
+
+// ---------- Prompt files ----------
+const PROMPT_KINDS: &[&str] = &["system", "task", "style", "constraints"];
+
+#[derive(Debug, Serialize)]
+pub struct PromptFileEntry {
+    pub path: String,
+    pub name: String,
+}
+
+fn normalize_prompt_kind(kind: String) -> Result<String, String> {
+    let kind = kind.to_lowercase();
+    if PROMPT_KINDS.contains(&kind.as_str()) {
+        Ok(kind)
+    } else {
+        Err("invalid prompt kind".into())
+    }
+}
+
+pub fn list_prompt_files(kind: String) -> Result<Vec<PromptFileEntry>, String> {
+    use walkdir::WalkDir;
+
+    let kind = normalize_prompt_kind(kind)?;
+    let root = PathBuf::from("prompts");
+    let base = root.join(&kind);
+    if !base.exists() {
+        return Ok(vec![]);
+    }
+    let mut out = vec![];
+    for entry in WalkDir::new(&base).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.path();
+        ensure_under(&root, path).map_err(|e| e.to_string())?;
+        let rel = path
+            .strip_prefix(&root)
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        out.push(PromptFileEntry { path: rel, name });
+    }
+    out.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(out)
+}
+
+pub fn read_prompt_file(rel_path: String) -> Result<FileContent, String> {
+    let base = PathBuf::from("prompts");
+    let path = base.join(&rel_path);
+    ensure_under(&base, &path).map_err(|e| e.to_string())?;
+    if !path.exists() {
+        return Err("file not found".into());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    Ok(FileContent {
+        path: path.display().to_string(),
+        content,
+    })
+}
+
+// ---------- Project file I/O ----------
+pub const PROJECT_ALLOWED_EXTS: &[&str] = &["py", "txt", "md", "json"];
+
+fn assert_allowed_project_ext(path: &Path) -> Result<(), String> {
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let is_allowed = PROJECT_ALLOWED_EXTS.iter().any(|allowed| ext == *allowed);
+    if is_allowed {
+        Ok(())
+    } else {
+        Err("unsupported extension".into())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectEntry {
+    pub path: String,
+    pub name: String,
+    pub size: u64,
+}
+
+pub fn list_project_files(exts: Option<Vec<String>>) -> Result<Vec<ProjectEntry>, String> {
+    list_project_files_in(Path::new("project"), exts)
+}
+
+/// [`list_project_files`] for the project folder `base`.
+pub fn list_project_files_in(
+    base: &Path,
+    exts: Option<Vec<String>>,
+) -> Result<Vec<ProjectEntry>, String> {
+    use walkdir::WalkDir;
+    if !base.exists() {
+        fs::create_dir_all(base).map_err(|e| e.to_string())?;
+    }
+    let mut out = vec![];
+    let allow_exts: Vec<String> = exts
+        .map(|v| v.into_iter().map(|s| s.to_lowercase()).collect())
+        .unwrap_or_else(|| PROJECT_ALLOWED_EXTS.iter().map(|s| s.to_string()).collect());
+    for e in WalkDir::new(base).into_iter().filter_map(|e| e.ok()) {
+        if e.file_type().is_file() {
+            let p = e.path();
+            let ext = p
+                .extension()
+                .and_then(|s| s.to_str())
+                .unwrap_or("")
+                .to_lowercase();
+            if !allow_exts.contains(&ext) {
+                continue;
+            }
+            let meta = fs::metadata(p).map_err(|e| e.to_string())?;
+            let rel = p.strip_prefix(base).unwrap().to_string_lossy().to_string();
+            let name = p.file_name().unwrap().to_string_lossy().to_string();
+            out.push(ProjectEntry {
+                path: rel,
+                name,
+                size: meta.len(),
+            });
+        }
+    }
+    out.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(out)
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileContent {
+    pub path: String,
+    pub content: String,
+}
+
+pub fn read_project_file(rel_path: String) -> Result<FileContent, String> {
+    read_project_file_in(Path::new("project"), &rel_path)
+}
+
+/// [`read_project_file`] for the project folder `base`.
+pub fn read_project_file_in(base: &Path, rel_path: &str) -> Result<FileContent, String> {
+    let p = base.join(rel_path);
+    assert_allowed_project_ext(&p)?;
+    if !p.exists() {
+        return Err("file not found".into());
+    }
+    ensure_under(base, &p).map_err(|e| e.to_string())?;
+    let txt = fs::read_to_string(&p).map_err(|e| e.to_string())?;
+    Ok(FileContent {
+        path: p.display().to_string(),
+        content: txt,
+    })
+}
+
+pub fn write_project_file(rel_path: String, content: String) -> Result<String, String> {
+    let base = PathBuf::from("project");
+    let p = base.join(&rel_path);
+    assert_allowed_project_ext(&p)?;
+    if let Some(dir) = p.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    ensure_under(&base, &p).map_err(|e| e.to_string())?;
+    fs::write(&p, content).map_err(|e| e.to_string())?;
+    Ok(p.display().to_string())
+}
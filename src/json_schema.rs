@@ -0,0 +1,288 @@
+//! Validator for the JSON Schema subset that local models can be constrained
+//! to: `type`, `enum`, `const`, object `properties` / `required` /
+//! `additionalProperties`, array `items` / `minItems` / `maxItems`, string
+//! length and numeric bounds. Unknown keywords are ignored.
+
+use serde_json::{Map, Value};
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|f| f.fract() == 0.0)
+        }
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn path_or_root(path: &str) -> &str {
+    if path.is_empty() {
+        "/"
+    } else {
+        path
+    }
+}
+
+fn check_type(
+    schema: &Map<String, Value>,
+    value: &Value,
+    path: &str,
+    errors: &mut Vec<String>,
+) -> bool {
+    let expected: Vec<&str> = match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => return true,
+    };
+    if expected.iter().any(|t| type_matches(t, value)) {
+        return true;
+    }
+    errors.push(format!(
+        "{}: expected {}, got {}",
+        path_or_root(path),
+        expected.join(" or "),
+        type_name(value)
+    ));
+    false
+}
+
+fn validate_object(
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                errors.push(format!(
+                    "{}: missing required property `{}`",
+                    path_or_root(path),
+                    key
+                ));
+            }
+        }
+    }
+    for (key, child) in object {
+        let child_path = format!("{}/{}", path, key);
+        match properties.and_then(|p| p.get(key)) {
+            Some(child_schema) => validate_at(child_schema, child, &child_path, errors),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    errors.push(format!("{}: unexpected property", child_path))
+                }
+                Some(extra @ Value::Object(_)) => validate_at(extra, child, &child_path, errors),
+                _ => {}
+            },
+        }
+    }
+}
+
+fn validate_array(
+    schema: &Map<String, Value>,
+    items: &[Value],
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    let len = items.len() as u64;
+    if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+        if len < min {
+            errors.push(format!(
+                "{}: expected at least {} items, got {}",
+                path_or_root(path),
+                min,
+                len
+            ));
+        }
+    }
+    if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+        if len > max {
+            errors.push(format!(
+                "{}: expected at most {} items, got {}",
+                path_or_root(path),
+                max,
+                len
+            ));
+        }
+    }
+    if let Some(item_schema) = schema.get("items") {
+        for (index, item) in items.iter().enumerate() {
+            validate_at(item_schema, item, &format!("{}/{}", path, index), errors);
+        }
+    }
+}
+
+fn validate_scalar(
+    schema: &Map<String, Value>,
+    value: &Value,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    if let Some(text) = value.as_str() {
+        let len = text.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+            if len < min {
+                errors.push(format!(
+                    "{}: shorter than {} characters",
+                    path_or_root(path),
+                    min
+                ));
+            }
+        }
+        if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+            if len > max {
+                errors.push(format!(
+                    "{}: longer than {} characters",
+                    path_or_root(path),
+                    max
+                ));
+            }
+        }
+    }
+    if let Some(number) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            if number < min {
+                errors.push(format!(
+                    "{}: {} is below the minimum {}",
+                    path_or_root(path),
+                    number,
+                    min
+                ));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            if number > max {
+                errors.push(format!(
+                    "{}: {} is above the maximum {}",
+                    path_or_root(path),
+                    number,
+                    max
+                ));
+            }
+        }
+    }
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        // `true` / `{}` accept anything; `false` accepts nothing.
+        if schema == &Value::Bool(false) {
+            errors.push(format!("{}: no value is allowed here", path_or_root(path)));
+        }
+        return;
+    };
+    if !check_type(schema, value, path, errors) {
+        return;
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            errors.push(format!(
+                "{}: {} is not one of the allowed values",
+                path_or_root(path),
+                value
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            errors.push(format!("{}: expected {}", path_or_root(path), expected));
+        }
+    }
+    match value {
+        Value::Object(object) => validate_object(schema, object, path, errors),
+        Value::Array(items) => validate_array(schema, items, path, errors),
+        _ => validate_scalar(schema, value, path, errors),
+    }
+}
+
+/// Returns one message per violation, each prefixed with a JSON pointer.
+pub fn validate(schema: &Value, value: &Value) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    validate_at(schema, value, "", &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn storyboard_schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["title", "steps"],
+            "additionalProperties": false,
+            "properties": {
+                "title": { "type": "string", "minLength": 1 },
+                "steps": {
+                    "type": "array",
+                    "minItems": 2,
+                    "items": {
+                        "type": "object",
+                        "required": ["shot", "seconds"],
+                        "properties": {
+                            "shot": { "type": "string", "enum": ["wide", "close", "medium"] },
+                            "seconds": { "type": "integer", "minimum": 1, "maximum": 30 }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn accepts_matching_documents() {
+        let value = json!({
+            "title": "Duel",
+            "steps": [{ "shot": "wide", "seconds": 4 }, { "shot": "close", "seconds": 2.0 }]
+        });
+        assert_eq!(validate(&storyboard_schema(), &value), Ok(()));
+        assert_eq!(validate(&json!(true), &value), Ok(()));
+    }
+
+    #[test]
+    fn reports_every_violation_with_its_path() {
+        let value = json!({
+            "steps": [{ "shot": "pan", "seconds": 45 }],
+            "notes": "extra"
+        });
+        let mut errors = validate(&storyboard_schema(), &value).unwrap_err();
+        // Property order depends on serde_json's `preserve_order` feature.
+        errors.sort();
+        assert_eq!(
+            errors,
+            vec![
+                "/: missing required property `title`",
+                "/notes: unexpected property",
+                "/steps/0/seconds: 45 is above the maximum 30",
+                "/steps/0/shot: \"pan\" is not one of the allowed values",
+                "/steps: expected at least 2 items, got 1",
+            ]
+        );
+        assert_eq!(
+            validate(&json!({"type": ["string", "null"]}), &json!(3)).unwrap_err(),
+            vec!["/: expected string or null, got number"]
+        );
+    }
+}
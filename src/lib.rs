@@ -0,0 +1,55 @@
+//! PromptForge engine: recipe composition, LLM streaming, run records and the
+//! sandboxed project/corpus access behind both the desktop app and the
+//! `promptforge` CLI.
+//!
+//! Everything outside [`app`] is plain Rust; long-running work reports through
+//! [`ollama_stream::EventSink`]. The Tauri command layer is behind the default
+//! `app` feature, so `default-features = false` builds without WebKit or GTK.
+
+#[cfg(feature = "app")]
+pub mod app;
+pub mod attachments;
+pub mod batch;
+pub mod chat;
+pub mod cli;
+pub mod compose;
+pub mod corpus_index;
+pub mod files;
+pub mod json_schema;
+pub mod llm_backend;
+pub mod llm_replay;
+pub mod mcp_server;
+pub mod mock_ollama;
+pub mod ollama_client;
+pub mod ollama_models;
+pub mod ollama_stream;
+pub mod openai_compat;
+pub mod openai_proxy;
+pub mod profiles;
+pub mod recipe_lint;
+pub mod redact;
+pub mod run_compare;
+pub mod run_export;
+pub mod run_gc;
+pub mod run_index;
+pub mod run_replay;
+pub mod runs;
+pub mod sandbox;
+pub mod setup_check;
+pub mod structured_output;
+pub mod sweep;
+#[doc(hidden)]
+pub mod test_support;
+pub mod txt_excerpt;
+pub mod workspace;
+
+#[cfg(test)]
+mod tests;
+
+#[cfg(feature = "app")]
+pub use app::configure_builder;
+#[cfg(feature = "app")]
+#[doc(hidden)]
+pub use app::workspace_test_support;
+#[doc(hidden)]
+pub use test_support::{project_io_test_support, project_test_support, prompt_test_support};
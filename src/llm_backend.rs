@@ -0,0 +1,343 @@
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::attachments::ImageAttachment;
+use crate::ollama_client::{
+    request_text, stream_ndjson, OllamaClientConfig, OllamaError, OllamaErrorKind,
+};
+use crate::ollama_models::list_models;
+use crate::ollama_stream::{parse_ollama_jsonl_chunk, OllamaEvent};
+use crate::openai_compat::OpenAiBackend;
+use crate::profiles::{resolve_profile, Profile};
+
+/// Which server protocol a profile talks to.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    #[default]
+    Ollama,
+    /// Any server exposing `/v1/chat/completions` (llama.cpp `server`, vLLM, LM Studio, ...).
+    Openai,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+    /// Base64-encoded images, as Ollama expects them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.into(),
+            images: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    /// Ollama-style options (`temperature`, `num_ctx`, ...); backends map what they support.
+    pub options: Value,
+    /// JSON schema the reply must follow.
+    pub format: Option<Value>,
+}
+
+impl ChatRequest {
+    pub fn from_profile(profile: &Profile, system_text: String, user_text: String) -> Self {
+        Self {
+            model: profile.model.clone(),
+            messages: vec![
+                ChatMessage::new("system", system_text),
+                ChatMessage::new("user", user_text),
+            ],
+            options: profile.ollama_options(),
+            format: profile.output_schema.clone(),
+        }
+    }
+
+    /// A recipe's schema takes precedence over the profile's.
+    pub fn with_format(mut self, schema: Option<Value>) -> Self {
+        if schema.is_some() {
+            self.format = schema;
+        }
+        self
+    }
+
+    /// Attaches images to the last (user) message.
+    pub fn with_images(mut self, images: &[ImageAttachment]) -> Self {
+        if let Some(message) = self.messages.last_mut() {
+            message.images = images.iter().map(|i| i.base64.clone()).collect();
+        }
+        self
+    }
+}
+
+/// Receives every raw response line together with the events parsed from it;
+/// returns `true` once the stream is finished.
+pub type LineSink<'a> = dyn FnMut(&str, Vec<OllamaEvent>) -> bool + Send + 'a;
+
+/// A chat-capable model server. Implementations own transport, timeouts and
+/// response parsing; callers only see [`OllamaEvent`]s.
+pub trait LlmBackend: Send + Sync {
+    fn kind(&self) -> BackendKind;
+
+    /// Non-streaming chat; resolves to the assistant text.
+    fn chat<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, Result<String, OllamaError>>;
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_line: &'a mut LineSink<'_>,
+    ) -> BoxFuture<'a, Result<(), OllamaError>>;
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, OllamaError>>;
+
+    /// Succeeds when the server is reachable and answering its API.
+    fn health(&self) -> BoxFuture<'_, Result<(), OllamaError>>;
+
+    /// Server version for run manifests; `None` when the backend has none.
+    fn version(&self) -> BoxFuture<'_, Result<Option<String>, OllamaError>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// The JSON body sent for `request`; recordings are keyed by its hash.
+    fn wire_payload(&self, request: &ChatRequest, stream: bool) -> Value {
+        OllamaBackend::payload(request, stream)
+    }
+}
+
+pub(crate) fn protocol_error(err: serde_json::Error) -> OllamaError {
+    OllamaError::new(OllamaErrorKind::Protocol, err.to_string())
+}
+
+pub struct OllamaBackend {
+    config: OllamaClientConfig,
+}
+
+impl OllamaBackend {
+    pub fn new(config: OllamaClientConfig) -> Self {
+        Self { config }
+    }
+
+    fn payload(request: &ChatRequest, stream: bool) -> Value {
+        let mut payload = json!({
+            "model": request.model,
+            "stream": stream,
+            "messages": request.messages,
+        });
+        if request.options.as_object().is_some_and(|o| !o.is_empty()) {
+            payload["options"] = request.options.clone();
+        }
+        if let Some(schema) = &request.format {
+            payload["format"] = schema.clone();
+        }
+        payload
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    message: ChatMessage,
+}
+
+impl LlmBackend for OllamaBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Ollama
+    }
+
+    fn chat<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, Result<String, OllamaError>> {
+        Box::pin(async move {
+            let client = self.config.build_client()?;
+            let url = self.config.endpoint("/api/chat");
+            let payload = Self::payload(request, false);
+            let body = request_text(&client, &self.config, |c| c.post(&url).json(&payload)).await?;
+            let response: OllamaChatResponse =
+                serde_json::from_str(&body).map_err(protocol_error)?;
+            Ok(response.message.content)
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_line: &'a mut LineSink<'_>,
+    ) -> BoxFuture<'a, Result<(), OllamaError>> {
+        Box::pin(async move {
+            let client = self.config.build_client()?;
+            let url = self.config.endpoint("/api/chat");
+            let payload = Self::payload(request, true);
+            stream_ndjson(
+                &client,
+                &self.config,
+                |c| c.post(&url).json(&payload),
+                |line| {
+                    let events = parse_ollama_jsonl_chunk(line).map_err(protocol_error)?;
+                    Ok(on_line(line, events))
+                },
+            )
+            .await
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, OllamaError>> {
+        Box::pin(async move {
+            let client = self.config.build_client()?;
+            let models = list_models(&client, &self.config).await?;
+            Ok(models.into_iter().map(|m| m.name).collect())
+        })
+    }
+
+    fn health(&self) -> BoxFuture<'_, Result<(), OllamaError>> {
+        Box::pin(async move {
+            let client = self.config.build_client()?;
+            let url = self.config.endpoint("/api/version");
+            request_text(&client, &self.config, |c| c.get(&url)).await?;
+            Ok(())
+        })
+    }
+
+    fn version(&self) -> BoxFuture<'_, Result<Option<String>, OllamaError>> {
+        Box::pin(async move {
+            let client = self.config.build_client()?;
+            let url = self.config.endpoint("/api/version");
+            let body = request_text(&client, &self.config, |c| c.get(&url)).await?;
+            let body: Value = serde_json::from_str(&body).map_err(protocol_error)?;
+            Ok(body["version"].as_str().map(str::to_string))
+        })
+    }
+
+    fn wire_payload(&self, request: &ChatRequest, stream: bool) -> Value {
+        Self::payload(request, stream)
+    }
+}
+
+/// Builds the backend named by `profile.backend`, honouring its `base_url`.
+pub fn backend_for(profile: &Profile) -> Box<dyn LlmBackend> {
+    let mut config = OllamaClientConfig::from_env();
+    match profile.backend {
+        BackendKind::Ollama => {
+            if let Some(url) = &profile.base_url {
+                config.base_url = url.clone();
+            }
+            Box::new(OllamaBackend::new(config))
+        }
+        BackendKind::Openai => Box::new(OpenAiBackend::from_env(config, profile.base_url.clone())),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackendStatus {
+    pub backend: BackendKind,
+    pub model: String,
+    pub healthy: bool,
+    pub models: Vec<String>,
+    pub error: Option<OllamaError>,
+}
+
+/// Health and model list for the backend a profile (or bare model name) resolves to.
+pub async fn backend_status(profile: String) -> Result<BackendStatus, String> {
+    let profile = resolve_profile(&profile).map_err(|e| e.to_string())?;
+    let backend = backend_for(&profile);
+    let checked = async {
+        backend.health().await?;
+        backend.list_models().await
+    }
+    .await;
+    let (healthy, models, error) = match checked {
+        Ok(models) => (true, models, None),
+        Err(err) => (false, Vec::new(), Some(err)),
+    };
+    Ok(BackendStatus {
+        backend: backend.kind(),
+        model: profile.model,
+        healthy,
+        models,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ollama::{MockConfig, MockOllama, MockReply};
+
+    fn request() -> ChatRequest {
+        let profile: Profile =
+            serde_yaml::from_str("model: llama3:8b\ntemperature: 0.5\n").unwrap();
+        ChatRequest::from_profile(&profile, "SYS".into(), "hi".into())
+    }
+
+    fn backend(base_url: String) -> OllamaBackend {
+        OllamaBackend::new(OllamaClientConfig {
+            base_url,
+            ..OllamaClientConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn ollama_backend_chats_and_streams() {
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        mock.push("/api/chat", MockReply::tokens(["Hello"]));
+        mock.push("/api/chat", MockReply::tokens(["He", "llo"]));
+        let backend = backend(mock.url());
+
+        assert_eq!(backend.chat(&request()).await.unwrap(), "Hello");
+        let sent = &mock.requests_to("/api/chat")[0];
+        assert_eq!(sent.method, "POST");
+        assert_eq!(sent.body["options"], json!({"temperature": 0.5}));
+
+        let mut events = Vec::new();
+        backend
+            .stream(&request(), &mut |_, parsed| {
+                let done = parsed.iter().any(|e| matches!(e, OllamaEvent::Done(_)));
+                events.extend(parsed);
+                done
+            })
+            .await
+            .unwrap();
+        assert_eq!(events[0], OllamaEvent::Chunk("He".into()));
+        assert_eq!(events[1], OllamaEvent::Chunk("llo".into()));
+        assert!(matches!(&events[2], OllamaEvent::Done(stats) if stats.eval_count == Some(2)));
+
+        assert_eq!(backend.list_models().await.unwrap(), vec!["llama3:8b"]);
+    }
+
+    #[test]
+    fn images_ride_on_the_user_message() {
+        let image = ImageAttachment {
+            path: "project/frame.png".into(),
+            size_bytes: 8,
+            sha256: "00".into(),
+            base64: "iVBORw0KGgo=".into(),
+        };
+        let payload = OllamaBackend::payload(&request().with_images(&[image]), true);
+        assert_eq!(payload["messages"][0].get("images"), None);
+        assert_eq!(payload["messages"][1]["images"], json!(["iVBORw0KGgo="]));
+        assert_eq!(payload.get("format"), None);
+
+        let schema = json!({"type": "object"});
+        let payload = OllamaBackend::payload(&request().with_format(Some(schema.clone())), false);
+        assert_eq!(payload["format"], schema);
+    }
+
+    #[test]
+    fn profiles_select_backend() {
+        let profile: Profile = serde_yaml::from_str(
+            "model: qwen2.5\nbackend: openai\nbase_url: http://127.0.0.1:8080\n",
+        )
+        .unwrap();
+        assert_eq!(backend_for(&profile).kind(), BackendKind::Openai);
+        assert_eq!(
+            backend_for(&Profile::for_model("llama3:8b")).kind(),
+            BackendKind::Ollama
+        );
+    }
+}
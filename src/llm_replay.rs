@@ -0,0 +1,466 @@
+//! Record and replay of backend calls. With `PROMPTFORGE_LLM_MODE=record`
+//! every call made for a streamed run is saved under
+//! `runs/<id>/recordings/<sha256>.json`, keyed by the hash of the exact
+//! request payload. With `PROMPTFORGE_LLM_MODE=replay` those recordings are
+//! served instead of the network, so recipe changes can be checked against
+//! earlier responses on machines without a model.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use chrono::Local;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::llm_backend::{
+    backend_for, protocol_error, BackendKind, ChatRequest, LineSink, LlmBackend,
+};
+use crate::ollama_client::{OllamaError, OllamaErrorKind};
+use crate::ollama_stream::{parse_ollama_jsonl_chunk, OllamaEvent};
+use crate::openai_compat::SseParser;
+use crate::profiles::Profile;
+
+pub const LLM_MODE_ENV: &str = "PROMPTFORGE_LLM_MODE";
+pub const RECORDINGS_DIR: &str = "recordings";
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum SessionMode {
+    #[default]
+    Live,
+    Record,
+    Replay,
+}
+
+impl SessionMode {
+    pub fn from_env() -> Self {
+        match env::var(LLM_MODE_ENV)
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "record" => Self::Record,
+            "replay" => Self::Replay,
+            _ => Self::Live,
+        }
+    }
+}
+
+/// One backend call: the payload that was sent and what came back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub key: String,
+    pub backend: BackendKind,
+    pub payload: Value,
+    /// Raw response lines of a streamed call, in arrival order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<String>,
+    /// Reply of a non-streaming call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub recorded_at: String,
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// sha256 of `payload` with object keys sorted, so the key does not depend
+/// on serde_json's map ordering.
+pub fn payload_key(payload: &Value) -> String {
+    let mut canonical = String::new();
+    write_canonical(payload, &mut canonical);
+    hex::encode(Sha256::digest(canonical.as_bytes()))
+}
+
+fn recording(backend: &dyn LlmBackend, payload: Value) -> Recording {
+    Recording {
+        key: payload_key(&payload),
+        backend: backend.kind(),
+        payload,
+        lines: Vec::new(),
+        text: None,
+        recorded_at: Local::now().to_rfc3339(),
+    }
+}
+
+/// Calls captured during one run; written next to its run record.
+#[derive(Debug, Default)]
+pub struct SessionRecorder {
+    recordings: Mutex<Vec<Recording>>,
+}
+
+impl SessionRecorder {
+    fn push(&self, recording: Recording) {
+        self.recordings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(recording);
+    }
+
+    /// Writes `<run_dir>/recordings/<key>.json`; returns how many were saved.
+    pub fn save(&self, run_dir: &Path) -> io::Result<usize> {
+        let recordings = self
+            .recordings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if recordings.is_empty() {
+            return Ok(0);
+        }
+        let dir = run_dir.join(RECORDINGS_DIR);
+        fs::create_dir_all(&dir)?;
+        for recording in recordings.iter() {
+            let json = serde_json::to_string_pretty(recording)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            fs::write(dir.join(format!("{}.json", recording.key)), json)?;
+        }
+        Ok(recordings.len())
+    }
+}
+
+/// Passes calls through to `inner` and records the completed ones.
+pub struct RecordingBackend {
+    inner: Box<dyn LlmBackend>,
+    recorder: Arc<SessionRecorder>,
+}
+
+impl RecordingBackend {
+    pub fn new(inner: Box<dyn LlmBackend>, recorder: Arc<SessionRecorder>) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl LlmBackend for RecordingBackend {
+    fn kind(&self) -> BackendKind {
+        self.inner.kind()
+    }
+
+    fn chat<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, Result<String, OllamaError>> {
+        Box::pin(async move {
+            let text = self.inner.chat(request).await?;
+            let mut call = recording(self.inner.as_ref(), self.wire_payload(request, false));
+            call.text = Some(text.clone());
+            self.recorder.push(call);
+            Ok(text)
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_line: &'a mut LineSink<'_>,
+    ) -> BoxFuture<'a, Result<(), OllamaError>> {
+        Box::pin(async move {
+            let mut lines = Vec::new();
+            self.inner
+                .stream(request, &mut |line, events| {
+                    lines.push(line.to_string());
+                    on_line(line, events)
+                })
+                .await?;
+            let mut call = recording(self.inner.as_ref(), self.wire_payload(request, true));
+            call.lines = lines;
+            self.recorder.push(call);
+            Ok(())
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, OllamaError>> {
+        self.inner.list_models()
+    }
+
+    fn health(&self) -> BoxFuture<'_, Result<(), OllamaError>> {
+        self.inner.health()
+    }
+
+    fn version(&self) -> BoxFuture<'_, Result<Option<String>, OllamaError>> {
+        self.inner.version()
+    }
+
+    fn wire_payload(&self, request: &ChatRequest, stream: bool) -> Value {
+        self.inner.wire_payload(request, stream)
+    }
+}
+
+/// Every recording found under a runs root, by key.
+#[derive(Debug, Default)]
+pub struct ReplayStore {
+    recordings: HashMap<String, Recording>,
+}
+
+impl ReplayStore {
+    /// Reads `<runs_root>/*/recordings/*.json`. Run ids sort by time, so the
+    /// newest recording of a payload wins; unreadable files are skipped.
+    pub fn load(runs_root: &Path) -> Self {
+        let mut files: Vec<_> = WalkDir::new(runs_root)
+            .min_depth(3)
+            .max_depth(3)
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|entry| entry.into_path())
+            .filter(|path| {
+                path.extension().is_some_and(|ext| ext == "json")
+                    && path
+                        .parent()
+                        .is_some_and(|dir| dir.ends_with(RECORDINGS_DIR))
+            })
+            .collect();
+        files.sort();
+        let recordings = files
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .filter_map(|json| serde_json::from_str::<Recording>(&json).ok())
+            .map(|recording| (recording.key.clone(), recording))
+            .collect();
+        Self { recordings }
+    }
+
+    /// [`ReplayStore::load`] once per runs root and process. Replay mode
+    /// never records, so the store cannot go stale while it is shared.
+    pub fn shared(runs_root: &Path) -> Arc<Self> {
+        static STORES: OnceLock<Mutex<HashMap<PathBuf, Arc<ReplayStore>>>> = OnceLock::new();
+        let mut stores = STORES
+            .get_or_init(Mutex::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        stores
+            .entry(runs_root.to_path_buf())
+            .or_insert_with(|| Arc::new(Self::load(runs_root)))
+            .clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.recordings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recordings.is_empty()
+    }
+}
+
+/// Serves recorded replies; `wire` only shapes payloads and never connects.
+pub struct ReplayBackend {
+    wire: Box<dyn LlmBackend>,
+    store: Arc<ReplayStore>,
+}
+
+impl ReplayBackend {
+    pub fn new(wire: Box<dyn LlmBackend>, store: Arc<ReplayStore>) -> Self {
+        Self { wire, store }
+    }
+
+    fn lookup(&self, request: &ChatRequest, stream: bool) -> Result<&Recording, OllamaError> {
+        let key = payload_key(&self.wire.wire_payload(request, stream));
+        self.store.recordings.get(&key).ok_or_else(|| {
+            OllamaError::new(
+                OllamaErrorKind::Model,
+                format!(
+                    "no recording for request {} (record it with {}=record)",
+                    &key[..12],
+                    LLM_MODE_ENV
+                ),
+            )
+        })
+    }
+}
+
+impl LlmBackend for ReplayBackend {
+    fn kind(&self) -> BackendKind {
+        self.wire.kind()
+    }
+
+    fn chat<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, Result<String, OllamaError>> {
+        Box::pin(async move {
+            let recording = self.lookup(request, false)?;
+            recording.text.clone().ok_or_else(|| {
+                OllamaError::new(OllamaErrorKind::Protocol, "recording has no reply text")
+            })
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_line: &'a mut LineSink<'_>,
+    ) -> BoxFuture<'a, Result<(), OllamaError>> {
+        Box::pin(async move {
+            let recording = self.lookup(request, true)?;
+            let mut parser = SseParser::default();
+            for line in &recording.lines {
+                let events: Vec<OllamaEvent> = match recording.backend {
+                    BackendKind::Ollama => parse_ollama_jsonl_chunk(line),
+                    BackendKind::Openai => parser.parse_line(line),
+                }
+                .map_err(protocol_error)?;
+                if on_line(line, events) {
+                    break;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, OllamaError>> {
+        Box::pin(async move {
+            let mut models: Vec<String> = self
+                .store
+                .recordings
+                .values()
+                .filter_map(|r| r.payload.get("model").and_then(Value::as_str))
+                .map(str::to_string)
+                .collect();
+            models.sort();
+            models.dedup();
+            Ok(models)
+        })
+    }
+
+    fn health(&self) -> BoxFuture<'_, Result<(), OllamaError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn wire_payload(&self, request: &ChatRequest, stream: bool) -> Value {
+        self.wire.wire_payload(request, stream)
+    }
+}
+
+/// [`backend_for`] adjusted for `mode`; replay reads the recordings under
+/// `runs_root`. The recorder is returned in record mode so the caller can
+/// save it with the run.
+pub fn session_backend(
+    profile: &Profile,
+    runs_root: &Path,
+    mode: SessionMode,
+) -> (Box<dyn LlmBackend>, Option<Arc<SessionRecorder>>) {
+    let backend = backend_for(profile);
+    match mode {
+        SessionMode::Live => (backend, None),
+        SessionMode::Record => {
+            let recorder = Arc::new(SessionRecorder::default());
+            (
+                Box::new(RecordingBackend::new(backend, recorder.clone())),
+                Some(recorder),
+            )
+        }
+        SessionMode::Replay => (
+            Box::new(ReplayBackend::new(backend, ReplayStore::shared(runs_root))),
+            None,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_backend::{ChatMessage, OllamaBackend};
+    use crate::mock_ollama::{MockConfig, MockOllama, MockReply};
+    use crate::ollama_client::{OllamaClientConfig, RetryPolicy};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    fn ollama(base_url: String) -> Box<dyn LlmBackend> {
+        Box::new(OllamaBackend::new(OllamaClientConfig {
+            base_url,
+            retry: RetryPolicy {
+                max_retries: 0,
+                ..RetryPolicy::default()
+            },
+            ..OllamaClientConfig::default()
+        }))
+    }
+
+    fn request(user: &str) -> ChatRequest {
+        ChatRequest {
+            model: "llama3:8b".into(),
+            messages: vec![ChatMessage::new("user", user)],
+            options: json!({ "temperature": 0.2, "seed": 7 }),
+            format: None,
+        }
+    }
+
+    async fn collect(backend: &dyn LlmBackend, request: &ChatRequest) -> Vec<OllamaEvent> {
+        let mut seen = Vec::new();
+        backend
+            .stream(request, &mut |_, events| {
+                seen.extend(events);
+                false
+            })
+            .await
+            .expect("stream");
+        seen
+    }
+
+    #[test]
+    fn payload_key_ignores_key_order() {
+        let a: Value =
+            serde_json::from_str(r#"{"a":1,"b":{"c":[1,{"x":1,"y":2}],"d":3}}"#).unwrap();
+        let b: Value =
+            serde_json::from_str(r#"{"b":{"d":3,"c":[1,{"y":2,"x":1}]},"a":1}"#).unwrap();
+        assert_eq!(payload_key(&a), payload_key(&b));
+        assert_ne!(payload_key(&a), payload_key(&json!({"a": 2})));
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_calls_without_the_server() {
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        mock.push("/api/chat", MockReply::tokens(["Hel", "lo"]));
+        let recorder = Arc::new(SessionRecorder::default());
+        let recording = RecordingBackend::new(ollama(mock.url()), recorder.clone());
+        let live_events = collect(&recording, &request("hi")).await;
+        let live_text = recording.chat(&request("again")).await.unwrap();
+
+        let runs = tempdir().unwrap();
+        let run_dir = runs.path().join("20240101-000000");
+        assert_eq!(recorder.save(&run_dir).unwrap(), 2);
+        let unreachable = mock.url();
+        drop(mock);
+
+        let store = ReplayStore::load(runs.path());
+        assert_eq!(store.len(), 2);
+        let replay = ReplayBackend::new(ollama(unreachable), Arc::new(store));
+        assert_eq!(collect(&replay, &request("hi")).await, live_events);
+        assert_eq!(replay.chat(&request("again")).await.unwrap(), live_text);
+        assert_eq!(replay.list_models().await.unwrap(), vec!["llama3:8b"]);
+
+        let missing = replay.chat(&request("changed")).await.unwrap_err();
+        assert_eq!(missing.kind, OllamaErrorKind::Model);
+        assert!(
+            missing.message.contains("no recording"),
+            "{}",
+            missing.message
+        );
+    }
+}
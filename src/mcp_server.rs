@@ -0,0 +1,587 @@
+//! Model Context Protocol server over stdio (`promptforge mcp`), so local
+//! agents can reuse the fragment library without any network listener.
+//!
+//! - prompts: every recipe, by name, with its params as arguments;
+//!   `prompts/get` returns the composed prompt.
+//! - tools: `compose_prompt`, `load_txt_excerpt` and `run_recipe`.
+//! - resources: files under `project/` and `corpus/` next to the data
+//!   directory, read-only and through the same sandboxed loaders as the app
+//!   (`promptforge://project/<path>`, `promptforge://corpus/<path>`).
+//!
+//! Messages are newline-delimited JSON-RPC 2.0; stdout carries nothing else.
+//! Requests are answered as they finish, so a running `run_recipe` does not
+//! hold up `ping` or the other methods.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde_json::{json, Map, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+use crate::chat::{spawn_chat_stream, ChatStreamJob};
+use crate::compose::{
+    compose_prompt_in, data_sandbox, fragment_file, named_recipe_path, read_yaml, recipe_file,
+    recipe_names, split_final_prompt, Fragment, Recipe,
+};
+use crate::corpus_index::SOURCE_ROOTS;
+use crate::files::{list_project_files_in, read_project_file_in};
+use crate::ollama_stream::{ChannelSink, StreamState};
+use crate::profiles::resolve_profile_in;
+use crate::recipe_lint::placeholders;
+use crate::runs::{runs_root, RunStatus};
+use crate::txt_excerpt::load_txt_excerpt_in;
+
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+const RESOURCE_SCHEME: &str = "promptforge://";
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid(message: impl Into<String>) -> Self {
+        Self {
+            code: INVALID_PARAMS,
+            message: message.into(),
+        }
+    }
+}
+
+/// Answers MCP requests against one data directory; runs go to `runs_root`.
+pub struct McpServer {
+    data_dir: PathBuf,
+    /// Folder holding `project/` and `corpus/`.
+    root: PathBuf,
+    runs_root: PathBuf,
+    state: StreamState,
+}
+
+impl Default for McpServer {
+    fn default() -> Self {
+        Self::new(data_sandbox(), runs_root())
+    }
+}
+
+fn text_content(text: impl Into<String>) -> Value {
+    json!({ "type": "text", "text": text.into() })
+}
+
+fn tool_result(result: Result<Vec<Value>, String>) -> Value {
+    match result {
+        Ok(content) => json!({ "content": content, "isError": false }),
+        Err(message) => json!({ "content": [text_content(message)], "isError": true }),
+    }
+}
+
+fn tool_definitions() -> Value {
+    json!([
+        {
+            "name": "compose_prompt",
+            "description": "Compose a recipe into its final prompt. Returns the prompt, its sha256 and the recipe's profile.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "recipe": { "type": "string", "description": "Recipe path relative to the data directory, e.g. recipes/demo.sora2.yaml" },
+                    "params": { "type": "object", "description": "Params overriding the recipe's" },
+                },
+                "required": ["recipe"],
+            },
+        },
+        {
+            "name": "load_txt_excerpt",
+            "description": "Head and tail excerpt of a text file under corpus/.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Path relative to corpus/" },
+                    "max_bytes": { "type": "integer", "minimum": 0 },
+                },
+                "required": ["path"],
+            },
+        },
+        {
+            "name": "run_recipe",
+            "description": "Compose a recipe and run it through its profile's backend; the run is saved like any other.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "recipe": { "type": "string" },
+                    "params": { "type": "object" },
+                    "user_input": { "type": "string" },
+                    "model": { "type": "string", "description": "Profile or model overriding the recipe's" },
+                },
+                "required": ["recipe"],
+            },
+        },
+    ])
+}
+
+fn string_arg<'a>(args: &'a Value, name: &str) -> Result<&'a str, String> {
+    args.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("`{}` is required", name))
+}
+
+fn object_arg(args: &Value, name: &str) -> Map<String, Value> {
+    args.get(name)
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default()
+}
+
+impl McpServer {
+    /// Serves `project/` and `corpus/` from the folder that holds `data_dir`.
+    pub fn new(data_dir: PathBuf, runs_root: PathBuf) -> Self {
+        let root = data_dir.parent().map(Path::to_path_buf).unwrap_or_default();
+        Self {
+            data_dir,
+            root,
+            runs_root,
+            state: StreamState::default(),
+        }
+    }
+
+    fn project_dir(&self) -> PathBuf {
+        self.root.join("project")
+    }
+
+    fn corpus_dir(&self) -> PathBuf {
+        self.root.join("corpus")
+    }
+
+    /// Answers one JSON-RPC message; `None` for notifications.
+    pub async fn handle(&self, message: Value) -> Option<Value> {
+        let id = message.get("id").cloned()?;
+        let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let result = match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "prompts": {}, "tools": {}, "resources": {} },
+                "serverInfo": { "name": "promptforge", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "ping" => Ok(json!({})),
+            "prompts/list" => Ok(json!({ "prompts": self.list_prompts() })),
+            "prompts/get" => self.get_prompt(&params),
+            "tools/list" => Ok(json!({ "tools": tool_definitions() })),
+            "tools/call" => self.call_tool(&params).await,
+            "resources/list" => Ok(json!({ "resources": self.list_resources() })),
+            "resources/read" => self.read_resource(&params),
+            _ => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method `{}`", method),
+            }),
+        };
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": error.code, "message": error.message },
+            }),
+        })
+    }
+
+    async fn handle_line(&self, line: String) -> Option<Value> {
+        match serde_json::from_str::<Value>(&line) {
+            Ok(message) => self.handle(message).await,
+            Err(err) => Some(json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": PARSE_ERROR, "message": err.to_string() },
+            })),
+        }
+    }
+
+    /// Reads messages from `reader` line by line until it closes, answering
+    /// each one as soon as it is done; pending requests are awaited at the end.
+    pub async fn serve<R, W>(&self, reader: R, mut writer: W) -> io::Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut lines = reader.lines();
+        let mut pending = FuturesUnordered::new();
+        let mut reading = true;
+        loop {
+            tokio::select! {
+                line = lines.next_line(), if reading => match line? {
+                    Some(line) if line.trim().is_empty() => {}
+                    Some(line) => pending.push(self.handle_line(line)),
+                    None => reading = false,
+                },
+                Some(reply) = pending.next(), if !pending.is_empty() => {
+                    if let Some(reply) = reply {
+                        writer.write_all(format!("{}\n", reply).as_bytes()).await?;
+                        writer.flush().await?;
+                    }
+                }
+                else => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Recipe params (with their defaults) plus any placeholder a fragment
+    /// uses; placeholders without a default are required.
+    fn prompt_arguments(&self, recipe: &Recipe) -> Vec<Value> {
+        let defaults = recipe.params.as_object().cloned().unwrap_or_default();
+        let mut arguments: BTreeMap<String, Value> = BTreeMap::new();
+        for (name, value) in &defaults {
+            let shown = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            arguments.insert(
+                name.clone(),
+                json!({ "name": name, "description": format!("default: {}", shown), "required": false }),
+            );
+        }
+        for id in &recipe.fragments {
+            let Ok(fragment) =
+                fragment_file(&self.data_dir, id).and_then(|p| read_yaml::<Fragment>(&p))
+            else {
+                continue;
+            };
+            for name in placeholders(&fragment.content) {
+                let required = name != "user_input";
+                arguments
+                    .entry(name.clone())
+                    .or_insert_with(|| json!({ "name": name, "required": required }));
+            }
+        }
+        arguments
+            .entry("user_input".into())
+            .or_insert_with(|| json!({ "name": "user_input", "required": false }));
+        arguments.into_values().collect()
+    }
+
+    fn list_prompts(&self) -> Vec<Value> {
+        recipe_names(&self.data_dir)
+            .into_iter()
+            .filter_map(|name| {
+                let path = recipe_file(&self.data_dir, &named_recipe_path(&name)).ok()?;
+                let recipe: Recipe = read_yaml(&path).ok()?;
+                Some(json!({
+                    "name": name,
+                    "description": format!("{} ({})", named_recipe_path(&name), recipe.profile),
+                    "arguments": self.prompt_arguments(&recipe),
+                }))
+            })
+            .collect()
+    }
+
+    fn get_prompt(&self, params: &Value) -> Result<Value, RpcError> {
+        let name = params
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::invalid("`name` is required"))?;
+        if !recipe_names(&self.data_dir).iter().any(|n| n == name) {
+            return Err(RpcError::invalid(format!("unknown prompt `{}`", name)));
+        }
+        // MCP sends every argument as a string; JSON values are parsed back.
+        let arguments: Map<String, Value> = object_arg(params, "arguments")
+            .into_iter()
+            .map(|(key, value)| match value {
+                Value::String(s) if key != "user_input" => {
+                    (key, serde_json::from_str(&s).unwrap_or(Value::String(s)))
+                }
+                other => (key, other),
+            })
+            .collect();
+        let composed = compose_prompt_in(
+            &self.data_dir,
+            &named_recipe_path(name),
+            Some(Value::Object(arguments)),
+        )
+        .map_err(|e| RpcError::invalid(e.to_string()))?;
+        Ok(json!({
+            "description": format!("sha256 {}", composed.sha256),
+            "messages": [{ "role": "user", "content": text_content(composed.final_prompt) }],
+        }))
+    }
+
+    async fn call_tool(&self, params: &Value) -> Result<Value, RpcError> {
+        let name = params
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::invalid("`name` is required"))?;
+        let args = params.get("arguments").cloned().unwrap_or(json!({}));
+        let result = match name {
+            "compose_prompt" => self.compose_tool(&args),
+            "load_txt_excerpt" => load_txt_excerpt_in(
+                &self.corpus_dir(),
+                string_arg(&args, "path").map_err(RpcError::invalid)?,
+                args.get("max_bytes").and_then(Value::as_u64),
+            )
+            .and_then(|excerpt| {
+                let json = serde_json::to_string_pretty(&excerpt).map_err(|e| e.to_string())?;
+                Ok(vec![text_content(json)])
+            }),
+            "run_recipe" => self.run_tool(&args).await,
+            _ => return Err(RpcError::invalid(format!("unknown tool `{}`", name))),
+        };
+        Ok(tool_result(result))
+    }
+
+    fn compose_tool(&self, args: &Value) -> Result<Vec<Value>, String> {
+        let recipe = string_arg(args, "recipe")?;
+        let params = Value::Object(object_arg(args, "params"));
+        let composed =
+            compose_prompt_in(&self.data_dir, recipe, Some(params)).map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(&composed).map_err(|e| e.to_string())?;
+        Ok(vec![text_content(json)])
+    }
+
+    async fn run_tool(&self, args: &Value) -> Result<Vec<Value>, String> {
+        let recipe = string_arg(args, "recipe")?;
+        let mut params = object_arg(args, "params");
+        if let Some(user_input) = args.get("user_input").and_then(Value::as_str) {
+            params.insert("user_input".into(), Value::String(user_input.into()));
+        }
+        let composed = compose_prompt_in(&self.data_dir, recipe, Some(Value::Object(params)))
+            .map_err(|e| e.to_string())?;
+        let model = args
+            .get("model")
+            .and_then(Value::as_str)
+            .unwrap_or(&composed.model);
+        let profile = resolve_profile_in(&self.data_dir, model).map_err(|e| e.to_string())?;
+        let (system_text, user_text) = split_final_prompt(&composed.final_prompt);
+        let mut job = ChatStreamJob::new(
+            &profile,
+            system_text,
+            user_text,
+            Some(recipe.to_string()),
+            Vec::new(),
+            composed.output_schema.clone(),
+        )
+        .with_composition(&composed);
+        job.runs_root = self.runs_root.clone();
+        // Events have no listener here; only the outcome matters.
+        let (tx, _) = mpsc::unbounded_channel();
+        let (_, done) = spawn_chat_stream(Arc::new(ChannelSink(tx)), &self.state, job).await?;
+        let outcome = done
+            .await
+            .map_err(|_| "stream ended without an outcome".to_string())?;
+        let summary = json!({ "run_id": outcome.run_id, "status": outcome.status });
+        if outcome.status != RunStatus::Completed {
+            return Err(format!("run failed: {}", summary));
+        }
+        Ok(vec![
+            text_content(outcome.partial_text),
+            text_content(summary.to_string()),
+        ])
+    }
+
+    fn list_resources(&self) -> Vec<Value> {
+        let mut resources = Vec::new();
+        let project = self.project_dir();
+        if project.is_dir() {
+            for entry in list_project_files_in(&project, None).unwrap_or_default() {
+                resources.push(json!({
+                    "uri": format!("{}project/{}", RESOURCE_SCHEME, entry.path.replace('\\', "/")),
+                    "name": entry.name,
+                    "mimeType": "text/plain",
+                }));
+            }
+        }
+        let corpus_exts = SOURCE_ROOTS
+            .iter()
+            .find(|(root, _)| *root == "corpus")
+            .map(|(_, exts)| *exts)
+            .unwrap_or_default();
+        let corpus_dir = self.corpus_dir();
+        let mut corpus: Vec<String> = walkdir::WalkDir::new(&corpus_dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter(|e| {
+                let ext = e.path().extension().and_then(|s| s.to_str()).unwrap_or("");
+                corpus_exts.contains(&ext.to_lowercase().as_str())
+            })
+            .filter_map(|e| {
+                let rel = e.path().strip_prefix(&corpus_dir).ok()?;
+                Some(rel.to_string_lossy().replace('\\', "/"))
+            })
+            .collect();
+        corpus.sort();
+        for rel in corpus {
+            resources.push(json!({
+                "uri": format!("{}corpus/{}", RESOURCE_SCHEME, rel),
+                "name": rel,
+                "description": "excerpt (head and tail) of a corpus file",
+                "mimeType": "text/plain",
+            }));
+        }
+        resources
+    }
+
+    fn read_resource(&self, params: &Value) -> Result<Value, RpcError> {
+        let uri = params
+            .get("uri")
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::invalid("`uri` is required"))?;
+        let rel = uri
+            .strip_prefix(RESOURCE_SCHEME)
+            .ok_or_else(|| RpcError::invalid(format!("unknown resource `{}`", uri)))?;
+        let text = if let Some(path) = rel.strip_prefix("project/") {
+            read_project_file_in(&self.project_dir(), path).map(|file| file.content)
+        } else if let Some(path) = rel.strip_prefix("corpus/") {
+            load_txt_excerpt_in(&self.corpus_dir(), path, None).map(|excerpt| excerpt.excerpt)
+        } else {
+            Err(format!("unknown resource `{}`", uri))
+        }
+        .map_err(RpcError::invalid)?;
+        Ok(json!({
+            "contents": [{ "uri": uri, "mimeType": "text/plain", "text": text }],
+        }))
+    }
+}
+
+/// Serves MCP on stdin/stdout until stdin closes.
+pub async fn serve_stdio() -> io::Result<()> {
+    McpServer::default()
+        .serve(BufReader::new(tokio::io::stdin()), tokio::io::stdout())
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ollama::{MockConfig, MockOllama, MockReply};
+    use crate::test_support::{data_fixture, write};
+    use tempfile::tempdir;
+
+    async fn serve_all(server: &McpServer, messages: &[Value]) -> Vec<Value> {
+        let input = messages
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut output = Vec::new();
+        server
+            .serve(BufReader::new(input.as_bytes()), &mut output)
+            .await
+            .unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn serves_prompts_and_tools_over_json_rpc() {
+        let temp = tempdir().unwrap();
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        let data = data_fixture(temp.path(), &mock.url());
+        write(
+            &data,
+            "fragments/task/echo.yaml",
+            "id: task.echo\nkind: task\ncontent: \"Plan {{steps}} shots in a {{tone}} tone.\"\n",
+        );
+        write(temp.path(), "project/notes.md", "# Notes\n");
+        write(temp.path(), "corpus/story.txt", "Once upon a time.");
+        let server = McpServer::new(data, temp.path().join("runs"));
+
+        let replies = serve_all(
+            &server,
+            &[
+                json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+                json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+                json!({ "jsonrpc": "2.0", "id": 2, "method": "prompts/list" }),
+                json!({ "jsonrpc": "2.0", "id": 3, "method": "prompts/get",
+                        "params": { "name": "echo", "arguments": { "steps": "4" } } }),
+                json!({ "jsonrpc": "2.0", "id": 4, "method": "tools/call",
+                        "params": { "name": "run_recipe",
+                                    "arguments": { "recipe": "recipes/echo.yaml", "params": { "steps": 2 } } } }),
+                json!({ "jsonrpc": "2.0", "id": 5, "method": "resources/read",
+                        "params": { "uri": "promptforge://project/../secret.txt" } }),
+                json!({ "jsonrpc": "2.0", "id": 6, "method": "nope" }),
+                json!({ "jsonrpc": "2.0", "id": 7, "method": "resources/list" }),
+                json!({ "jsonrpc": "2.0", "id": 8, "method": "resources/read",
+                        "params": { "uri": "promptforge://project/notes.md" } }),
+                json!({ "jsonrpc": "2.0", "id": 9, "method": "tools/call",
+                        "params": { "name": "load_txt_excerpt", "arguments": { "path": "story.txt" } } }),
+            ],
+        )
+        .await;
+        let reply = |id: i64| replies.iter().find(|r| r["id"] == id).unwrap();
+
+        // The notification gets no reply.
+        assert_eq!(replies.len(), 9);
+        assert_eq!(reply(1)["result"]["protocolVersion"], PROTOCOL_VERSION);
+        let prompt = &reply(2)["result"]["prompts"][0];
+        assert_eq!(prompt["name"], "echo");
+        let arguments: Vec<(&str, bool)> = prompt["arguments"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| (a["name"].as_str().unwrap(), a["required"] == true))
+            .collect();
+        assert_eq!(
+            arguments,
+            vec![("steps", true), ("tone", false), ("user_input", false)]
+        );
+        let text = reply(3)["result"]["messages"][0]["content"]["text"]
+            .as_str()
+            .unwrap();
+        assert!(text.starts_with("Plan 4 shots in a plain tone."));
+        assert_eq!(reply(4)["result"]["isError"], false);
+        assert_eq!(
+            reply(4)["result"]["content"][0]["text"],
+            "This is a mock reply."
+        );
+        assert_eq!(reply(5)["error"]["code"], INVALID_PARAMS);
+        assert_eq!(reply(6)["error"]["code"], METHOD_NOT_FOUND);
+
+        // Resources come from the folder holding the data directory.
+        let uris: Vec<&str> = reply(7)["result"]["resources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["uri"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            uris,
+            vec![
+                "promptforge://project/notes.md",
+                "promptforge://corpus/story.txt"
+            ]
+        );
+        assert_eq!(reply(8)["result"]["contents"][0]["text"], "# Notes\n");
+        let excerpt = reply(9)["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(excerpt.contains("Once upon a time."), "{}", excerpt);
+    }
+
+    #[tokio::test]
+    async fn answers_pings_while_a_run_is_going() {
+        let temp = tempdir().unwrap();
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        mock.push("/api/chat", MockReply::tokens(["slow"]).delay(300));
+        let data = data_fixture(temp.path(), &mock.url());
+        let server = McpServer::new(data, temp.path().join("runs"));
+
+        let replies = serve_all(
+            &server,
+            &[
+                json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call",
+                        "params": { "name": "run_recipe", "arguments": { "recipe": "recipes/echo.yaml" } } }),
+                json!({ "jsonrpc": "2.0", "id": 2, "method": "ping" }),
+            ],
+        )
+        .await;
+        let ids: Vec<&Value> = replies.iter().map(|r| &r["id"]).collect();
+        assert_eq!(ids, vec![&json!(2), &json!(1)]);
+        assert_eq!(replies[1]["result"]["content"][0]["text"], "slow");
+    }
+}
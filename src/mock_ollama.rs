@@ -0,0 +1,544 @@
+//! Scriptable stand-in for the Ollama HTTP API, used by the integration tests
+//! and by `src/bin/mock_ollama.rs` for offline UI work.
+//!
+//! Serves `/api/version`, `/api/tags`, `/api/ps`, `/api/show`, `/api/chat`,
+//! `/api/generate`, `/api/pull`, `/api/embeddings` and `/api/delete`. Replies
+//! queued with [`MockOllama::push`] are served in order for their path; once a
+//! queue is empty the endpoint falls back to a canned answer. Bodies are sent
+//! chunked so a scripted [`MockStep::Disconnect`] surfaces as a truncated
+//! response rather than a clean end of stream.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+
+/// One step of a scripted reply.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockStep {
+    /// Generated text, rendered as a `/api/chat` or `/api/generate` chunk.
+    Token(String),
+    /// A raw NDJSON line, written as-is.
+    Raw(String),
+    /// An `{"error": ...}` chunk; the body ends right after it.
+    Error(String),
+    Delay(Duration),
+    /// Drops the connection without terminating the chunked body.
+    Disconnect,
+    /// Keeps the connection open without sending anything else.
+    Hang,
+}
+
+/// A scripted HTTP reply. Tokens are streamed one per line when the request
+/// asked for a stream and concatenated into one body otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockReply {
+    pub status: u16,
+    pub steps: Vec<MockStep>,
+    /// Whether a final `"done": true` line with generation stats is appended.
+    pub done: bool,
+}
+
+impl MockReply {
+    pub fn tokens<I, S>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            status: 200,
+            steps: tokens
+                .into_iter()
+                .map(|t| MockStep::Token(t.into()))
+                .collect(),
+            done: true,
+        }
+    }
+
+    /// A non-2xx reply with an Ollama-style error body.
+    pub fn status(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            steps: vec![MockStep::Raw(json!({ "error": message }).to_string())],
+            done: false,
+        }
+    }
+
+    /// A 200 reply made of raw lines, e.g. `/api/pull` progress.
+    pub fn lines<I, S>(lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            status: 200,
+            steps: lines.into_iter().map(|l| MockStep::Raw(l.into())).collect(),
+            done: false,
+        }
+    }
+
+    pub fn token(mut self, text: &str) -> Self {
+        self.steps.push(MockStep::Token(text.into()));
+        self
+    }
+
+    pub fn delay(mut self, millis: u64) -> Self {
+        self.steps
+            .push(MockStep::Delay(Duration::from_millis(millis)));
+        self
+    }
+
+    pub fn error_chunk(mut self, message: &str) -> Self {
+        self.steps.push(MockStep::Error(message.into()));
+        self.done = false;
+        self
+    }
+
+    pub fn disconnect(mut self) -> Self {
+        self.steps.push(MockStep::Disconnect);
+        self.done = false;
+        self
+    }
+
+    pub fn hang(mut self) -> Self {
+        self.steps.push(MockStep::Hang);
+        self.done = false;
+        self
+    }
+}
+
+/// A request as the mock received it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub body: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    pub models: Vec<String>,
+    /// Reported by `/api/version`.
+    pub version: String,
+    /// Reported by `/api/show` as `<arch>.context_length`.
+    pub context_length: u64,
+    /// Fallback reply for chat and generate, streamed word by word.
+    pub reply: String,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            models: vec!["llama3:8b".into()],
+            version: "0.5.7".into(),
+            context_length: 8192,
+            reply: "This is a mock reply.".into(),
+        }
+    }
+}
+
+struct Shared {
+    config: MockConfig,
+    scripts: Mutex<HashMap<String, VecDeque<MockReply>>>,
+    requests: Mutex<Vec<MockRequest>>,
+}
+
+/// A running mock server; stops accepting connections when dropped.
+pub struct MockOllama {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+impl MockOllama {
+    /// Starts on an ephemeral localhost port.
+    pub async fn start(config: MockConfig) -> io::Result<Self> {
+        Self::bind("127.0.0.1:0", config).await
+    }
+
+    pub async fn bind(addr: &str, config: MockConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            config,
+            scripts: Mutex::new(HashMap::new()),
+            requests: Mutex::new(Vec::new()),
+        });
+        let shared_for_task = shared.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(socket, shared_for_task.clone()));
+            }
+        });
+        Ok(Self { addr, shared, task })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Queues `reply` for the next request to `path` (e.g. `/api/chat`).
+    pub fn push(&self, path: &str, reply: MockReply) {
+        self.shared
+            .scripts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(path.to_string())
+            .or_default()
+            .push_back(reply);
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.shared
+            .requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Requests received for `path`, e.g. `/api/chat`.
+    pub fn requests_to(&self, path: &str) -> Vec<MockRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.path == path)
+            .collect()
+    }
+
+    /// Serves until the accept loop fails; used by the standalone binary.
+    pub async fn serve_forever(mut self) {
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for MockOllama {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4)
+}
+
+async fn read_request(socket: &mut TcpStream) -> io::Result<Option<MockRequest>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0_u8; 4096];
+    let head_end = loop {
+        if let Some(end) = find_head_end(&buf) {
+            break end;
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Ok(None);
+        }
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("").to_string();
+    let path = request_line.next().unwrap_or("").to_string();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while buf.len() < head_end + content_length {
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+    let body_end = buf.len().min(head_end + content_length);
+    let body = serde_json::from_slice(&buf[head_end..body_end]).unwrap_or(Value::Null);
+    Ok(Some(MockRequest { method, path, body }))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Status",
+    }
+}
+
+fn model_of(request: &MockRequest) -> String {
+    request
+        .body
+        .get("model")
+        .or_else(|| request.body.get("name"))
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .to_string()
+}
+
+fn wants_stream(request: &MockRequest) -> bool {
+    request.body.get("stream").and_then(Value::as_bool) != Some(false)
+}
+
+fn json_reply(value: Value) -> MockReply {
+    MockReply::lines([value.to_string()])
+}
+
+fn token_line(path: &str, model: &str, text: &str, done: bool) -> Value {
+    let mut line = if path == "/api/generate" {
+        json!({ "model": model, "response": text, "done": done })
+    } else {
+        json!({
+            "model": model,
+            "message": { "role": "assistant", "content": text },
+            "done": done,
+        })
+    };
+    line["created_at"] = json!("2024-01-01T00:00:00Z");
+    line
+}
+
+fn done_line(path: &str, model: &str, text: &str, tokens: u64) -> Value {
+    let mut line = token_line(path, model, text, true);
+    let stats = json!({
+        "done_reason": "stop",
+        "total_duration": 1_000_000_u64 * (tokens + 1),
+        "load_duration": 1_000_000_u64,
+        "prompt_eval_count": 8,
+        "prompt_eval_duration": 1_000_000_u64,
+        "eval_count": tokens,
+        "eval_duration": 1_000_000_u64 * tokens.max(1),
+    });
+    for (key, value) in stats.as_object().into_iter().flatten() {
+        line[key] = value.clone();
+    }
+    line
+}
+
+/// Deterministic letter-frequency vector, so related texts score close.
+fn embedding(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0_f32; 8];
+    for c in text.chars().filter(|c| c.is_alphanumeric()) {
+        vector[(c.to_ascii_lowercase() as usize) % 8] += 1.0;
+    }
+    vector
+}
+
+fn default_reply(config: &MockConfig, request: &MockRequest) -> MockReply {
+    let known = |model: &str| config.models.iter().any(|m| m == model);
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/api/version") => json_reply(json!({ "version": config.version })),
+        ("GET", "/api/tags") => json_reply(json!({
+            "models": config.models.iter().map(|name| json!({
+                "name": name,
+                "model": name,
+                "size": 4_661_224_676_u64,
+                "digest": "mock",
+                "modified_at": "2024-01-01T00:00:00Z",
+                "details": { "family": "llama", "parameter_size": "8B", "quantization_level": "Q4_0" },
+            })).collect::<Vec<_>>()
+        })),
+        ("GET", "/api/ps") => json_reply(json!({ "models": [] })),
+        ("POST", "/api/show") if known(&model_of(request)) => json_reply(json!({
+            "details": { "family": "llama", "parameter_size": "8B", "quantization_level": "Q4_0" },
+            "model_info": {
+                "general.architecture": "llama",
+                "llama.context_length": config.context_length,
+            },
+            "parameters": "stop \"<|eot_id|>\"",
+            "template": "{{ .Prompt }}",
+        })),
+        ("POST", "/api/chat") | ("POST", "/api/generate") if known(&model_of(request)) => {
+            MockReply::tokens(config.reply.split_inclusive(' '))
+        }
+        ("POST", "/api/show") | ("POST", "/api/chat") | ("POST", "/api/generate") => {
+            MockReply::status(404, &format!("model '{}' not found", model_of(request)))
+        }
+        ("POST", "/api/pull") => MockReply::lines([
+            r#"{"status":"pulling manifest"}"#,
+            r#"{"status":"downloading","digest":"sha256:mock","total":100,"completed":50}"#,
+            r#"{"status":"downloading","digest":"sha256:mock","total":100,"completed":100}"#,
+            r#"{"status":"success"}"#,
+        ]),
+        ("POST", "/api/embeddings") => {
+            let prompt = request.body.get("prompt").and_then(Value::as_str);
+            json_reply(json!({ "embedding": embedding(prompt.unwrap_or("")) }))
+        }
+        ("DELETE", "/api/delete") => json_reply(json!({})),
+        _ => MockReply::status(404, "not found"),
+    }
+}
+
+async fn write_chunk(socket: &mut TcpStream, line: &str) -> io::Result<()> {
+    let data = format!("{}\n", line);
+    let framed = format!("{:x}\r\n{}\r\n", data.len(), data);
+    socket.write_all(framed.as_bytes()).await?;
+    socket.flush().await
+}
+
+/// Writes `reply`; returns `false` when the script cut the connection short.
+async fn write_reply(
+    socket: &mut TcpStream,
+    request: &MockRequest,
+    reply: MockReply,
+) -> io::Result<bool> {
+    let stream = wants_stream(request);
+    let content_type = if stream {
+        "application/x-ndjson"
+    } else {
+        "application/json"
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n",
+        reply.status,
+        reason(reply.status),
+        content_type
+    );
+    socket.write_all(head.as_bytes()).await?;
+
+    let model = model_of(request);
+    let mut text = String::new();
+    let mut tokens = 0;
+    let mut last_raw = None;
+    let mut done = reply.done;
+    for step in reply.steps {
+        match step {
+            MockStep::Token(token) => {
+                tokens += 1;
+                if stream {
+                    let line = token_line(&request.path, &model, &token, false);
+                    write_chunk(socket, &line.to_string()).await?;
+                }
+                text.push_str(&token);
+            }
+            MockStep::Raw(line) if stream => write_chunk(socket, &line).await?,
+            MockStep::Raw(line) => last_raw = Some(line),
+            MockStep::Error(message) => {
+                write_chunk(socket, &json!({ "error": message }).to_string()).await?;
+                done = false;
+                last_raw = None;
+                break;
+            }
+            MockStep::Delay(duration) => tokio::time::sleep(duration).await,
+            MockStep::Disconnect => return Ok(false),
+            MockStep::Hang => std::future::pending::<()>().await,
+        }
+    }
+    if done {
+        let body_text = if stream { "" } else { text.as_str() };
+        let line = done_line(&request.path, &model, body_text, tokens);
+        write_chunk(socket, &line.to_string()).await?;
+    } else if let Some(line) = last_raw {
+        write_chunk(socket, &line).await?;
+    }
+    socket.write_all(b"0\r\n\r\n").await?;
+    Ok(true)
+}
+
+async fn serve_connection(mut socket: TcpStream, shared: Arc<Shared>) {
+    let Ok(Some(request)) = read_request(&mut socket).await else {
+        return;
+    };
+    shared
+        .requests
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(request.clone());
+    let scripted = shared
+        .scripts
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_mut(&request.path)
+        .and_then(VecDeque::pop_front);
+    let reply = scripted.unwrap_or_else(|| default_reply(&shared.config, &request));
+    if let Ok(true) = write_reply(&mut socket, &request, reply).await {
+        let _ = socket.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn post(url: &str, body: Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(url)
+            .json(&body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_defaults_and_scripts() {
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        let tags: Value = reqwest::get(format!("{}/api/tags", mock.url()))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(tags["models"][0]["name"], "llama3:8b");
+
+        let chat = json!({ "model": "llama3:8b", "stream": false, "messages": [] });
+        let reply: Value = post(&format!("{}/api/chat", mock.url()), chat.clone())
+            .await
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(reply["message"]["content"], "This is a mock reply.");
+        assert_eq!(reply["done"], true);
+
+        mock.push(
+            "/api/chat",
+            MockReply::tokens(["Hel", "lo"]).error_chunk("oom"),
+        );
+        let mut streamed = chat.clone();
+        streamed["stream"] = json!(true);
+        let body = post(&format!("{}/api/chat", mock.url()), streamed)
+            .await
+            .text()
+            .await
+            .unwrap();
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1]["message"]["content"], "lo");
+        assert_eq!(lines[2]["error"], "oom");
+
+        let missing = post(
+            &format!("{}/api/show", mock.url()),
+            json!({ "model": "nope" }),
+        )
+        .await;
+        assert_eq!(missing.status().as_u16(), 404);
+        assert_eq!(mock.requests().len(), 4);
+        assert_eq!(mock.requests()[1].body, chat);
+    }
+
+    #[tokio::test]
+    async fn disconnect_truncates_the_body() {
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        mock.push("/api/generate", MockReply::tokens(["a"]).disconnect());
+        let response = post(
+            &format!("{}/api/generate", mock.url()),
+            json!({ "model": "llama3:8b", "prompt": "hi" }),
+        )
+        .await;
+        assert!(response.text().await.is_err());
+    }
+}
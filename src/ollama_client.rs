@@ -0,0 +1,452 @@
+use std::env;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, timeout, timeout_at, Instant};
+
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+const OLLAMA_URL_ENV: &str = "PROMPTFORGE_OLLAMA_URL";
+const CONNECT_TIMEOUT_ENV: &str = "PROMPTFORGE_OLLAMA_CONNECT_TIMEOUT_MS";
+const FIRST_TOKEN_TIMEOUT_ENV: &str = "PROMPTFORGE_OLLAMA_FIRST_TOKEN_TIMEOUT_MS";
+const IDLE_TIMEOUT_ENV: &str = "PROMPTFORGE_OLLAMA_IDLE_TIMEOUT_MS";
+const REQUEST_TIMEOUT_ENV: &str = "PROMPTFORGE_OLLAMA_REQUEST_TIMEOUT_MS";
+const RETRIES_ENV: &str = "PROMPTFORGE_OLLAMA_RETRIES";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OllamaTimeouts {
+    /// TCP connect to the Ollama server.
+    pub connect: Duration,
+    /// From sending the request until the first streamed chunk (covers model load).
+    pub first_token: Duration,
+    /// Maximum gap between two streamed chunks.
+    pub idle: Duration,
+    /// Whole request for non-streaming calls.
+    pub request: Duration,
+}
+
+impl Default for OllamaTimeouts {
+    fn default() -> Self {
+        // Model loads on CPU-only machines routinely take a minute or more.
+        Self {
+            connect: Duration::from_secs(5),
+            first_token: Duration::from_secs(180),
+            idle: Duration::from_secs(60),
+            request: Duration::from_secs(600),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(4),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OllamaClientConfig {
+    pub base_url: String,
+    pub timeouts: OllamaTimeouts,
+    pub retry: RetryPolicy,
+}
+
+impl Default for OllamaClientConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_OLLAMA_URL.to_string(),
+            timeouts: OllamaTimeouts::default(),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+fn env_millis(key: &str) -> Option<Duration> {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|ms| *ms > 0)
+        .map(Duration::from_millis)
+}
+
+impl OllamaClientConfig {
+    /// Defaults overridden by the `PROMPTFORGE_OLLAMA_*` environment variables.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(url) = env::var(OLLAMA_URL_ENV) {
+            if !url.trim().is_empty() {
+                config.base_url = url.trim().to_string();
+            }
+        }
+        let t = &mut config.timeouts;
+        t.connect = env_millis(CONNECT_TIMEOUT_ENV).unwrap_or(t.connect);
+        t.first_token = env_millis(FIRST_TOKEN_TIMEOUT_ENV).unwrap_or(t.first_token);
+        t.idle = env_millis(IDLE_TIMEOUT_ENV).unwrap_or(t.idle);
+        t.request = env_millis(REQUEST_TIMEOUT_ENV).unwrap_or(t.request);
+        if let Some(retries) = env::var(RETRIES_ENV)
+            .ok()
+            .and_then(|v| v.trim().parse::<u32>().ok())
+        {
+            config.retry.max_retries = retries;
+        }
+        config
+    }
+
+    pub fn endpoint(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    pub fn build_client(&self) -> Result<Client, OllamaError> {
+        Client::builder()
+            .connect_timeout(self.timeouts.connect)
+            .build()
+            .map_err(|err| OllamaError::new(OllamaErrorKind::Network, err.to_string()))
+    }
+}
+
+/// Coarse failure category surfaced to the UI in the `ollama:error` payload.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OllamaErrorKind {
+    /// Connect, first-token or idle timeout elapsed.
+    Timeout,
+    /// The server could not be reached at all.
+    Refused,
+    /// 5xx responses that persisted through every retry.
+    Server,
+    /// Ollama answered but rejected the request or failed while generating.
+    Model,
+    /// The response could not be parsed.
+    Protocol,
+    /// Any other transport failure.
+    Network,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OllamaError {
+    pub kind: OllamaErrorKind,
+    pub message: String,
+}
+
+impl std::fmt::Display for OllamaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for OllamaError {}
+
+impl OllamaError {
+    pub fn new(kind: OllamaErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    fn from_reqwest(err: &reqwest::Error) -> Self {
+        let kind = if err.is_timeout() {
+            OllamaErrorKind::Timeout
+        } else if err.is_connect() {
+            OllamaErrorKind::Refused
+        } else if err.is_decode() {
+            OllamaErrorKind::Protocol
+        } else {
+            OllamaErrorKind::Network
+        };
+        Self::new(kind, err.to_string())
+    }
+
+    fn timed_out(what: &str, limit: Duration) -> Self {
+        Self::new(
+            OllamaErrorKind::Timeout,
+            format!("{} timed out after {} ms", what, limit.as_millis()),
+        )
+    }
+}
+
+/// Ollama sends `{"error": "..."}`; OpenAI-compatible servers nest a `message`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ErrorDetail {
+    Text(String),
+    Object { message: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
+
+async fn error_from_status(response: Response) -> OllamaError {
+    let status = response.status();
+    let kind = if status.is_server_error() {
+        OllamaErrorKind::Server
+    } else {
+        OllamaErrorKind::Model
+    };
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<ErrorBody>(&body)
+        .map(|b| match b.error {
+            ErrorDetail::Text(message) | ErrorDetail::Object { message } => message,
+        })
+        .unwrap_or_else(|_| format!("ollama responded with {}", status));
+    OllamaError::new(kind, message)
+}
+
+/// Sends the request built by `build`, retrying refused connections and 5xx
+/// responses with exponential backoff. Nothing has been streamed to the caller
+/// yet at this point, so retrying is always safe.
+pub async fn send_with_retry<B>(
+    client: &Client,
+    config: &OllamaClientConfig,
+    build: B,
+    deadline: Instant,
+    what: &str,
+) -> Result<Response, OllamaError>
+where
+    B: Fn(&Client) -> RequestBuilder,
+{
+    let limit = deadline.saturating_duration_since(Instant::now());
+    let mut attempt = 0;
+    loop {
+        let sent = timeout_at(deadline, build(client).send())
+            .await
+            .map_err(|_| OllamaError::timed_out(what, limit))?;
+        let err = match sent {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) => {
+                let err = error_from_status(response).await;
+                if err.kind != OllamaErrorKind::Server {
+                    return Err(err);
+                }
+                err
+            }
+            Err(err) => {
+                let err = OllamaError::from_reqwest(&err);
+                if err.kind != OllamaErrorKind::Refused {
+                    return Err(err);
+                }
+                err
+            }
+        };
+        if attempt >= config.retry.max_retries {
+            return Err(err);
+        }
+        sleep(config.retry.backoff(attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// Non-streaming request bounded by `timeouts.request`; returns the body text.
+pub async fn request_text<B>(
+    client: &Client,
+    config: &OllamaClientConfig,
+    build: B,
+) -> Result<String, OllamaError>
+where
+    B: Fn(&Client) -> RequestBuilder,
+{
+    let limit = config.timeouts.request;
+    let deadline = Instant::now() + limit;
+    let response = send_with_retry(client, config, build, deadline, "request").await?;
+    timeout_at(deadline, response.text())
+        .await
+        .map_err(|_| OllamaError::timed_out("request", limit))?
+        .map_err(|err| OllamaError::from_reqwest(&err))
+}
+
+/// Streams an NDJSON response line by line. `on_line` returns `Ok(true)` once
+/// the stream is complete. The first chunk must arrive within
+/// `timeouts.first_token` of sending, later chunks within `timeouts.idle` of
+/// each other.
+pub async fn stream_ndjson<B, F>(
+    client: &Client,
+    config: &OllamaClientConfig,
+    build: B,
+    mut on_line: F,
+) -> Result<(), OllamaError>
+where
+    B: Fn(&Client) -> RequestBuilder,
+    F: FnMut(&str) -> Result<bool, OllamaError>,
+{
+    let timeouts = config.timeouts;
+    let first_deadline = Instant::now() + timeouts.first_token;
+    let response = send_with_retry(client, config, build, first_deadline, "first token").await?;
+    let mut stream = response.bytes_stream();
+    // Buffer raw bytes so multi-byte characters split across chunks stay intact.
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut received_any = false;
+
+    loop {
+        let next = if received_any {
+            timeout(timeouts.idle, stream.next())
+                .await
+                .map_err(|_| OllamaError::timed_out("idle stream", timeouts.idle))?
+        } else {
+            timeout_at(first_deadline, stream.next())
+                .await
+                .map_err(|_| OllamaError::timed_out("first token", timeouts.first_token))?
+        };
+        let Some(item) = next else {
+            break;
+        };
+        let bytes = item.map_err(|err| OllamaError::from_reqwest(&err))?;
+        if bytes.is_empty() {
+            continue;
+        }
+        received_any = true;
+        buffer.extend_from_slice(&bytes);
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let raw: Vec<u8> = buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.trim().is_empty() {
+                continue;
+            }
+            if on_line(line)? {
+                return Ok(());
+            }
+        }
+    }
+
+    let rest = String::from_utf8_lossy(&buffer);
+    if !rest.trim().is_empty() {
+        on_line(rest.trim_end())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ollama::{MockConfig, MockOllama, MockReply};
+
+    fn fast_config(base_url: String) -> OllamaClientConfig {
+        OllamaClientConfig {
+            base_url,
+            timeouts: OllamaTimeouts {
+                connect: Duration::from_millis(200),
+                first_token: Duration::from_millis(300),
+                idle: Duration::from_millis(300),
+                request: Duration::from_millis(500),
+            },
+            retry: RetryPolicy {
+                max_retries: 2,
+                initial_backoff: Duration::from_millis(10),
+                max_backoff: Duration::from_millis(20),
+            },
+        }
+    }
+
+    /// A mock serving `replies` to `/api/chat`, in order.
+    async fn scripted_mock(replies: Vec<MockReply>) -> MockOllama {
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        for reply in replies {
+            mock.push("/api/chat", reply);
+        }
+        mock
+    }
+
+    /// A refused connection needs a port nothing listens on, not the mock.
+    fn unused_port_url() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        format!("http://127.0.0.1:{}", port)
+    }
+
+    async fn collect_stream(config: &OllamaClientConfig) -> Result<Vec<String>, OllamaError> {
+        let client = config.build_client()?;
+        let url = config.endpoint("/api/chat");
+        let mut lines = Vec::new();
+        stream_ndjson(
+            &client,
+            config,
+            |c| c.post(&url),
+            |line| {
+                lines.push(line.to_string());
+                Ok(line.contains("\"done\":true"))
+            },
+        )
+        .await?;
+        Ok(lines)
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_before_streaming() {
+        let mock = scripted_mock(vec![
+            MockReply::status(503, "loading"),
+            MockReply::lines(["{\"response\":\"a\"}", "{\"done\":true}"]),
+        ])
+        .await;
+        let lines = collect_stream(&fast_config(mock.url()))
+            .await
+            .expect("stream");
+        assert_eq!(lines, vec!["{\"response\":\"a\"}", "{\"done\":true}"]);
+        assert_eq!(mock.requests_to("/api/chat").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn classifies_failures() {
+        let refused = collect_stream(&fast_config(unused_port_url()))
+            .await
+            .expect_err("refused");
+        assert_eq!(refused.kind, OllamaErrorKind::Refused);
+
+        let mock = scripted_mock(vec![MockReply::status(404, "model 'nope' not found")]).await;
+        let missing = collect_stream(&fast_config(mock.url()))
+            .await
+            .expect_err("404");
+        assert_eq!(missing.kind, OllamaErrorKind::Model);
+        assert_eq!(missing.message, "model 'nope' not found");
+
+        let mock = scripted_mock(vec![MockReply::status(500, "boom"); 3]).await;
+        let server = collect_stream(&fast_config(mock.url()))
+            .await
+            .expect_err("5xx");
+        assert_eq!(server.kind, OllamaErrorKind::Server);
+        assert_eq!(mock.requests_to("/api/chat").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn first_token_watchdog_fires() {
+        let mock = scripted_mock(vec![MockReply::lines(Vec::<String>::new()).hang()]).await;
+        let err = collect_stream(&fast_config(mock.url()))
+            .await
+            .expect_err("timeout");
+        assert_eq!(err.kind, OllamaErrorKind::Timeout);
+        assert!(err.message.contains("first token"), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn idle_timeout_fires_after_first_chunk() {
+        let mock = scripted_mock(vec![MockReply::lines(["{\"response\":\"a\"}"]).hang()]).await;
+        let err = collect_stream(&fast_config(mock.url()))
+            .await
+            .expect_err("idle");
+        assert_eq!(err.kind, OllamaErrorKind::Timeout);
+        assert!(err.message.contains("idle"), "{}", err.message);
+    }
+}
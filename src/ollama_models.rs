@@ -0,0 +1,435 @@
+use std::sync::{Arc, Mutex as StdMutex, PoisonError};
+
+use futures_util::future::{AbortHandle, Abortable};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::time::Instant;
+
+use crate::ollama_client::{
+    request_text, send_with_retry, stream_ndjson, OllamaClientConfig, OllamaError, OllamaErrorKind,
+};
+use crate::ollama_stream::{
+    emit, EventSink, StreamErrorPayload, StreamKind, StreamOutcome, StreamState,
+};
+use crate::runs::RunStatus;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelDetailsSummary {
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub family: Option<String>,
+    #[serde(default)]
+    pub parameter_size: Option<String>,
+    #[serde(default)]
+    pub quantization_level: Option<String>,
+}
+
+/// One entry of `/api/tags`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelSummary {
+    pub name: String,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub modified_at: Option<String>,
+    #[serde(default)]
+    pub details: ModelDetailsSummary,
+}
+
+/// One entry of `/api/ps`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunningModel {
+    pub name: String,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub size_vram: Option<u64>,
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    #[serde(default)]
+    pub details: ModelDetailsSummary,
+}
+
+/// The parts of `/api/show` the UI cares about.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelDetails {
+    pub model: String,
+    pub parameters: Option<String>,
+    pub template: Option<String>,
+    pub context_length: Option<u64>,
+    pub quantization: Option<String>,
+    pub family: Option<String>,
+    pub parameter_size: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+    #[serde(default, skip_serializing)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PullProgressPayload {
+    pub stream_id: usize,
+    pub model: String,
+    #[serde(flatten)]
+    pub progress: PullProgress,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PullEndPayload {
+    pub stream_id: usize,
+    pub model: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsBody {
+    #[serde(default)]
+    models: Vec<ModelSummary>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PsBody {
+    #[serde(default)]
+    models: Vec<RunningModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShowBody {
+    #[serde(default)]
+    parameters: Option<String>,
+    #[serde(default)]
+    template: Option<String>,
+    #[serde(default)]
+    details: ModelDetailsSummary,
+    #[serde(default)]
+    model_info: serde_json::Map<String, Value>,
+}
+
+fn parse_json<T: for<'de> Deserialize<'de>>(body: &str) -> Result<T, OllamaError> {
+    serde_json::from_str(body)
+        .map_err(|err| OllamaError::new(OllamaErrorKind::Protocol, err.to_string()))
+}
+
+/// `model_info` keys are prefixed with the architecture, e.g. `llama.context_length`.
+fn context_length(model_info: &serde_json::Map<String, Value>) -> Option<u64> {
+    model_info
+        .iter()
+        .find(|(key, _)| key.ends_with(".context_length"))
+        .and_then(|(_, value)| value.as_u64())
+}
+
+pub async fn list_models(
+    client: &Client,
+    config: &OllamaClientConfig,
+) -> Result<Vec<ModelSummary>, OllamaError> {
+    let url = config.endpoint("/api/tags");
+    let body = request_text(client, config, |c| c.get(&url)).await?;
+    Ok(parse_json::<TagsBody>(&body)?.models)
+}
+
+pub async fn list_running_models(
+    client: &Client,
+    config: &OllamaClientConfig,
+) -> Result<Vec<RunningModel>, OllamaError> {
+    let url = config.endpoint("/api/ps");
+    let body = request_text(client, config, |c| c.get(&url)).await?;
+    Ok(parse_json::<PsBody>(&body)?.models)
+}
+
+pub async fn show_model(
+    client: &Client,
+    config: &OllamaClientConfig,
+    model: &str,
+) -> Result<ModelDetails, OllamaError> {
+    let url = config.endpoint("/api/show");
+    let body = request_text(client, config, |c| {
+        c.post(&url).json(&json!({ "model": model }))
+    })
+    .await?;
+    let show: ShowBody = parse_json(&body)?;
+    Ok(ModelDetails {
+        model: model.to_string(),
+        parameters: show.parameters,
+        template: show.template,
+        context_length: context_length(&show.model_info),
+        quantization: show.details.quantization_level,
+        family: show.details.family,
+        parameter_size: show.details.parameter_size,
+    })
+}
+
+pub async fn delete_model(
+    client: &Client,
+    config: &OllamaClientConfig,
+    model: &str,
+) -> Result<(), OllamaError> {
+    let url = config.endpoint("/api/delete");
+    // Older servers only understand `name`, newer ones prefer `model`.
+    let body = json!({ "model": model, "name": model });
+    let deadline = Instant::now() + config.timeouts.request;
+    send_with_retry(
+        client,
+        config,
+        |c| c.delete(&url).json(&body),
+        deadline,
+        "delete",
+    )
+    .await?;
+    Ok(())
+}
+
+/// Streams `/api/pull`, handing every progress line to `on_progress`.
+pub async fn pull_model_stream<F>(
+    client: &Client,
+    config: &OllamaClientConfig,
+    model: &str,
+    mut on_progress: F,
+) -> Result<(), OllamaError>
+where
+    F: FnMut(PullProgress),
+{
+    let url = config.endpoint("/api/pull");
+    let body = json!({ "model": model, "stream": true });
+    stream_ndjson(
+        client,
+        config,
+        |c| c.post(&url).json(&body),
+        |line| {
+            let progress: PullProgress = parse_json(line)?;
+            if let Some(error) = progress.error {
+                return Err(OllamaError::new(OllamaErrorKind::Model, error));
+            }
+            let finished = progress.status == "success";
+            on_progress(progress);
+            Ok(finished)
+        },
+    )
+    .await
+}
+
+fn client_for(config: &OllamaClientConfig) -> Result<Client, String> {
+    config.build_client().map_err(|e| e.to_string())
+}
+
+pub async fn list_ollama_models() -> Result<Vec<ModelSummary>, String> {
+    let config = OllamaClientConfig::from_env();
+    list_models(&client_for(&config)?, &config)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+pub async fn list_running_ollama_models() -> Result<Vec<RunningModel>, String> {
+    let config = OllamaClientConfig::from_env();
+    list_running_models(&client_for(&config)?, &config)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+pub async fn show_ollama_model(model: String) -> Result<ModelDetails, String> {
+    let config = OllamaClientConfig::from_env();
+    show_model(&client_for(&config)?, &config, &model)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+pub async fn delete_ollama_model(model: String) -> Result<(), String> {
+    let config = OllamaClientConfig::from_env();
+    delete_model(&client_for(&config)?, &config, &model)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Starts a pull in the stream registry so it can be listed and cancelled with
+/// `abort_stream` like a chat stream. Progress arrives as `ollama:pull_progress`.
+pub async fn start_model_pull(
+    sink: Arc<dyn EventSink>,
+    state: &StreamState,
+    model: String,
+) -> Result<usize, String> {
+    let (handle, registration) = AbortHandle::new_pair();
+    let (stream_id, outcome_tx) = state
+        .register(handle, StreamKind::Pull, &model)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let state_for_cleanup = state.clone();
+    let error = Arc::new(StdMutex::new(None::<OllamaError>));
+    let error_for_task = error.clone();
+
+    let task = async move {
+        let config = OllamaClientConfig::from_env();
+        let result = async {
+            let client = config.build_client()?;
+            pull_model_stream(&client, &config, &model, |progress| {
+                emit(
+                    sink.as_ref(),
+                    "ollama:pull_progress",
+                    PullProgressPayload {
+                        stream_id,
+                        model: model.clone(),
+                        progress,
+                    },
+                );
+            })
+            .await
+        }
+        .await;
+        match result {
+            Ok(()) => {
+                emit(
+                    sink.as_ref(),
+                    "ollama:pull_end",
+                    PullEndPayload { stream_id, model },
+                );
+            }
+            Err(err) => {
+                *error_for_task
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner) = Some(err.clone());
+                emit(
+                    sink.as_ref(),
+                    "ollama:pull_error",
+                    StreamErrorPayload::new(stream_id, err),
+                );
+            }
+        }
+    };
+
+    let abortable = Abortable::new(task, registration);
+    tokio::spawn(async move {
+        let aborted = abortable.await.is_err();
+        state_for_cleanup.remove(stream_id).await;
+        let failed = error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some();
+        let status = match (aborted, failed) {
+            (true, _) => RunStatus::Aborted,
+            (false, true) => RunStatus::Failed,
+            (false, false) => RunStatus::Completed,
+        };
+        let _ = outcome_tx.send(StreamOutcome {
+            stream_id,
+            status,
+            run_id: None,
+            partial_text: String::new(),
+            save_error: None,
+            structured: None,
+        });
+    });
+
+    Ok(stream_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ollama::{MockConfig, MockOllama, MockReply};
+
+    async fn start_mock() -> (MockOllama, Client, OllamaClientConfig) {
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        let mut config = OllamaClientConfig {
+            base_url: mock.url(),
+            ..OllamaClientConfig::default()
+        };
+        config.retry.max_retries = 0;
+        (mock, config.build_client().unwrap(), config)
+    }
+
+    /// Method of the one request the mock received for `path`.
+    fn method_of(mock: &MockOllama, path: &str) -> String {
+        let requests = mock.requests_to(path);
+        assert_eq!(requests.len(), 1, "{:?}", requests);
+        requests[0].method.clone()
+    }
+
+    #[tokio::test]
+    async fn show_extracts_context_length_and_quantization() {
+        let (mock, client, config) = start_mock().await;
+        let details = show_model(&client, &config, "llama3:8b")
+            .await
+            .expect("show");
+
+        assert_eq!(method_of(&mock, "/api/show"), "POST");
+        assert_eq!(details.context_length, Some(8192));
+        assert_eq!(details.quantization.as_deref(), Some("Q4_0"));
+        assert_eq!(details.template.as_deref(), Some("{{ .Prompt }}"));
+    }
+
+    #[tokio::test]
+    async fn lists_installed_and_running_models() {
+        let (mock, client, config) = start_mock().await;
+        let models = list_models(&client, &config).await.expect("tags");
+        assert_eq!(method_of(&mock, "/api/tags"), "GET");
+        assert_eq!(models[0].name, "llama3:8b");
+        assert_eq!(
+            models[0].details.quantization_level.as_deref(),
+            Some("Q4_0")
+        );
+
+        mock.push(
+            "/api/ps",
+            MockReply::lines([json!({"models": [{"name": "llama3:8b", "size_vram": 0,
+                "expires_at": "2025-01-01T00:05:00Z"}]})
+            .to_string()]),
+        );
+        let running = list_running_models(&client, &config).await.expect("ps");
+        assert_eq!(method_of(&mock, "/api/ps"), "GET");
+        assert_eq!(running[0].size_vram, Some(0));
+    }
+
+    #[tokio::test]
+    async fn delete_reports_missing_model() {
+        let (mock, client, config) = start_mock().await;
+        mock.push("/api/delete", MockReply::status(404, "model 'x' not found"));
+        let err = delete_model(&client, &config, "x")
+            .await
+            .expect_err("missing");
+        assert_eq!(method_of(&mock, "/api/delete"), "DELETE");
+        assert_eq!(err.kind, OllamaErrorKind::Model);
+        assert_eq!(err.message, "model 'x' not found");
+    }
+
+    #[tokio::test]
+    async fn pull_streams_progress_until_success() {
+        let (mock, client, config) = start_mock().await;
+        mock.push(
+            "/api/pull",
+            MockReply::lines([
+                r#"{"status":"pulling manifest"}"#,
+                r#"{"status":"downloading","digest":"sha256:abc","total":100,"completed":40}"#,
+                r#"{"status":"success"}"#,
+            ]),
+        );
+        let mut seen = Vec::new();
+        pull_model_stream(&client, &config, "llama3:8b", |p| seen.push(p))
+            .await
+            .expect("pull");
+        assert_eq!(method_of(&mock, "/api/pull"), "POST");
+        let statuses: Vec<&str> = seen.iter().map(|p| p.status.as_str()).collect();
+        assert_eq!(statuses, vec!["pulling manifest", "downloading", "success"]);
+        assert_eq!(seen[1].completed, Some(40));
+
+        mock.push(
+            "/api/pull",
+            MockReply::lines([r#"{"error":"pull model manifest: file does not exist"}"#]),
+        );
+        let err = pull_model_stream(&client, &config, "nope", |_| {})
+            .await
+            .expect_err("pull error");
+        assert_eq!(err.kind, OllamaErrorKind::Model);
+    }
+}
@@ -1,51 +1,312 @@
+use std::collections::BTreeMap;
+use std::env;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use futures_util::future::{AbortHandle, Abortable};
-use serde::Deserialize;
-use tokio::sync::Mutex;
-#[derive(Clone, Default)]
+use chrono::Local;
+use futures_util::future::AbortHandle;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+use crate::ollama_client::{OllamaError, OllamaErrorKind};
+use crate::runs::RunStatus;
+use crate::structured_output::StructuredOutput;
+
+pub const DEFAULT_MAX_CONCURRENT_STREAMS: usize = 4;
+const MAX_STREAMS_ENV: &str = "PROMPTFORGE_MAX_STREAMS";
+
+fn max_streams_from_env() -> usize {
+    env::var(MAX_STREAMS_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|limit| *limit > 0)
+        .unwrap_or(DEFAULT_MAX_CONCURRENT_STREAMS)
+}
+
+/// Registry of in-flight streams keyed by stream id.
+#[derive(Clone)]
 pub struct StreamState {
-    inner: Arc<Mutex<Option<TrackedHandle>>>,
+    inner: Arc<Mutex<BTreeMap<usize, TrackedHandle>>>,
     counter: Arc<AtomicUsize>,
+    limit: Arc<AtomicUsize>,
 }
 struct TrackedHandle {
-    id: usize,
     handle: AbortHandle,
+    info: StreamInfo,
+    outcome: oneshot::Receiver<StreamOutcome>,
+}
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamKind {
+    Chat,
+    Pull,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StreamInfo {
+    pub id: usize,
+    pub kind: StreamKind,
+    pub model: String,
+    pub started_at: String,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamLimitReached {
+    pub limit: usize,
+}
+impl std::fmt::Display for StreamLimitReached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "too many concurrent streams (limit {})", self.limit)
+    }
+}
+impl std::error::Error for StreamLimitReached {}
+impl Default for StreamState {
+    fn default() -> Self {
+        Self::with_limit(max_streams_from_env())
+    }
 }
 impl StreamState {
-    pub async fn register(&self, handle: AbortHandle) -> (usize, Option<AbortHandle>) {
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(BTreeMap::new())),
+            counter: Arc::new(AtomicUsize::new(0)),
+            limit: Arc::new(AtomicUsize::new(limit.max(1))),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::SeqCst)
+    }
+
+    /// Changes the limit for new chat streams; streams already running are left
+    /// alone.
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit.max(1), Ordering::SeqCst);
+    }
+
+    /// Registers a new stream. The returned sender reports how the stream ended
+    /// to whoever aborts it. Only chat streams count against the limit; a model
+    /// pull must not keep a chat from starting.
+    pub async fn register(
+        &self,
+        handle: AbortHandle,
+        kind: StreamKind,
+        model: &str,
+    ) -> Result<(usize, oneshot::Sender<StreamOutcome>), StreamLimitReached> {
         let mut guard = self.inner.lock().await;
+        let limit = self.limit();
+        let chats = guard
+            .values()
+            .filter(|tracked| tracked.info.kind == StreamKind::Chat)
+            .count();
+        if kind == StreamKind::Chat && chats >= limit {
+            return Err(StreamLimitReached { limit });
+        }
         let id = self.counter.fetch_add(1, Ordering::SeqCst) + 1;
-        let previous = guard.replace(TrackedHandle { id, handle });
-        (id, previous.map(|tracked| tracked.handle))
+        let info = StreamInfo {
+            id,
+            kind,
+            model: model.to_string(),
+            started_at: Local::now().to_rfc3339(),
+        };
+        let (sender, outcome) = oneshot::channel();
+        guard.insert(
+            id,
+            TrackedHandle {
+                handle,
+                info,
+                outcome,
+            },
+        );
+        Ok((id, sender))
+    }
+
+    /// Aborts the stream with the given id and returns a receiver for its
+    /// outcome, or `None` if it is not running.
+    pub async fn abort(&self, id: usize) -> Option<oneshot::Receiver<StreamOutcome>> {
+        let mut guard = self.inner.lock().await;
+        guard.remove(&id).map(|tracked| {
+            tracked.handle.abort();
+            tracked.outcome
+        })
     }
 
-    pub async fn take(&self) -> Option<AbortHandle> {
+    /// Aborts the most recently started stream of `kind`, so stopping a chat
+    /// never cancels a pull started after it.
+    pub async fn abort_latest(&self, kind: StreamKind) -> Option<oneshot::Receiver<StreamOutcome>> {
         let mut guard = self.inner.lock().await;
-        guard.take().map(|tracked| tracked.handle)
+        let id = guard
+            .iter()
+            .rev()
+            .find(|(_, tracked)| tracked.info.kind == kind)
+            .map(|(id, _)| *id)?;
+        guard.remove(&id).map(|tracked| {
+            tracked.handle.abort();
+            tracked.outcome
+        })
+    }
+
+    pub async fn list(&self) -> Vec<StreamInfo> {
+        let guard = self.inner.lock().await;
+        guard.values().map(|tracked| tracked.info.clone()).collect()
     }
 
-    pub async fn clear_if(&self, id: usize) {
+    pub async fn remove(&self, id: usize) {
         let mut guard = self.inner.lock().await;
-        if guard.as_ref().map(|tracked| tracked.id) == Some(id) {
-            guard.take();
+        guard.remove(&id);
+    }
+}
+/// Receives stream events: the Tauri window in the app, a channel in tests.
+pub trait EventSink: Send + Sync + 'static {
+    fn emit_event(&self, event: &str, payload: serde_json::Value);
+}
+/// Forwards events to a channel; used by the CLI and tests.
+pub struct ChannelSink(pub mpsc::UnboundedSender<(String, serde_json::Value)>);
+impl EventSink for ChannelSink {
+    fn emit_event(&self, event: &str, payload: serde_json::Value) {
+        let _ = self.0.send((event.to_string(), payload));
+    }
+}
+pub fn emit<P: Serialize>(sink: &dyn EventSink, event: &str, payload: P) {
+    if let Ok(payload) = serde_json::to_value(payload) {
+        sink.emit_event(event, payload);
+    }
+}
+/// How a stream ended and where its run record was written. Emitted as
+/// `ollama:saved` and returned from the abort commands.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StreamOutcome {
+    pub stream_id: usize,
+    pub status: RunStatus,
+    pub run_id: Option<String>,
+    pub partial_text: String,
+    pub save_error: Option<String>,
+    pub structured: Option<StructuredOutput>,
+}
+/// Everything received on a stream so far; survives abort because the stream
+/// task only holds a shared reference to it.
+#[derive(Debug, Clone, Default)]
+pub struct StreamCapture {
+    pub text: String,
+    pub raw: String,
+    pub stats: Option<GenerationStats>,
+    pub error: Option<OllamaError>,
+    /// Set once a schema-constrained reply has been validated.
+    pub structured: Option<StructuredOutput>,
+}
+impl StreamCapture {
+    pub fn status(&self) -> RunStatus {
+        if self.error.is_some() {
+            RunStatus::Failed
+        } else if self.structured.as_ref().is_some_and(|s| !s.is_valid()) {
+            RunStatus::SchemaInvalid
+        } else {
+            RunStatus::Completed
+        }
+    }
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamChunkPayload {
+    pub stream_id: usize,
+    pub text: String,
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamEndPayload {
+    pub stream_id: usize,
+    pub stats: GenerationStats,
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamErrorPayload {
+    pub stream_id: usize,
+    pub kind: OllamaErrorKind,
+    pub message: String,
+}
+impl StreamErrorPayload {
+    pub fn new(stream_id: usize, error: OllamaError) -> Self {
+        Self {
+            stream_id,
+            kind: error.kind,
+            message: error.message,
         }
     }
 }
+/// Counters reported on the final Ollama chunk (durations in nanoseconds), plus
+/// throughput figures derived from them and measured by the stream task.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationStats {
+    #[serde(default)]
+    pub total_duration: Option<u64>,
+    #[serde(default)]
+    pub load_duration: Option<u64>,
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    pub prompt_eval_duration: Option<u64>,
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+    #[serde(default)]
+    pub eval_duration: Option<u64>,
+    #[serde(default)]
+    pub tokens_per_second: Option<f64>,
+    #[serde(default)]
+    pub time_to_first_token_ms: Option<u64>,
+}
+impl GenerationStats {
+    fn with_throughput(mut self) -> Self {
+        self.tokens_per_second = match (self.eval_count, self.eval_duration) {
+            (Some(count), Some(duration)) if duration > 0 => {
+                Some(count as f64 * 1_000_000_000.0 / duration as f64)
+            }
+            _ => None,
+        };
+        self
+    }
+}
+/// Measures time-to-first-token from the moment the request is sent.
+pub struct FirstTokenTimer {
+    started: Instant,
+    first_token: Option<Duration>,
+}
+impl FirstTokenTimer {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            first_token: None,
+        }
+    }
+
+    pub fn mark(&mut self) {
+        if self.first_token.is_none() {
+            self.first_token = Some(self.started.elapsed());
+        }
+    }
+
+    pub fn first_token_ms(&self) -> Option<u64> {
+        self.first_token.map(|d| d.as_millis() as u64)
+    }
+}
 #[derive(Debug, Deserialize)]
 struct OllamaChunk {
     #[serde(default)]
     response: String,
     #[serde(default)]
+    message: Option<OllamaChunkMessage>,
+    #[serde(default)]
     done: bool,
     #[serde(default)]
     error: Option<String>,
+    #[serde(flatten)]
+    stats: GenerationStats,
 }
-#[derive(Debug, PartialEq, Eq)]
+/// `/api/chat` streams `message.content` where `/api/generate` streams `response`.
+#[derive(Debug, Deserialize)]
+struct OllamaChunkMessage {
+    #[serde(default)]
+    content: String,
+}
+#[derive(Debug, PartialEq)]
 pub enum OllamaEvent {
     Chunk(String),
-    Done,
+    Done(GenerationStats),
     Error(String),
 }
 pub fn parse_ollama_jsonl_chunk(line: &str) -> Result<Vec<OllamaEvent>, serde_json::Error> {
@@ -54,26 +315,27 @@ pub fn parse_ollama_jsonl_chunk(line: &str) -> Result<Vec<OllamaEvent>, serde_js
         return Ok(vec![OllamaEvent::Error(err)]);
     }
     let mut events = Vec::new();
-    if !chunk.response.is_empty() {
-        events.push(OllamaEvent::Chunk(chunk.response));
+    let text = match chunk.message {
+        Some(message) if chunk.response.is_empty() => message.content,
+        _ => chunk.response,
+    };
+    if !text.is_empty() {
+        events.push(OllamaEvent::Chunk(text));
     }
     if chunk.done {
-        events.push(OllamaEvent::Done);
+        events.push(OllamaEvent::Done(chunk.stats.with_throughput()));
     }
     Ok(events)
 }
 #[cfg(test)]
 mod tests {
     use super::*;
-    use futures_util::future::Aborted;
+    use futures_util::future::{AbortRegistration, Abortable, Aborted};
     use std::time::Duration;
+    use tokio::task::JoinHandle;
 
-    #[tokio::test]
-    async fn abort_resets_state() {
-        let state = StreamState::default();
-        let (handle, registration) = AbortHandle::new_pair();
-        assert!(state.register(handle).await.1.is_none());
-        let join = tokio::spawn(async move {
+    fn spawn_sleeper(registration: AbortRegistration) -> JoinHandle<Result<(), Aborted>> {
+        tokio::spawn(async move {
             Abortable::new(
                 async move {
                     tokio::time::sleep(Duration::from_secs(30)).await;
@@ -81,31 +343,96 @@ mod tests {
                 registration,
             )
             .await
-        });
-        let handle = state.take().await.expect("handle stored");
-        handle.abort();
-        assert!(state.take().await.is_none());
+        })
+    }
+
+    #[tokio::test]
+    async fn abort_resets_state() {
+        let state = StreamState::with_limit(2);
+        let (handle, registration) = AbortHandle::new_pair();
+        let (id, _outcome) = state
+            .register(handle, StreamKind::Chat, "llama3")
+            .await
+            .expect("registered");
+        let join = spawn_sleeper(registration);
+        assert!(state.abort(id).await.is_some());
+        assert!(state.abort(id).await.is_none());
+        assert!(state.list().await.is_empty());
         assert!(matches!(join.await.unwrap(), Err(Aborted)));
     }
 
     #[tokio::test]
-    async fn replacing_handle_aborts_previous() {
-        let state = StreamState::default();
+    async fn concurrent_streams_are_tracked_independently() {
+        let state = StreamState::with_limit(2);
         let (handle1, registration1) = AbortHandle::new_pair();
-        assert!(state.register(handle1).await.1.is_none());
-        let join = tokio::spawn(async move {
-            Abortable::new(
-                async move {
-                    tokio::time::sleep(Duration::from_secs(30)).await;
-                },
-                registration1,
-            )
+        let (handle2, registration2) = AbortHandle::new_pair();
+        let (id1, _outcome1) = state
+            .register(handle1, StreamKind::Chat, "llama3")
+            .await
+            .expect("first");
+        let (id2, outcome2) = state
+            .register(handle2, StreamKind::Pull, "phi3")
+            .await
+            .expect("second");
+        assert_ne!(id1, id2);
+        let join1 = spawn_sleeper(registration1);
+        let join2 = spawn_sleeper(registration2);
+
+        let models: Vec<String> = state.list().await.into_iter().map(|s| s.model).collect();
+        assert_eq!(models, vec!["llama3".to_string(), "phi3".to_string()]);
+
+        let receiver = state
+            .abort_latest(StreamKind::Pull)
+            .await
+            .expect("latest pull");
+        assert!(matches!(join2.await.unwrap(), Err(Aborted)));
+        assert!(!join1.is_finished());
+        let outcome = StreamOutcome {
+            stream_id: id2,
+            status: RunStatus::Aborted,
+            run_id: Some("run".into()),
+            partial_text: "Hel".into(),
+            save_error: None,
+            structured: None,
+        };
+        outcome2.send(outcome.clone()).unwrap();
+        assert_eq!(receiver.await.unwrap(), outcome);
+        assert!(state.abort(id1).await.is_some());
+        assert!(matches!(join1.await.unwrap(), Err(Aborted)));
+    }
+
+    #[tokio::test]
+    async fn limit_rejects_extra_streams() {
+        let state = StreamState::with_limit(1);
+        let (handle1, _registration1) = AbortHandle::new_pair();
+        let (id, _outcome) = state
+            .register(handle1, StreamKind::Chat, "llama3")
             .await
-        });
+            .expect("first");
         let (handle2, _registration2) = AbortHandle::new_pair();
-        let prev = state.register(handle2).await.1.expect("previous handle");
-        prev.abort();
-        assert!(matches!(join.await.unwrap(), Err(Aborted)));
+        assert_eq!(
+            state
+                .register(handle2.clone(), StreamKind::Chat, "llama3")
+                .await
+                .err(),
+            Some(StreamLimitReached { limit: 1 })
+        );
+        state.remove(id).await;
+        let (chat, _outcome) = state
+            .register(handle2, StreamKind::Chat, "llama3")
+            .await
+            .expect("slot freed");
+
+        // Pulls neither take a chat slot nor get stopped with the chat.
+        let (handle3, _registration3) = AbortHandle::new_pair();
+        let (pull, _outcome) = state
+            .register(handle3, StreamKind::Pull, "phi3")
+            .await
+            .expect("pull beside a chat");
+        assert!(state.abort_latest(StreamKind::Chat).await.is_some());
+        let running: Vec<usize> = state.list().await.into_iter().map(|s| s.id).collect();
+        assert_eq!(running, [pull]);
+        assert_ne!(chat, pull);
     }
 
     #[test]
@@ -115,17 +442,42 @@ mod tests {
                 r#"{"response":"Hel","done":false}"#,
                 vec![OllamaEvent::Chunk("Hel".into())],
             ),
-            (r#"{"response":"","done":true}"#, vec![OllamaEvent::Done]),
+            (
+                r#"{"response":"","done":true}"#,
+                vec![OllamaEvent::Done(GenerationStats::default())],
+            ),
             (
                 r#"{"error":"boom"}"#,
                 vec![OllamaEvent::Error("boom".into())],
             ),
             (
                 r#"{"response":"Hi","done":true}"#,
-                vec![OllamaEvent::Chunk("Hi".into()), OllamaEvent::Done],
+                vec![
+                    OllamaEvent::Chunk("Hi".into()),
+                    OllamaEvent::Done(GenerationStats::default()),
+                ],
+            ),
+            (
+                r#"{"message":{"role":"assistant","content":"Yo"},"done":false}"#,
+                vec![OllamaEvent::Chunk("Yo".into())],
             ),
         ] {
             assert_eq!(parse_ollama_jsonl_chunk(input).unwrap(), expected);
         }
     }
+
+    #[test]
+    fn final_chunk_carries_stats() {
+        let line = r#"{"message":{"role":"assistant","content":""},"done":true,"total_duration":5000000000,"load_duration":1000000000,"prompt_eval_count":26,"prompt_eval_duration":500000000,"eval_count":40,"eval_duration":2000000000}"#;
+        let events = parse_ollama_jsonl_chunk(line).unwrap();
+        let [OllamaEvent::Done(stats)] = events.as_slice() else {
+            panic!("expected a single done event, got {:?}", events);
+        };
+        assert_eq!(stats.total_duration, Some(5_000_000_000));
+        assert_eq!(stats.load_duration, Some(1_000_000_000));
+        assert_eq!(stats.prompt_eval_count, Some(26));
+        assert_eq!(stats.eval_count, Some(40));
+        assert_eq!(stats.tokens_per_second, Some(20.0));
+        assert_eq!(stats.time_to_first_token_ms, None);
+    }
 }
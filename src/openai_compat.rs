@@ -0,0 +1,441 @@
+use std::env;
+use std::mem;
+
+use futures_util::future::BoxFuture;
+use reqwest::RequestBuilder;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::attachments::sniff_mime;
+use crate::llm_backend::{
+    protocol_error, BackendKind, ChatMessage, ChatRequest, LineSink, LlmBackend,
+};
+use crate::ollama_client::{request_text, stream_ndjson, OllamaClientConfig, OllamaError};
+use crate::ollama_stream::{GenerationStats, OllamaEvent};
+
+/// llama.cpp `server` listens here by default.
+pub const DEFAULT_OPENAI_URL: &str = "http://127.0.0.1:8080";
+
+const OPENAI_URL_ENV: &str = "PROMPTFORGE_OPENAI_URL";
+const OPENAI_API_KEY_ENV: &str = "PROMPTFORGE_OPENAI_API_KEY";
+
+/// Ollama option names and their `/v1/chat/completions` counterparts.
+const OPTION_MAP: [(&str, &str); 4] = [
+    ("temperature", "temperature"),
+    ("top_p", "top_p"),
+    ("seed", "seed"),
+    ("num_predict", "max_tokens"),
+];
+
+/// Talks to a local OpenAI-compatible server. `base_url` is the server root,
+/// without the `/v1` suffix. Timeouts and retries follow the Ollama settings.
+pub struct OpenAiBackend {
+    config: OllamaClientConfig,
+    api_key: Option<String>,
+}
+
+impl OpenAiBackend {
+    pub fn new(config: OllamaClientConfig, api_key: Option<String>) -> Self {
+        Self { config, api_key }
+    }
+
+    /// Uses `base_url` when given, else `PROMPTFORGE_OPENAI_URL`, else the llama.cpp default.
+    pub fn from_env(mut config: OllamaClientConfig, base_url: Option<String>) -> Self {
+        config.base_url = base_url
+            .or_else(|| env::var(OPENAI_URL_ENV).ok())
+            .filter(|url| !url.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_OPENAI_URL.to_string());
+        let api_key = env::var(OPENAI_API_KEY_ENV)
+            .ok()
+            .filter(|key| !key.trim().is_empty());
+        Self::new(config, api_key)
+    }
+
+    fn authorize(&self, builder: RequestBuilder) -> RequestBuilder {
+        match &self.api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        }
+    }
+
+    fn payload(request: &ChatRequest, stream: bool) -> Value {
+        let messages: Vec<Value> = request.messages.iter().map(message_json).collect();
+        let mut payload = json!({
+            "model": request.model,
+            "messages": messages,
+            "stream": stream,
+        });
+        if stream {
+            payload["stream_options"] = json!({ "include_usage": true });
+        }
+        if let Some(schema) = &request.format {
+            payload["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": { "name": "output", "schema": schema, "strict": true },
+            });
+        }
+        for (from, to) in OPTION_MAP {
+            if let Some(value) = request.options.get(from) {
+                payload[to] = value.clone();
+            }
+        }
+        payload
+    }
+}
+
+/// Images become `image_url` content parts carrying data URLs.
+fn message_json(message: &ChatMessage) -> Value {
+    if message.images.is_empty() {
+        return json!({ "role": message.role, "content": message.content });
+    }
+    let mut parts = vec![json!({ "type": "text", "text": message.content })];
+    parts.extend(message.images.iter().map(|image| {
+        json!({
+            "type": "image_url",
+            "image_url": { "url": format!("data:{};base64,{}", sniff_mime(image), image) },
+        })
+    }));
+    json!({ "role": message.role, "content": parts })
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionError {
+    message: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CompletionText {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionChoice {
+    #[serde(default)]
+    delta: CompletionText,
+    #[serde(default)]
+    message: CompletionText,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionUsage {
+    #[serde(default)]
+    prompt_tokens: Option<u64>,
+    #[serde(default)]
+    completion_tokens: Option<u64>,
+}
+
+/// llama.cpp extension carrying generation timings.
+#[derive(Debug, Deserialize)]
+struct CompletionTimings {
+    #[serde(default)]
+    prompt_ms: Option<f64>,
+    #[serde(default)]
+    predicted_ms: Option<f64>,
+    #[serde(default)]
+    predicted_per_second: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    choices: Vec<CompletionChoice>,
+    #[serde(default)]
+    usage: Option<CompletionUsage>,
+    #[serde(default)]
+    timings: Option<CompletionTimings>,
+    #[serde(default)]
+    error: Option<CompletionError>,
+}
+
+fn ms_to_ns(ms: Option<f64>) -> Option<u64> {
+    ms.map(|ms| (ms * 1_000_000.0) as u64)
+}
+
+/// Turns `data:` lines of a chat completion SSE stream into [`OllamaEvent`]s.
+/// Usage and timings arrive before `[DONE]`, so they are held until then.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    stats: GenerationStats,
+}
+
+impl SseParser {
+    pub fn parse_line(&mut self, line: &str) -> Result<Vec<OllamaEvent>, serde_json::Error> {
+        // Comments, `event:` and `id:` fields carry nothing we use.
+        let Some(data) = line.strip_prefix("data:") else {
+            return Ok(Vec::new());
+        };
+        let data = data.trim();
+        if data == "[DONE]" {
+            return Ok(vec![OllamaEvent::Done(mem::take(&mut self.stats))]);
+        }
+        let chunk: CompletionChunk = serde_json::from_str(data)?;
+        if let Some(error) = chunk.error {
+            return Ok(vec![OllamaEvent::Error(error.message)]);
+        }
+        if let Some(usage) = chunk.usage {
+            self.stats.prompt_eval_count = usage.prompt_tokens;
+            self.stats.eval_count = usage.completion_tokens;
+        }
+        if let Some(timings) = chunk.timings {
+            self.stats.prompt_eval_duration = ms_to_ns(timings.prompt_ms);
+            self.stats.eval_duration = ms_to_ns(timings.predicted_ms);
+            self.stats.tokens_per_second = timings.predicted_per_second;
+        }
+        let text: String = chunk
+            .choices
+            .into_iter()
+            .filter_map(|choice| choice.delta.content)
+            .collect();
+        Ok(if text.is_empty() {
+            Vec::new()
+        } else {
+            vec![OllamaEvent::Chunk(text)]
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    #[serde(default)]
+    data: Vec<ModelEntry>,
+}
+
+impl LlmBackend for OpenAiBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Openai
+    }
+
+    fn chat<'a>(&'a self, request: &'a ChatRequest) -> BoxFuture<'a, Result<String, OllamaError>> {
+        Box::pin(async move {
+            let client = self.config.build_client()?;
+            let url = self.config.endpoint("/v1/chat/completions");
+            let payload = Self::payload(request, false);
+            let body = request_text(&client, &self.config, |c| {
+                self.authorize(c.post(&url)).json(&payload)
+            })
+            .await?;
+            let response: CompletionChunk = serde_json::from_str(&body).map_err(protocol_error)?;
+            Ok(response
+                .choices
+                .into_iter()
+                .filter_map(|choice| choice.message.content)
+                .collect())
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        request: &'a ChatRequest,
+        on_line: &'a mut LineSink<'_>,
+    ) -> BoxFuture<'a, Result<(), OllamaError>> {
+        Box::pin(async move {
+            let client = self.config.build_client()?;
+            let url = self.config.endpoint("/v1/chat/completions");
+            let payload = Self::payload(request, true);
+            let mut parser = SseParser::default();
+            stream_ndjson(
+                &client,
+                &self.config,
+                |c| self.authorize(c.post(&url)).json(&payload),
+                |line| {
+                    let events = parser.parse_line(line).map_err(protocol_error)?;
+                    Ok(on_line(line, events))
+                },
+            )
+            .await
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, OllamaError>> {
+        Box::pin(async move {
+            let client = self.config.build_client()?;
+            let url = self.config.endpoint("/v1/models");
+            let body = request_text(&client, &self.config, |c| self.authorize(c.get(&url))).await?;
+            let list: ModelList = serde_json::from_str(&body).map_err(protocol_error)?;
+            Ok(list.data.into_iter().map(|m| m.id).collect())
+        })
+    }
+
+    fn health(&self) -> BoxFuture<'_, Result<(), OllamaError>> {
+        // `/health` is llama.cpp-specific; every compatible server lists models.
+        Box::pin(async move { self.list_models().await.map(|_| ()) })
+    }
+
+    fn wire_payload(&self, request: &ChatRequest, stream: bool) -> Value {
+        Self::payload(request, stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama_client::OllamaErrorKind;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    /// Answers each connection with the next `(status, content type, body)`.
+    async fn spawn_mock_server(
+        replies: Vec<(&'static str, &'static str, String)>,
+    ) -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for (status, content_type, body) in replies {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0_u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let _ = tx.send(String::from_utf8_lossy(&buf[..n]).to_string());
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    content_type,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (format!("http://{}", addr), rx)
+    }
+
+    fn backend(base_url: String) -> OpenAiBackend {
+        let config = OllamaClientConfig {
+            base_url,
+            ..OllamaClientConfig::default()
+        };
+        OpenAiBackend::new(config, Some("sk-local".into()))
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "qwen2.5".into(),
+            messages: vec![ChatMessage::new("user", "hi")],
+            options: json!({ "temperature": 0.2, "num_ctx": 4096, "num_predict": 64 }),
+            format: None,
+        }
+    }
+
+    async fn collect(backend: &OpenAiBackend) -> Result<Vec<OllamaEvent>, OllamaError> {
+        let mut events = Vec::new();
+        backend
+            .stream(&request(), &mut |_, parsed| {
+                let done = parsed.iter().any(|e| matches!(e, OllamaEvent::Done(_)));
+                events.extend(parsed);
+                done
+            })
+            .await?;
+        Ok(events)
+    }
+
+    #[tokio::test]
+    async fn streams_sse_chat_completions() {
+        let sse = concat!(
+            ": keep-alive\n\n",
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"He\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"llo\"},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":2}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let (url, mut requests) =
+            spawn_mock_server(vec![("200 OK", "text/event-stream", sse.into())]).await;
+        let events = collect(&backend(url)).await.unwrap();
+
+        assert_eq!(
+            &events[..2],
+            &[
+                OllamaEvent::Chunk("He".into()),
+                OllamaEvent::Chunk("llo".into())
+            ]
+        );
+        assert!(matches!(
+            &events[2],
+            OllamaEvent::Done(stats) if stats.eval_count == Some(2) && stats.prompt_eval_count == Some(5)
+        ));
+        let sent = requests.recv().await.unwrap();
+        assert!(sent.starts_with("POST /v1/chat/completions"));
+        assert!(sent
+            .to_lowercase()
+            .contains("authorization: bearer sk-local"));
+        assert!(sent.contains(r#""max_tokens":64"#));
+        assert!(!sent.contains("num_ctx"));
+    }
+
+    #[tokio::test]
+    async fn chat_models_and_errors() {
+        let (url, _requests) = spawn_mock_server(vec![
+            (
+                "200 OK",
+                "application/json",
+                r#"{"choices":[{"message":{"role":"assistant","content":"Hello"}}]}"#.into(),
+            ),
+            (
+                "200 OK",
+                "application/json",
+                r#"{"object":"list","data":[{"id":"qwen2.5"},{"id":"phi3"}]}"#.into(),
+            ),
+            (
+                "200 OK",
+                "text/event-stream",
+                "data: {\"error\":{\"message\":\"context overflow\"}}\n\n".into(),
+            ),
+            (
+                "400 Bad Request",
+                "application/json",
+                r#"{"error":{"message":"unknown model"}}"#.into(),
+            ),
+        ])
+        .await;
+        let backend = backend(url);
+
+        assert_eq!(backend.chat(&request()).await.unwrap(), "Hello");
+        assert_eq!(
+            backend.list_models().await.unwrap(),
+            vec!["qwen2.5", "phi3"]
+        );
+        assert_eq!(
+            collect(&backend).await.unwrap(),
+            vec![OllamaEvent::Error("context overflow".into())]
+        );
+        let err = backend.chat(&request()).await.unwrap_err();
+        assert_eq!(err.kind, OllamaErrorKind::Model);
+        assert_eq!(err.message, "unknown model");
+    }
+
+    #[test]
+    fn images_become_data_url_parts() {
+        let mut message = ChatMessage::new("user", "critique");
+        message.images = vec!["iVBORw0KGgo=".into()];
+        assert_eq!(
+            message_json(&message),
+            json!({"role": "user", "content": [
+                {"type": "text", "text": "critique"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}},
+            ]})
+        );
+    }
+
+    #[test]
+    fn keeps_llama_cpp_timings() {
+        let mut parser = SseParser::default();
+        parser
+            .parse_line(r#"data: {"choices":[],"timings":{"predicted_ms":500.0,"predicted_per_second":40.0}}"#)
+            .unwrap();
+        let events = parser.parse_line("data: [DONE]").unwrap();
+        let OllamaEvent::Done(stats) = &events[0] else {
+            panic!("expected done");
+        };
+        assert_eq!(stats.eval_duration, Some(500_000_000));
+        assert_eq!(stats.tokens_per_second, Some(40.0));
+    }
+}
@@ -0,0 +1,704 @@
+//! Localhost-only OpenAI-compatible endpoint (`promptforge serve`), so editors
+//! and scripts that speak `/v1/chat/completions` can use curated recipes.
+//!
+//! A `model` of `recipe:<name>` composes `recipes/<name>.yaml` with the last
+//! user message as `user_input` and sends the result to the recipe's profile;
+//! any other name is resolved like a profile or bare model and gets the
+//! request's system messages plus its last user message. Earlier turns are not
+//! forwarded. Every request streams through the same machinery as the app, so
+//! each one ends up as a run under `runs/`.
+//!
+//! Binding to loopback keeps other machines out, but not web pages open in a
+//! local browser: requests must name the proxy itself in `Host` (against DNS
+//! rebinding) and, when they carry an `Origin`, come from a loopback one.
+
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::Local;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::chat::{spawn_chat_stream, ChatStreamJob};
+use crate::compose::{
+    compose_prompt_in, data_sandbox, named_recipe_path, recipe_names, split_final_prompt,
+};
+use crate::ollama_models::list_ollama_models;
+use crate::ollama_stream::{ChannelSink, StreamState};
+use crate::profiles::resolve_profile_in;
+use crate::runs::{runs_root, RunStatus};
+
+pub const DEFAULT_PROXY_ADDR: &str = "127.0.0.1:11500";
+pub const RECIPE_MODEL_PREFIX: &str = "recipe:";
+
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Where the proxy reads recipes and writes runs.
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    pub data_dir: PathBuf,
+    pub runs_root: PathBuf,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: data_sandbox(),
+            runs_root: runs_root(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatMessage {
+    role: String,
+    /// A string or OpenAI content parts; only text parts are kept.
+    #[serde(default)]
+    content: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    #[serde(default)]
+    stream: bool,
+    /// Extra recipe params (non-standard); `user_input` always comes from the
+    /// last user message.
+    #[serde(default)]
+    params: Map<String, Value>,
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl HttpRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An OpenAI-style error reply.
+#[derive(Debug)]
+struct ProxyError {
+    status: u16,
+    kind: &'static str,
+    message: String,
+}
+
+impl ProxyError {
+    fn new(status: u16, kind: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            kind,
+            message: message.into(),
+        }
+    }
+
+    fn invalid(message: impl Into<String>) -> Self {
+        Self::new(400, "invalid_request_error", message)
+    }
+
+    fn body(&self) -> Value {
+        json!({ "error": { "message": self.message, "type": self.kind } })
+    }
+}
+
+struct Shared {
+    config: ProxyConfig,
+    state: StreamState,
+    port: u16,
+}
+
+/// A running proxy; stops accepting connections when dropped.
+pub struct ProxyServer {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl ProxyServer {
+    /// Binds `addr`, refusing anything that is not a loopback address.
+    pub async fn bind(addr: &str, config: ProxyConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        if !addr.ip().is_loopback() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is not a localhost address", addr),
+            ));
+        }
+        let shared = Arc::new(Shared {
+            config,
+            state: StreamState::default(),
+            port: addr.port(),
+        });
+        let task = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(socket, shared.clone()));
+            }
+        });
+        Ok(Self { addr, task })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Serves until the accept loop fails; used by `promptforge serve`.
+    pub async fn serve_forever(mut self) {
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for ProxyServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n").map(|i| i + 4)
+}
+
+async fn read_request(socket: &mut TcpStream) -> io::Result<Option<HttpRequest>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0_u8; 8192];
+    let head_end = loop {
+        if let Some(end) = find_head_end(&buf) {
+            break end;
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Ok(None);
+        }
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("").to_string();
+    let path = request_line.next().unwrap_or("").to_string();
+    let headers: Vec<(String, String)> = head
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > MAX_BODY_BYTES {
+        return Ok(None);
+    }
+    while buf.len() < head_end + content_length {
+        let read = socket.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+    let body_end = buf.len().min(head_end + content_length);
+    Ok(Some(HttpRequest {
+        method,
+        path,
+        headers,
+        body: buf[head_end..body_end].to_vec(),
+    }))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        429 => "Too Many Requests",
+        502 => "Bad Gateway",
+        _ => "Status",
+    }
+}
+
+async fn write_json(socket: &mut TcpStream, status: u16, body: &Value) -> io::Result<()> {
+    let body = body.to_string();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        body.len()
+    );
+    socket.write_all(head.as_bytes()).await?;
+    socket.write_all(body.as_bytes()).await?;
+    socket.flush().await
+}
+
+async fn write_event(socket: &mut TcpStream, data: &str) -> io::Result<()> {
+    socket
+        .write_all(format!("data: {}\n\n", data).as_bytes())
+        .await?;
+    socket.flush().await
+}
+
+/// `recipe:<name>` for every recipe under `data_dir/recipes`.
+pub fn recipe_models(data_dir: &Path) -> Vec<String> {
+    recipe_names(data_dir)
+        .into_iter()
+        .map(|name| format!("{}{}", RECIPE_MODEL_PREFIX, name))
+        .collect()
+}
+
+/// Recipes first, then whatever Ollama has installed (omitted when it is down).
+async fn list_models(config: &ProxyConfig) -> Value {
+    let model = |id: &str, owner: &str| json!({ "id": id, "object": "model", "created": 0, "owned_by": owner });
+    let mut data: Vec<Value> = recipe_models(&config.data_dir)
+        .iter()
+        .map(|id| model(id, "promptforge"))
+        .collect();
+    if let Ok(models) = list_ollama_models().await {
+        data.extend(models.iter().map(|m| model(&m.name, "ollama")));
+    }
+    json!({ "object": "list", "data": data })
+}
+
+fn message_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Turns a completion request into a chat job, composing the recipe it names.
+fn prepare_job(
+    config: &ProxyConfig,
+    request: &CompletionRequest,
+) -> Result<ChatStreamJob, ProxyError> {
+    let user_input = request
+        .messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "user")
+        .map(|m| message_text(&m.content))
+        .ok_or_else(|| ProxyError::invalid("messages must include a user message"))?;
+
+    let mut job = if let Some(name) = request.model.strip_prefix(RECIPE_MODEL_PREFIX) {
+        let recipe = named_recipe_path(name);
+        if !config.data_dir.join(&recipe).is_file() {
+            return Err(ProxyError::new(
+                404,
+                "model_not_found",
+                format!("recipe `{}` not found", name),
+            ));
+        }
+        let mut params = request.params.clone();
+        params.insert("user_input".into(), Value::String(user_input));
+        let composed = compose_prompt_in(&config.data_dir, &recipe, Some(Value::Object(params)))
+            .map_err(|e| ProxyError::invalid(e.to_string()))?;
+        let profile = resolve_profile_in(&config.data_dir, &composed.model)
+            .map_err(|e| ProxyError::invalid(e.to_string()))?;
+        let (system_text, user_text) = split_final_prompt(&composed.final_prompt);
+        ChatStreamJob::new(
+            &profile,
+            system_text,
+            user_text,
+            Some(recipe),
+            Vec::new(),
+            composed.output_schema.clone(),
+        )
+        .with_composition(&composed)
+    } else {
+        let profile = resolve_profile_in(&config.data_dir, &request.model)
+            .map_err(|e| ProxyError::invalid(e.to_string()))?;
+        let system_text = request
+            .messages
+            .iter()
+            .filter(|m| m.role == "system")
+            .map(|m| message_text(&m.content))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        ChatStreamJob::new(&profile, system_text, user_input, None, Vec::new(), None)
+    };
+    job.runs_root = config.runs_root.clone();
+    Ok(job)
+}
+
+fn completion_chunk(id: &str, model: &str, delta: Value, finish: Option<&str>) -> Value {
+    json!({
+        "id": id,
+        "object": "chat.completion.chunk",
+        "created": Local::now().timestamp(),
+        "model": model,
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": finish }],
+    })
+}
+
+fn usage(stats: &Value) -> Value {
+    let prompt = stats["prompt_eval_count"].as_u64().unwrap_or(0);
+    let completion = stats["eval_count"].as_u64().unwrap_or(0);
+    json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": prompt + completion,
+    })
+}
+
+async fn chat_completion(
+    socket: &mut TcpStream,
+    shared: &Shared,
+    body: &[u8],
+) -> Result<(), ProxyError> {
+    let request: CompletionRequest =
+        serde_json::from_slice(body).map_err(|e| ProxyError::invalid(e.to_string()))?;
+    let job = prepare_job(&shared.config, &request)?;
+    let (tx, mut events) = mpsc::unbounded_channel();
+    let (stream_id, _) = spawn_chat_stream(Arc::new(ChannelSink(tx)), &shared.state, job)
+        .await
+        .map_err(|e| ProxyError::new(429, "rate_limit_error", e))?;
+
+    let id = format!(
+        "chatcmpl-{}-{}",
+        Local::now().format("%Y%m%d%H%M%S"),
+        stream_id
+    );
+    let model = request.model.as_str();
+    if request.stream {
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+        let opened = socket.write_all(head.as_bytes()).await.is_ok()
+            && write_event(
+                socket,
+                &completion_chunk(&id, model, json!({ "role": "assistant" }), None).to_string(),
+            )
+            .await
+            .is_ok();
+        if !opened {
+            shared.state.abort(stream_id).await;
+            return Ok(());
+        }
+    }
+
+    let mut stats = Value::Null;
+    let mut error = None;
+    while let Some((event, payload)) = events.recv().await {
+        match event.as_str() {
+            "ollama:chunk" if request.stream => {
+                let delta = json!({ "content": payload["text"] });
+                let chunk = completion_chunk(&id, model, delta, None).to_string();
+                if write_event(socket, &chunk).await.is_err() {
+                    // The client went away; the run is recorded as aborted.
+                    shared.state.abort(stream_id).await;
+                    return Ok(());
+                }
+            }
+            "ollama:end" => stats = payload["stats"].clone(),
+            "ollama:error" => error = payload["message"].as_str().map(str::to_string),
+            "ollama:saved" => {
+                let status: Option<RunStatus> =
+                    serde_json::from_value(payload["status"].clone()).ok();
+                let failed = status != Some(RunStatus::Completed);
+                let run_id = payload["run_id"].clone();
+                if request.stream {
+                    if let (true, Some(message)) = (failed, &error) {
+                        let body = ProxyError::new(502, "api_error", message.as_str()).body();
+                        let _ = write_event(socket, &body.to_string()).await;
+                    }
+                    let mut last = completion_chunk(&id, model, json!({}), Some("stop"));
+                    last["usage"] = usage(&stats);
+                    last["run_id"] = run_id;
+                    let _ = write_event(socket, &last.to_string()).await;
+                    let _ = write_event(socket, "[DONE]").await;
+                    return Ok(());
+                }
+                if failed {
+                    return Err(ProxyError::new(
+                        502,
+                        "api_error",
+                        error.unwrap_or_else(|| "the backend did not finish the reply".into()),
+                    ));
+                }
+                let reply = json!({
+                    "id": id,
+                    "object": "chat.completion",
+                    "created": Local::now().timestamp(),
+                    "model": model,
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": payload["partial_text"] },
+                        "finish_reason": "stop",
+                    }],
+                    "usage": usage(&stats),
+                    "run_id": run_id,
+                });
+                let _ = write_json(socket, 200, &reply).await;
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+    Err(ProxyError::new(
+        502,
+        "api_error",
+        "stream ended without an outcome",
+    ))
+}
+
+/// `host[:port]` split at the port; IPv6 hosts keep their brackets.
+fn split_port(authority: &str) -> (&str, Option<&str>) {
+    match authority.rfind(':') {
+        Some(i) if !authority[i..].contains(']') => (&authority[..i], Some(&authority[i + 1..])),
+        _ => (authority, None),
+    }
+}
+
+fn is_loopback_host(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost") || host == "127.0.0.1" || host == "[::1]"
+}
+
+/// Rejects requests a web page could have made: a `Host` other than this
+/// proxy (DNS rebinding) or a non-loopback `Origin` (cross-site POSTs, which
+/// browsers send without a preflight as `text/plain`).
+fn check_caller(request: &HttpRequest, port: u16) -> Result<(), ProxyError> {
+    let forbidden = |message: &str| ProxyError::new(403, "permission_error", message);
+    let (host, host_port) = split_port(request.header("host").unwrap_or(""));
+    let port_matches = match host_port {
+        Some(p) => p.parse::<u16>().ok() == Some(port),
+        None => port == 80,
+    };
+    if !is_loopback_host(host) || !port_matches {
+        return Err(forbidden("Host must be this localhost proxy"));
+    }
+    if let Some(origin) = request.header("origin") {
+        let authority = origin
+            .strip_prefix("http://")
+            .or_else(|| origin.strip_prefix("https://"))
+            .unwrap_or("");
+        if !is_loopback_host(split_port(authority).0) {
+            return Err(forbidden("cross-origin requests are not allowed"));
+        }
+    }
+    Ok(())
+}
+
+async fn serve_connection(mut socket: TcpStream, shared: Arc<Shared>) {
+    let Ok(Some(request)) = read_request(&mut socket).await else {
+        return;
+    };
+    if let Err(error) = check_caller(&request, shared.port) {
+        let _ = write_json(&mut socket, error.status, &error.body()).await;
+        let _ = socket.shutdown().await;
+        return;
+    }
+    let path = request.path.split('?').next().unwrap_or("");
+    let result = match (request.method.as_str(), path) {
+        ("GET", "/v1/models") => {
+            let models = list_models(&shared.config).await;
+            let _ = write_json(&mut socket, 200, &models).await;
+            Ok(())
+        }
+        ("POST", "/v1/chat/completions") => {
+            chat_completion(&mut socket, &shared, &request.body).await
+        }
+        (_, "/v1/models") | (_, "/v1/chat/completions") => Err(ProxyError::new(
+            405,
+            "invalid_request_error",
+            "method not allowed",
+        )),
+        _ => Err(ProxyError::new(404, "invalid_request_error", "not found")),
+    };
+    if let Err(error) = result {
+        let _ = write_json(&mut socket, error.status, &error.body()).await;
+    }
+    let _ = socket.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ollama::{MockConfig, MockOllama};
+    use crate::runs::list_run_metas;
+    use crate::test_support::data_fixture;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn serves_recipes_as_models() {
+        let temp = tempdir().unwrap();
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        let data = data_fixture(temp.path(), &mock.url());
+        let runs_root = temp.path().join("runs");
+        let config = ProxyConfig {
+            data_dir: data,
+            runs_root: runs_root.clone(),
+        };
+        assert!(ProxyServer::bind("0.0.0.0:0", config.clone())
+            .await
+            .is_err());
+        let proxy = ProxyServer::bind("127.0.0.1:0", config).await.unwrap();
+        let client = reqwest::Client::new();
+
+        let models: Value = reqwest::get(format!("{}/v1/models", proxy.url()))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(models["data"][0]["id"], "recipe:echo");
+
+        let url = format!("{}/v1/chat/completions", proxy.url());
+        let body = json!({
+            "model": "recipe:echo",
+            "messages": [{ "role": "user", "content": "duel at dawn" }],
+            "params": { "tone": "calm" },
+        });
+        let reply: Value = client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            reply["choices"][0]["message"]["content"],
+            "This is a mock reply."
+        );
+        assert_eq!(reply["usage"]["prompt_tokens"], 8);
+        let sent = mock.requests_to("/api/chat")[0].body.to_string();
+        assert!(sent.contains("Answer in a calm tone.") && sent.contains("duel at dawn"));
+
+        let mut streamed = body.clone();
+        streamed["stream"] = json!(true);
+        let text = client
+            .post(&url)
+            .json(&streamed)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let data: Vec<&str> = text
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .collect();
+        assert_eq!(data.last(), Some(&"[DONE]"));
+        let content: String = data[..data.len() - 1]
+            .iter()
+            .map(|d| serde_json::from_str::<Value>(d).unwrap())
+            .filter_map(|c| {
+                c["choices"][0]["delta"]["content"]
+                    .as_str()
+                    .map(str::to_string)
+            })
+            .collect();
+        assert_eq!(content, "This is a mock reply.");
+
+        let missing = client
+            .post(&url)
+            .json(&json!({ "model": "recipe:nope", "messages": [{ "role": "user", "content": "x" }] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(missing.status().as_u16(), 404);
+
+        let runs = list_run_metas(&runs_root).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert!(runs
+            .iter()
+            .all(|run| run.recipe_path.as_deref() == Some("recipes/echo.yaml")));
+    }
+
+    /// Sends a raw request so the `Host` header can be anything.
+    async fn status_for(addr: SocketAddr, head: &str) -> String {
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        socket.write_all(head.as_bytes()).await.unwrap();
+        let mut reply = String::new();
+        socket.read_to_string(&mut reply).await.unwrap();
+        reply.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn rejects_foreign_hosts_and_origins() {
+        let temp = tempdir().unwrap();
+        let config = ProxyConfig {
+            data_dir: temp.path().join("data"),
+            runs_root: temp.path().join("runs"),
+        };
+        let proxy = ProxyServer::bind("127.0.0.1:0", config).await.unwrap();
+        let port = proxy.addr.port();
+        // Allowed requests reach routing and get a 404 for this path.
+        let get = |host: &str, extra: &str| {
+            format!(
+                "GET /v1/missing HTTP/1.1\r\nHost: {}\r\n{}Connection: close\r\n\r\n",
+                host, extra
+            )
+        };
+
+        for host in [format!("127.0.0.1:{}", port), format!("LOCALHOST:{}", port)] {
+            let status = status_for(proxy.addr, &get(&host, "")).await;
+            assert_eq!(status, "HTTP/1.1 404 Not Found", "{}", host);
+        }
+        for host in [
+            format!("rebound.example:{}", port),
+            "127.0.0.1:1".to_string(),
+            "localhost".to_string(),
+        ] {
+            let status = status_for(proxy.addr, &get(&host, "")).await;
+            assert_eq!(status, "HTTP/1.1 403 Forbidden", "{}", host);
+        }
+        let missing = "GET /v1/missing HTTP/1.1\r\nConnection: close\r\n\r\n";
+        assert_eq!(
+            status_for(proxy.addr, missing).await,
+            "HTTP/1.1 403 Forbidden"
+        );
+
+        let host = format!("127.0.0.1:{}", port);
+        let local = get(&host, "Origin: http://localhost:5173\r\n");
+        assert_eq!(
+            status_for(proxy.addr, &local).await,
+            "HTTP/1.1 404 Not Found"
+        );
+        for origin in [
+            "https://evil.example",
+            "null",
+            "http://127.0.0.1.evil.example",
+        ] {
+            let head = get(&host, &format!("Origin: {}\r\n", origin));
+            let status = status_for(proxy.addr, &head).await;
+            assert_eq!(status, "HTTP/1.1 403 Forbidden", "{}", origin);
+        }
+
+        // A page's simple cross-site POST is refused before any run starts.
+        let response = reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", proxy.url()))
+            .header("Origin", "https://evil.example")
+            .header("Content-Type", "text/plain")
+            .body(r#"{"model":"llama3","messages":[{"role":"user","content":"x"}]}"#)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 403);
+        assert!(!temp.path().join("runs").exists());
+    }
+}
@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::llm_backend::BackendKind;
+
+/// A model profile from `data/profiles/<name>.yaml`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub model: String,
+    #[serde(default)]
+    pub backend: BackendKind,
+    /// Server root overriding the backend's configured URL.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub num_ctx: Option<u64>,
+    #[serde(default)]
+    pub top_p: Option<f64>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+    /// JSON schema replies must follow (sent as Ollama's `format`).
+    #[serde(default)]
+    pub output_schema: Option<Value>,
+}
+
+impl Profile {
+    /// Profile for a bare model name, with every option left to the server default.
+    pub fn for_model(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// The `options` object sent to Ollama; unset fields are omitted.
+    pub fn ollama_options(&self) -> Value {
+        let mut options = Map::new();
+        if let Some(temperature) = self.temperature {
+            options.insert("temperature".into(), temperature.into());
+        }
+        if let Some(num_ctx) = self.num_ctx {
+            options.insert("num_ctx".into(), num_ctx.into());
+        }
+        if let Some(top_p) = self.top_p {
+            options.insert("top_p".into(), top_p.into());
+        }
+        if let Some(seed) = self.seed {
+            options.insert("seed".into(), seed.into());
+        }
+        Value::Object(options)
+    }
+}
+
+pub fn profile_path(sandbox: &Path, name: &str) -> PathBuf {
+    let raw = PathBuf::from(name);
+    let is_file_ref = matches!(
+        raw.extension().and_then(|e| e.to_str()),
+        Some("yaml") | Some("yml")
+    );
+    if !is_file_ref {
+        sandbox.join("profiles").join(format!("{}.yaml", name))
+    } else if raw.is_absolute() || raw.starts_with(sandbox) {
+        raw
+    } else {
+        sandbox.join(raw)
+    }
+}
+
+/// Resolves `name` to a profile file under the data sandbox. Recipes may also
+/// name a model directly (`profile: llama3:8b`); when no profile file exists the
+/// name is used as the model with default options.
+pub fn resolve_profile(name: &str) -> Result<Profile> {
+    resolve_profile_in(&crate::compose::data_sandbox(), name)
+}
+
+/// [`resolve_profile`] against an explicit data directory.
+pub fn resolve_profile_in(sandbox: &Path, name: &str) -> Result<Profile> {
+    let path = profile_path(sandbox, name);
+    if !path.is_file() {
+        return Ok(Profile::for_model(name));
+    }
+    crate::sandbox::ensure_under(sandbox, &path)?;
+    crate::compose::read_yaml(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn options_only_include_set_fields() {
+        let profile: Profile =
+            serde_yaml::from_str("model: llama3:8b\ntemperature: 0.5\nnum_ctx: 8192\n").unwrap();
+        assert_eq!(
+            profile.ollama_options(),
+            json!({"temperature": 0.5, "num_ctx": 8192})
+        );
+        assert_eq!(Profile::for_model("phi3").ollama_options(), json!({}));
+    }
+
+    #[test]
+    fn profile_names_map_into_profiles_dir() {
+        let sandbox = Path::new("data");
+        assert_eq!(
+            profile_path(sandbox, "ollama_llama3_8b"),
+            PathBuf::from("data/profiles/ollama_llama3_8b.yaml")
+        );
+        assert_eq!(
+            profile_path(sandbox, "profiles/custom.yaml"),
+            PathBuf::from("data/profiles/custom.yaml")
+        );
+        assert_eq!(
+            profile_path(sandbox, "data/profiles/custom.yml"),
+            PathBuf::from("data/profiles/custom.yml")
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Static checks on a recipe that `compose_prompt` would otherwise only hit at
+//! run time, or silently accept.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::compose::{fragment_file, read_yaml, recipe_file, Fragment, Recipe};
+use crate::profiles::{profile_path, Profile};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintLevel {
+    /// `compose_prompt` would fail.
+    Error,
+    /// Composes, but probably not as intended.
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintIssue {
+    pub level: LintLevel,
+    pub message: String,
+}
+
+impl LintIssue {
+    fn error(message: String) -> Self {
+        Self {
+            level: LintLevel::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            level: LintLevel::Warning,
+            message,
+        }
+    }
+}
+
+/// `{{name}}` placeholders in `content`, in order of first appearance.
+pub fn placeholders(content: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let name = &after[..end];
+        if !name.is_empty() && !found.iter().any(|f| f == name) {
+            found.push(name.to_string());
+        }
+        rest = &after[end + 2..];
+    }
+    found
+}
+
+/// Lints `recipe_path` against the data sandbox at `sandbox`.
+pub fn lint_recipe(sandbox: &Path, recipe_path: &str) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let recipe: Recipe = match recipe_file(sandbox, recipe_path).and_then(|p| read_yaml(&p)) {
+        Ok(recipe) => recipe,
+        Err(err) => return vec![LintIssue::error(format!("recipe: {}", err))],
+    };
+
+    let profile = profile_path(sandbox, &recipe.profile);
+    let names_file = Path::new(&recipe.profile)
+        .extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml");
+    if profile.is_file() {
+        if let Err(err) = read_yaml::<Profile>(&profile) {
+            issues.push(LintIssue::error(format!(
+                "profile `{}`: {}",
+                recipe.profile, err
+            )));
+        }
+    } else if names_file {
+        issues.push(LintIssue::error(format!(
+            "profile `{}` not found",
+            recipe.profile
+        )));
+    }
+
+    if recipe.fragments.is_empty() {
+        issues.push(LintIssue::warning("recipe has no fragments".into()));
+    }
+    let params: BTreeSet<&str> = recipe
+        .params
+        .as_object()
+        .map(|o| o.keys().map(String::as_str).collect())
+        .unwrap_or_default();
+    let mut seen = BTreeSet::new();
+    let mut used = BTreeSet::new();
+    for id in &recipe.fragments {
+        if !seen.insert(id.as_str()) {
+            issues.push(LintIssue::warning(format!(
+                "fragment `{}` is listed twice",
+                id
+            )));
+            continue;
+        }
+        let fragment: Fragment = match fragment_file(sandbox, id).and_then(|p| read_yaml(&p)) {
+            Ok(fragment) => fragment,
+            Err(err) => {
+                issues.push(LintIssue::error(format!("fragment `{}`: {}", id, err)));
+                continue;
+            }
+        };
+        if fragment.id != *id {
+            issues.push(LintIssue::warning(format!(
+                "fragment `{}` declares id `{}`",
+                id, fragment.id
+            )));
+        }
+        for name in placeholders(&fragment.content) {
+            if !params.contains(name.as_str()) && name != "user_input" {
+                issues.push(LintIssue::warning(format!(
+                    "fragment `{}` uses `{{{{{}}}}}` but the recipe has no such param",
+                    id, name
+                )));
+            }
+            used.insert(name);
+        }
+    }
+    for name in params {
+        if name != "user_input" && !used.contains(name) {
+            issues.push(LintIssue::warning(format!(
+                "param `{}` is not used by any fragment",
+                name
+            )));
+        }
+    }
+
+    if let Some(schema) = &recipe.output_schema {
+        if !schema.is_object() && !schema.is_boolean() {
+            issues.push(LintIssue::error(
+                "output_schema must be a JSON schema object".into(),
+            ));
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn finds_placeholders_once() {
+        assert_eq!(
+            placeholders("{{goal}} in {{steps}} steps, {{goal}} again, {{open"),
+            vec!["goal", "steps"]
+        );
+    }
+
+    #[test]
+    fn reports_missing_fragments_and_param_mismatches() {
+        let temp = tempdir().unwrap();
+        let base = temp.path();
+        write(
+            base,
+            "fragments/task/plan.yaml",
+            "id: task.plan\nkind: task\ncontent: \"Plan {{goal}} for {{audience}}\"\n",
+        );
+        write(
+            base,
+            "recipes/plan.yaml",
+            "profile: llama3:8b\nfragments:\n  - task.plan\n  - task.plan\n  - style.missing\nparams:\n  goal: x\n  tone: y\noutput_schema: \"json\"\n",
+        );
+
+        let issues = lint_recipe(base, "recipes/plan.yaml");
+        let messages: Vec<(LintLevel, &str)> = issues
+            .iter()
+            .map(|i| (i.level, i.message.as_str()))
+            .collect();
+        assert_eq!(messages.len(), 5, "{:?}", messages);
+        assert_eq!(messages[0].0, LintLevel::Warning);
+        assert!(messages[0].1.contains("{{audience}}"));
+        assert!(messages[1].1.contains("listed twice"));
+        assert_eq!(messages[2].0, LintLevel::Error);
+        assert!(messages[2].1.starts_with("fragment `style.missing`"));
+        assert!(messages[3].1.contains("param `tone`"));
+        assert_eq!(messages[4].0, LintLevel::Error);
+
+        let escaped = lint_recipe(base, "../outside.yaml");
+        assert_eq!(escaped[0].level, LintLevel::Error);
+    }
+}
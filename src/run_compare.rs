@@ -0,0 +1,394 @@
+//! Side-by-side comparison of two saved runs: what changed in the prompt and
+//! where it came from, and what that did to the response and its cost.
+
+use std::collections::BTreeSet;
+use std::ops::Sub;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use similar::{ChangeTag, TextDiff};
+
+use crate::compose::FragmentSource;
+use crate::ollama_stream::GenerationStats;
+use crate::run_index::{read_run, RunRecord, RunSummary};
+
+/// Lines of unchanged context around each hunk of a unified diff.
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffGranularity {
+    #[default]
+    Line,
+    Word,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// A fragment that was added, removed or whose file changed between runs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FragmentChange {
+    pub id: String,
+    pub change: ChangeKind,
+    pub left: Option<FragmentSource>,
+    pub right: Option<FragmentSource>,
+}
+
+/// A param or Ollama option that differs; `Null` stands for "not set".
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValueChange {
+    pub key: String,
+    pub change: ChangeKind,
+    pub left: Value,
+    pub right: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffSpan {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResponseDiff {
+    pub granularity: DiffGranularity,
+    /// Consecutive lines or words with the same op, for highlighting.
+    pub spans: Vec<DiffSpan>,
+    /// A unified diff for lines; `[-old-]{+new+}` inline markup for words.
+    pub text: String,
+}
+
+impl ResponseDiff {
+    /// Word diffs spell out the whole text even when nothing changed, so
+    /// `text` alone does not tell.
+    pub fn is_unchanged(&self) -> bool {
+        self.spans.iter().all(|span| span.op == DiffOp::Equal)
+    }
+}
+
+/// Both values and `right - left` when both are known.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Delta<T> {
+    pub left: Option<T>,
+    pub right: Option<T>,
+    pub delta: Option<T>,
+}
+
+impl<T: Copy + Sub<Output = T>> Delta<T> {
+    fn new(left: Option<T>, right: Option<T>) -> Self {
+        Delta {
+            left,
+            right,
+            delta: left.zip(right).map(|(l, r)| r - l),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunDeltas {
+    pub duration_ms: Delta<i64>,
+    pub time_to_first_token_ms: Delta<i64>,
+    pub prompt_tokens: Delta<i64>,
+    pub completion_tokens: Delta<i64>,
+    pub tokens_per_second: Delta<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunComparison {
+    pub left: RunSummary,
+    pub right: RunSummary,
+    /// Unified diff of `prompt.final.txt`; empty when the prompts match.
+    pub prompt_diff: String,
+    pub fragments: Vec<FragmentChange>,
+    pub params: Vec<ValueChange>,
+    pub options: Vec<ValueChange>,
+    pub response: ResponseDiff,
+    pub deltas: RunDeltas,
+}
+
+fn unified_diff(left: &str, right: &str, left_name: &str, right_name: &str) -> String {
+    let diff = TextDiff::from_lines(left, right);
+    if diff.ratio() == 1.0 {
+        return String::new();
+    }
+    diff.unified_diff()
+        .context_radius(DIFF_CONTEXT)
+        .header(left_name, right_name)
+        .to_string()
+}
+
+fn fragment_changes(left: &[FragmentSource], right: &[FragmentSource]) -> Vec<FragmentChange> {
+    let find = |list: &[FragmentSource], id: &str| list.iter().find(|f| f.id == id).cloned();
+    let mut ids: Vec<&str> = right.iter().map(|f| f.id.as_str()).collect();
+    ids.extend(
+        left.iter()
+            .map(|f| f.id.as_str())
+            .filter(|id| !right.iter().any(|f| f.id == *id)),
+    );
+    ids.into_iter()
+        .filter_map(|id| {
+            let (l, r) = (find(left, id), find(right, id));
+            let change = match (&l, &r) {
+                (None, Some(_)) => ChangeKind::Added,
+                (Some(_), None) => ChangeKind::Removed,
+                (Some(l), Some(r)) if l != r => ChangeKind::Changed,
+                _ => return None,
+            };
+            Some(FragmentChange {
+                id: id.to_string(),
+                change,
+                left: l,
+                right: r,
+            })
+        })
+        .collect()
+}
+
+/// Top-level keys whose values differ; non-object values compare as a whole
+/// under the empty key.
+fn value_changes(left: &Value, right: &Value) -> Vec<ValueChange> {
+    let empty = serde_json::Map::new();
+    let as_object = |value: &Value| match value {
+        Value::Object(map) => Some(map.clone()),
+        Value::Null => Some(empty.clone()),
+        _ => None,
+    };
+    let (Some(l), Some(r)) = (as_object(left), as_object(right)) else {
+        return if left == right {
+            Vec::new()
+        } else {
+            vec![ValueChange {
+                key: String::new(),
+                change: ChangeKind::Changed,
+                left: left.clone(),
+                right: right.clone(),
+            }]
+        };
+    };
+    let keys: BTreeSet<&String> = l.keys().chain(r.keys()).collect();
+    keys.into_iter()
+        .filter_map(|key| {
+            let (lv, rv) = (l.get(key), r.get(key));
+            let change = match (lv, rv) {
+                (None, Some(_)) => ChangeKind::Added,
+                (Some(_), None) => ChangeKind::Removed,
+                (Some(a), Some(b)) if a != b => ChangeKind::Changed,
+                _ => return None,
+            };
+            Some(ValueChange {
+                key: key.clone(),
+                change,
+                left: lv.cloned().unwrap_or_default(),
+                right: rv.cloned().unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+fn response_diff(
+    left: &RunRecord,
+    right: &RunRecord,
+    granularity: DiffGranularity,
+) -> ResponseDiff {
+    let (left_id, right_id) = (&left.meta.run_id, &right.meta.run_id);
+    let (left, right) = (left.response.as_str(), right.response.as_str());
+    let diff = match granularity {
+        DiffGranularity::Line => TextDiff::from_lines(left, right),
+        DiffGranularity::Word => TextDiff::from_words(left, right),
+    };
+    let mut spans: Vec<DiffSpan> = Vec::new();
+    for change in diff.iter_all_changes() {
+        let op = match change.tag() {
+            ChangeTag::Equal => DiffOp::Equal,
+            ChangeTag::Delete => DiffOp::Delete,
+            ChangeTag::Insert => DiffOp::Insert,
+        };
+        match spans.last_mut() {
+            Some(last) if last.op == op => last.text.push_str(change.value()),
+            _ => spans.push(DiffSpan {
+                op,
+                text: change.value().to_string(),
+            }),
+        }
+    }
+    let text = match granularity {
+        DiffGranularity::Line => unified_diff(left, right, left_id, right_id),
+        DiffGranularity::Word => spans
+            .iter()
+            .map(|span| match span.op {
+                DiffOp::Equal => span.text.clone(),
+                DiffOp::Delete => format!("[-{}-]", span.text),
+                DiffOp::Insert => format!("{{+{}+}}", span.text),
+            })
+            .collect(),
+    };
+    ResponseDiff {
+        granularity,
+        spans,
+        text,
+    }
+}
+
+fn deltas(left: &RunRecord, right: &RunRecord) -> RunDeltas {
+    let stat = |record: &RunRecord, pick: fn(&GenerationStats) -> Option<u64>| {
+        record.meta.stats.as_ref().and_then(pick).map(|n| n as i64)
+    };
+    let both =
+        |pick: fn(&GenerationStats) -> Option<u64>| Delta::new(stat(left, pick), stat(right, pick));
+    RunDeltas {
+        duration_ms: Delta::new(
+            left.meta.duration_ms.map(|n| n as i64),
+            right.meta.duration_ms.map(|n| n as i64),
+        ),
+        time_to_first_token_ms: both(|s| s.time_to_first_token_ms),
+        prompt_tokens: both(|s| s.prompt_eval_count),
+        completion_tokens: both(|s| s.eval_count),
+        tokens_per_second: Delta::new(
+            left.meta.stats.as_ref().and_then(|s| s.tokens_per_second),
+            right.meta.stats.as_ref().and_then(|s| s.tokens_per_second),
+        ),
+    }
+}
+
+pub fn compare_records(
+    left: &RunRecord,
+    right: &RunRecord,
+    granularity: DiffGranularity,
+) -> RunComparison {
+    RunComparison {
+        left: RunSummary::from(&left.meta),
+        right: RunSummary::from(&right.meta),
+        prompt_diff: unified_diff(
+            &left.prompt,
+            &right.prompt,
+            &left.meta.run_id,
+            &right.meta.run_id,
+        ),
+        fragments: fragment_changes(&left.meta.fragments, &right.meta.fragments),
+        params: value_changes(&left.meta.params, &right.meta.params),
+        options: value_changes(&left.meta.options, &right.meta.options),
+        response: response_diff(left, right, granularity),
+        deltas: deltas(left, right),
+    }
+}
+
+/// Compares run `left` (the baseline) with run `right`.
+pub fn compare_runs(
+    root: &Path,
+    left: &str,
+    right: &str,
+    granularity: DiffGranularity,
+) -> Result<RunComparison, String> {
+    let read = |run_id: &str| read_run(root, run_id).map_err(|e| format!("{}: {}", run_id, e));
+    Ok(compare_records(&read(left)?, &read(right)?, granularity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runs::{RunMeta, RunStatus};
+    use serde_json::json;
+
+    fn record(run_id: &str, sha: &str, params: Value, response: &str, eval: u64) -> RunRecord {
+        let meta = json!({
+            "run_id": run_id,
+            "status": "completed",
+            "model": "llama3:8b",
+            "started_at": "2025-01-01T00:00:00+00:00",
+            "finished_at": "2025-01-01T00:00:01+00:00",
+            "params": params,
+            "options": {"temperature": 0.2},
+            "fragments": [
+                {"id": "style", "path": "fragments/style.yaml", "sha256": sha},
+                {"id": "task", "path": "fragments/task.yaml", "sha256": "t"}
+            ],
+            "duration_ms": 1000 + eval,
+        });
+        let mut meta: RunMeta = serde_json::from_value(meta).unwrap();
+        meta.stats = Some(GenerationStats {
+            eval_count: Some(eval),
+            ..GenerationStats::default()
+        });
+        RunRecord {
+            prompt: format!("SYS\nstyle {}\n---\nUSER_INPUT\nharbour\n", sha),
+            response: response.into(),
+            structured: None,
+            meta,
+        }
+    }
+
+    #[test]
+    fn compares_prompts_provenance_params_and_responses() {
+        let left = record(
+            "a",
+            "s1",
+            json!({"steps": 4, "tone": "calm"}),
+            "A quiet harbour at dawn",
+            40,
+        );
+        let mut right = record(
+            "b",
+            "s2",
+            json!({"steps": 6, "tone": "calm"}),
+            "A busy harbour at dusk",
+            55,
+        );
+        right.meta.fragments.pop();
+        assert_eq!(right.meta.status, RunStatus::Completed);
+
+        let cmp = compare_records(&left, &right, DiffGranularity::Word);
+        assert!(cmp.prompt_diff.starts_with("--- a\n+++ b\n"));
+        assert!(cmp.prompt_diff.contains("-style s1\n+style s2\n"));
+
+        let changes: Vec<_> = cmp
+            .fragments
+            .iter()
+            .map(|f| (f.id.as_str(), f.change))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("style", ChangeKind::Changed),
+                ("task", ChangeKind::Removed)
+            ]
+        );
+        assert_eq!(cmp.params.len(), 1);
+        assert_eq!(
+            (cmp.params[0].left.clone(), cmp.params[0].right.clone()),
+            (json!(4), json!(6))
+        );
+        assert!(cmp.options.is_empty());
+
+        assert_eq!(
+            cmp.response.text,
+            "A [-quiet-]{+busy+} harbour at [-dawn-]{+dusk+}"
+        );
+        assert_eq!(cmp.deltas.completion_tokens.delta, Some(15));
+        assert_eq!(cmp.deltas.duration_ms.delta, Some(15));
+        assert_eq!(cmp.deltas.tokens_per_second.delta, None);
+
+        assert!(!cmp.response.is_unchanged());
+
+        let same = compare_records(&left, &left, DiffGranularity::Line);
+        assert!(same.prompt_diff.is_empty() && same.response.text.is_empty());
+        assert!(same.fragments.is_empty() && same.params.is_empty());
+        assert!(same.response.is_unchanged());
+        let same_words = compare_records(&left, &left, DiffGranularity::Word);
+        assert!(same_words.response.is_unchanged() && !same_words.response.text.is_empty());
+    }
+}
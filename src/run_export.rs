@@ -0,0 +1,377 @@
+//! Exports of saved runs: a self-contained Markdown or HTML report to share,
+//! or a JSONL dataset for fine-tuning and evaluation tools. Prompts,
+//! responses and params pass through [`Redactor`] first.
+
+use std::path::Path;
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::compose::split_final_prompt;
+use crate::redact::Redactor;
+use crate::run_index::{list_runs, read_run, RunQuery, RunRecord};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Markdown,
+    Html,
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// Which runs to export: `run_ids` when given, otherwise every run matching
+/// `query` (all of them unless `query.limit` is set).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ExportRequest {
+    pub run_ids: Vec<String>,
+    pub query: RunQuery,
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunExport {
+    pub format: ExportFormat,
+    pub runs: usize,
+    /// Secret types that were replaced with `<REDACTED:TYPE>`.
+    pub masked_types: Vec<String>,
+    pub content: String,
+}
+
+/// A run's text with secrets masked.
+struct ExportedRun {
+    record: RunRecord,
+    prompt: String,
+    response: String,
+    params: Value,
+}
+
+fn collect(root: &Path, request: &ExportRequest) -> Result<Vec<RunRecord>, String> {
+    let ids: Vec<String> = if request.run_ids.is_empty() {
+        let query = RunQuery {
+            limit: Some(request.query.limit.unwrap_or(usize::MAX)),
+            ..request.query.clone()
+        };
+        list_runs(root, &query)?
+            .items
+            .into_iter()
+            .map(|run| run.run_id)
+            .collect()
+    } else {
+        request.run_ids.clone()
+    };
+    ids.iter()
+        .map(|run_id| read_run(root, run_id).map_err(|e| format!("{}: {}", run_id, e)))
+        .collect()
+}
+
+pub fn export_runs(root: &Path, request: &ExportRequest) -> Result<RunExport, String> {
+    let records = collect(root, request)?;
+    if records.is_empty() {
+        return Err("no runs to export".into());
+    }
+    let mut redactor = Redactor::default();
+    let runs: Vec<ExportedRun> = records
+        .into_iter()
+        .map(|record| ExportedRun {
+            prompt: redactor.mask(&record.prompt),
+            response: redactor.mask(&record.response),
+            params: redactor.mask_json(&record.meta.params),
+            record,
+        })
+        .collect();
+    let masked_types = redactor.masked_types();
+    let content = match request.format {
+        ExportFormat::Markdown => markdown_report(&runs, &masked_types),
+        ExportFormat::Html => html_report(&runs, &masked_types),
+        ExportFormat::Jsonl => jsonl_dataset(&runs),
+    };
+    Ok(RunExport {
+        format: request.format,
+        runs: runs.len(),
+        masked_types,
+        content,
+    })
+}
+
+/// Label/value rows shared by both report formats.
+fn facts(run: &ExportedRun) -> Vec<(&'static str, String)> {
+    let meta = &run.record.meta;
+    let mut rows = vec![
+        (
+            "Status",
+            json!(meta.status).as_str().unwrap_or_default().to_string(),
+        ),
+        ("Model", meta.model.clone()),
+        (
+            "Recipe",
+            meta.recipe_path.clone().unwrap_or_else(|| "-".into()),
+        ),
+        ("Started", meta.started_at.clone()),
+    ];
+    if let Some(ms) = meta.duration_ms {
+        rows.push(("Duration", format!("{:.1} s", ms as f64 / 1000.0)));
+    }
+    if let Some(stats) = &meta.stats {
+        let count = |n: Option<u64>| n.map(|n| n.to_string()).unwrap_or_else(|| "-".into());
+        let mut tokens = format!(
+            "prompt {} / response {}",
+            count(stats.prompt_eval_count),
+            count(stats.eval_count)
+        );
+        if let Some(tps) = stats.tokens_per_second {
+            tokens.push_str(&format!(" ({:.1} tokens/s)", tps));
+        }
+        rows.push(("Tokens", tokens));
+    }
+    if !meta.options.is_null() {
+        rows.push(("Options", meta.options.to_string()));
+    }
+    if let Some(original) = &meta.replay_of {
+        rows.push(("Replay of", original.clone()));
+    }
+    rows
+}
+
+fn report_intro(runs: &[ExportedRun], masked_types: &[String]) -> String {
+    let mut intro = format!(
+        "{} run(s), exported {}",
+        runs.len(),
+        Local::now().format("%Y-%m-%d %H:%M")
+    );
+    if !masked_types.is_empty() {
+        intro.push_str(&format!("; masked secrets: {}", masked_types.join(", ")));
+    }
+    intro
+}
+
+/// A backtick fence longer than any run of backticks in `text`.
+fn fence(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat((longest + 1).max(3))
+}
+
+fn code_block(lang: &str, text: &str) -> String {
+    let fence = fence(text);
+    let newline = if text.ends_with('\n') { "" } else { "\n" };
+    format!("{}{}\n{}{}{}\n", fence, lang, text, newline, fence)
+}
+
+fn markdown_report(runs: &[ExportedRun], masked_types: &[String]) -> String {
+    let mut out = format!(
+        "# PromptForge run report\n\n{}\n",
+        report_intro(runs, masked_types)
+    );
+    for run in runs {
+        out.push_str(&format!(
+            "\n## {}\n\n| | |\n|---|---|\n",
+            run.record.meta.run_id
+        ));
+        for (label, value) in facts(run) {
+            out.push_str(&format!("| {} | {} |\n", label, value.replace('|', "\\|")));
+        }
+        if !run.params.is_null() {
+            let params = serde_json::to_string_pretty(&run.params).unwrap_or_default();
+            out.push_str(&format!("\n### Params\n\n{}", code_block("json", &params)));
+        }
+        if !run.record.meta.fragments.is_empty() {
+            out.push_str("\n### Fragments\n\n");
+            for fragment in &run.record.meta.fragments {
+                out.push_str(&format!(
+                    "- `{}` — {} (sha256 `{}`)\n",
+                    fragment.id,
+                    fragment.path,
+                    fragment.sha256.get(..12).unwrap_or(&fragment.sha256)
+                ));
+            }
+        }
+        out.push_str(&format!(
+            "\n### Prompt\n\n{}",
+            code_block("text", &run.prompt)
+        ));
+        out.push_str(&format!(
+            "\n### Response\n\n{}",
+            code_block("text", &run.response)
+        ));
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:960px;margin:2rem auto;padding:0 1rem;color:#222}\
+section{border-top:1px solid #ddd;margin-top:2rem}\
+table{border-collapse:collapse}td,th{text-align:left;padding:.2rem .8rem .2rem 0;vertical-align:top}\
+pre{background:#f6f6f6;padding:.8rem;white-space:pre-wrap;word-break:break-word}\
+code{font-size:.9em}";
+
+fn html_report(runs: &[ExportedRun], masked_types: &[String]) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>PromptForge run report</title>\n<style>{}</style>\n</head>\n<body>\n<h1>PromptForge run report</h1>\n<p>{}</p>\n",
+        HTML_STYLE,
+        escape_html(&report_intro(runs, masked_types))
+    );
+    for run in runs {
+        out.push_str(&format!(
+            "<section>\n<h2>{}</h2>\n<table>\n",
+            escape_html(&run.record.meta.run_id)
+        ));
+        for (label, value) in facts(run) {
+            out.push_str(&format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                label,
+                escape_html(&value)
+            ));
+        }
+        out.push_str("</table>\n");
+        if !run.params.is_null() {
+            let params = serde_json::to_string_pretty(&run.params).unwrap_or_default();
+            out.push_str(&format!(
+                "<h3>Params</h3>\n<pre><code>{}</code></pre>\n",
+                escape_html(&params)
+            ));
+        }
+        if !run.record.meta.fragments.is_empty() {
+            out.push_str("<h3>Fragments</h3>\n<ul>\n");
+            for fragment in &run.record.meta.fragments {
+                out.push_str(&format!(
+                    "<li><code>{}</code> — {} (sha256 <code>{}</code>)</li>\n",
+                    escape_html(&fragment.id),
+                    escape_html(&fragment.path),
+                    escape_html(fragment.sha256.get(..12).unwrap_or(&fragment.sha256))
+                ));
+            }
+            out.push_str("</ul>\n");
+        }
+        out.push_str(&format!(
+            "<h3>Prompt</h3>\n<pre>{}</pre>\n<h3>Response</h3>\n<pre>{}</pre>\n</section>\n",
+            escape_html(&run.prompt),
+            escape_html(&run.response)
+        ));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// One line per run: chat `messages` (system, user, assistant) for
+/// fine-tuning, `prompt`/`completion` for eval tools, plus run metadata.
+fn jsonl_dataset(runs: &[ExportedRun]) -> String {
+    runs.iter()
+        .map(|run| {
+            let meta = &run.record.meta;
+            let (system_text, user_text) = split_final_prompt(&run.prompt);
+            let messages = if user_text.is_empty() {
+                json!([
+                    { "role": "user", "content": system_text },
+                    { "role": "assistant", "content": run.response },
+                ])
+            } else {
+                json!([
+                    { "role": "system", "content": system_text },
+                    { "role": "user", "content": user_text },
+                    { "role": "assistant", "content": run.response },
+                ])
+            };
+            let line = json!({
+                "messages": messages,
+                "prompt": run.prompt,
+                "completion": run.response,
+                "metadata": {
+                    "run_id": meta.run_id,
+                    "status": meta.status,
+                    "model": meta.model,
+                    "recipe_path": meta.recipe_path,
+                    "params": run.params,
+                    "options": meta.options,
+                    "prompt_sha256": meta.prompt_sha256,
+                    "stats": meta.stats,
+                },
+            });
+            format!("{}\n", line)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama_stream::StreamCapture;
+    use crate::runs::{write_stream_run, RunStatus, StreamRunRequest};
+
+    #[test]
+    fn exports_reports_and_datasets_with_secrets_masked() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let request = StreamRunRequest {
+            recipe_path: Some("recipes/demo.yaml".into()),
+            system_text: "Write <b>shots</b>".into(),
+            user_text: "\n---\nUSER_INPUT\nmy token=abcdefghijklmnopqrstu".into(),
+            params: json!({"tone": "calm"}),
+            ..StreamRunRequest::test_default()
+        };
+        let capture = StreamCapture {
+            text: "Shot 1 ```code```".into(),
+            ..StreamCapture::default()
+        };
+        let (run_id, _) = write_stream_run(root, &request, &capture, RunStatus::Completed).unwrap();
+
+        let export = |format| {
+            export_runs(
+                root,
+                &ExportRequest {
+                    format,
+                    ..ExportRequest::default()
+                },
+            )
+            .unwrap()
+        };
+        let markdown = export(ExportFormat::Markdown);
+        assert_eq!(
+            (markdown.runs, markdown.masked_types.clone()),
+            (1, vec!["API_KEY".to_string()])
+        );
+        assert!(markdown.content.contains(&format!("## {}", run_id)));
+        assert!(markdown.content.contains("| Model | llama3:8b |"));
+        assert!(markdown
+            .content
+            .contains("````text\nShot 1 ```code```\n````"));
+        assert!(!markdown.content.contains("abcdefghijklmnopqrstu"));
+
+        let html = export(ExportFormat::Html).content;
+        assert!(html.contains("Write &lt;b&gt;shots&lt;/b&gt;"));
+        assert!(!html.contains("<REDACTED") && html.contains("&lt;REDACTED:API_KEY&gt;"));
+
+        let jsonl = export(ExportFormat::Jsonl).content;
+        let line: Value = serde_json::from_str(jsonl.lines().next().unwrap()).unwrap();
+        assert_eq!(line["messages"][0]["content"], "Write <b>shots</b>");
+        assert_eq!(line["messages"][2]["content"], "Shot 1 ```code```");
+        assert_eq!(line["metadata"]["params"]["tone"], "calm");
+        assert!(line["prompt"]
+            .as_str()
+            .unwrap()
+            .contains("<REDACTED:API_KEY>"));
+
+        let missing = ExportRequest {
+            run_ids: vec!["nope".into()],
+            ..ExportRequest::default()
+        };
+        assert!(export_runs(root, &missing).is_err());
+    }
+}
@@ -0,0 +1,486 @@
+//! Retention for saved runs. Runs hold full prompts and responses, so the
+//! runs folder is trimmed by age, count and total size according to
+//! `<runs_root>/retention.json`. Pinned runs are never removed and do not
+//! count towards the limits. Sweep groups and batch results files go once
+//! every run they list is gone.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::batch::{BatchResult, BATCHES_DIR};
+use crate::run_index::{indexed_run_ids, refresh_run_index};
+use crate::runs::{
+    migrate_legacy_runs, read_run_meta, run_id_millis, run_ids, RunMeta, GROUPS_DIR,
+};
+
+pub const RETENTION_FILE: &str = "retention.json";
+/// Staging folders younger than this may still be written by a running save.
+const STALE_PARTIAL_AGE: Duration = Duration::from_secs(60 * 60);
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+const MB: u64 = 1024 * 1024;
+
+/// Limits applied by [`gc_runs`]; unset limits are not enforced.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Remove runs that started more than this many days ago.
+    pub max_age_days: Option<u64>,
+    /// Keep at most this many (unpinned) runs, newest first.
+    pub max_runs: Option<usize>,
+    /// Keep the newest runs that fit in this many megabytes.
+    pub max_total_mb: Option<u64>,
+    /// Apply the policy when the app starts.
+    pub gc_on_startup: bool,
+}
+
+impl RetentionPolicy {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_age_days.is_none() && self.max_runs.is_none() && self.max_total_mb.is_none()
+    }
+}
+
+/// The saved policy; an empty one when none was saved.
+pub fn read_retention_policy(root: &Path) -> Result<RetentionPolicy, String> {
+    match fs::read_to_string(root.join(RETENTION_FILE)) {
+        Ok(s) => serde_json::from_str(&s).map_err(|e| format!("{}: {}", RETENTION_FILE, e)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(RetentionPolicy::default()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+pub fn write_retention_policy(root: &Path, policy: &RetentionPolicy) -> Result<(), String> {
+    fs::create_dir_all(root).map_err(|e| e.to_string())?;
+    let json = serde_json::to_string_pretty(policy).map_err(|e| e.to_string())?;
+    fs::write(root.join(RETENTION_FILE), json).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GcReason {
+    MaxAge,
+    MaxRuns,
+    MaxTotalSize,
+    /// Every run the group or batch produced is gone.
+    RunsRemoved,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GcRun {
+    pub run_id: String,
+    pub started_at: String,
+    pub bytes: u64,
+    pub reason: GcReason,
+}
+
+/// A sweep group folder or batch results file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GcArtifact {
+    pub path: String,
+    pub bytes: u64,
+    pub reason: GcReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GcReport {
+    /// Nothing was deleted; `removed` lists what would have been.
+    pub dry_run: bool,
+    pub policy: RetentionPolicy,
+    /// Oldest first.
+    pub removed: Vec<GcRun>,
+    /// `groups/<group_id>` folders whose runs are all gone; groups that never
+    /// ran go by `max_age_days`.
+    pub groups: Vec<GcArtifact>,
+    /// `batches/*.jsonl` results files, by the same rule.
+    pub batches: Vec<GcArtifact>,
+    /// Runs, groups and batch results together.
+    pub freed_bytes: u64,
+    pub kept: usize,
+    pub kept_bytes: u64,
+    pub pinned: usize,
+    /// Leftover `.<run_id>.partial` folders from saves that never finished.
+    pub stale_partials: Vec<String>,
+    /// Runs that could not be deleted, with the reason.
+    pub errors: Vec<String>,
+}
+
+/// Total size of the files under `path`.
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map(|m| m.len()).unwrap_or(0),
+        })
+        .sum()
+}
+
+fn started_ms(meta: &RunMeta) -> Option<i64> {
+    DateTime::parse_from_rfc3339(&meta.started_at)
+        .map(|t| t.timestamp_millis())
+        .ok()
+        .or_else(|| run_id_millis(&meta.run_id))
+}
+
+fn modified_ms(path: &Path) -> Option<i64> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(DateTime::<Local>::from(modified).timestamp_millis())
+}
+
+/// Run ids recorded in a batch results file.
+fn result_run_ids(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str::<BatchResult>(line).ok())
+        .filter_map(|result| result.run_id)
+        .collect()
+}
+
+/// Why a group or results file listing `run_ids` should go: all of them are
+/// `gone`, or there were none and it is older than `cutoff`.
+fn artifact_reason(
+    run_ids: &[String],
+    gone: impl Fn(&str) -> bool,
+    modified: Option<i64>,
+    cutoff: Option<i64>,
+) -> Option<GcReason> {
+    if run_ids.is_empty() {
+        cutoff
+            .zip(modified)
+            .is_some_and(|(cutoff, ms)| ms < cutoff)
+            .then_some(GcReason::MaxAge)
+    } else if run_ids.iter().all(|id| gone(id)) {
+        Some(GcReason::RunsRemoved)
+    } else {
+        None
+    }
+}
+
+/// Sweep groups and batch results files that [`gc_runs_in`] removes, given
+/// that `gone` tells which runs no longer exist.
+fn stale_artifacts(
+    root: &Path,
+    batches_dir: &Path,
+    gone: impl Fn(&str) -> bool,
+    cutoff: Option<i64>,
+) -> (Vec<GcArtifact>, Vec<GcArtifact>) {
+    let listed = |dir: &Path, keep: fn(&fs::DirEntry) -> bool| -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .filter(keep)
+                    .map(|entry| entry.path())
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+        paths
+    };
+    let groups = listed(&root.join(GROUPS_DIR), |entry| {
+        entry.file_type().is_ok_and(|t| t.is_dir())
+    })
+    .into_iter()
+    .filter_map(|dir| {
+        let run_ids = result_run_ids(&dir.join("results.jsonl"));
+        let reason = artifact_reason(&run_ids, &gone, modified_ms(&dir), cutoff)?;
+        Some(GcArtifact {
+            bytes: dir_size(&dir),
+            path: dir.display().to_string(),
+            reason,
+        })
+    })
+    .collect();
+    let batches = listed(batches_dir, |entry| {
+        entry.path().extension().is_some_and(|ext| ext == "jsonl")
+            && entry.file_type().is_ok_and(|t| t.is_file())
+    })
+    .into_iter()
+    .filter_map(|file| {
+        let run_ids = result_run_ids(&file);
+        let reason = artifact_reason(&run_ids, &gone, modified_ms(&file), cutoff)?;
+        Some(GcArtifact {
+            bytes: fs::metadata(&file).map(|m| m.len()).unwrap_or(0),
+            path: file.display().to_string(),
+            reason,
+        })
+    })
+    .collect();
+    (groups, batches)
+}
+
+/// Staging folders under `root` older than [`STALE_PARTIAL_AGE`].
+fn stale_partials(root: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .is_some_and(|age| age >= STALE_PARTIAL_AGE)
+        })
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with('.') && name.ends_with(".partial"))
+        .collect();
+    names.sort();
+    names
+}
+
+/// [`gc_runs_in`] with batch results in the working directory's `batches/`,
+/// where batches save them.
+pub fn gc_runs(root: &Path, policy: &RetentionPolicy, dry_run: bool) -> Result<GcReport, String> {
+    gc_runs_in(root, Path::new(BATCHES_DIR), policy, dry_run)
+}
+
+/// Applies `policy` to the runs under `root`. With `dry_run` nothing is
+/// deleted and the report lists what would be. Runs are considered newest
+/// first; once one is over the count or size limit, every older unpinned run
+/// goes too. Runs without a readable `run.json` are left alone. Sweep groups
+/// and the results files in `batches_dir` go once all of their runs are gone
+/// from `root`; files listing runs `root` never had are kept.
+pub fn gc_runs_in(
+    root: &Path,
+    batches_dir: &Path,
+    policy: &RetentionPolicy,
+    dry_run: bool,
+) -> Result<GcReport, String> {
+    let indexed = indexed_run_ids(root);
+    migrate_legacy_runs(root).map_err(|e| e.to_string())?;
+    let mut runs: Vec<(RunMeta, Option<i64>)> = run_ids(root)
+        .map_err(|e| e.to_string())?
+        .iter()
+        .filter_map(|run_id| read_run_meta(root, run_id).ok())
+        .map(|meta| {
+            let started = started_ms(&meta);
+            (meta, started)
+        })
+        .collect();
+    runs.sort_by(|a, b| (b.1, &b.0.run_id).cmp(&(a.1, &a.0.run_id)));
+
+    let now = Local::now().timestamp_millis();
+    let cutoff = policy
+        .max_age_days
+        .map(|days| now - (days as i64).saturating_mul(DAY_MS));
+    let max_bytes = policy.max_total_mb.map(|mb| mb.saturating_mul(MB));
+    let mut report = GcReport {
+        dry_run,
+        policy: policy.clone(),
+        removed: Vec::new(),
+        groups: Vec::new(),
+        batches: Vec::new(),
+        freed_bytes: 0,
+        kept: 0,
+        kept_bytes: 0,
+        pinned: 0,
+        stale_partials: stale_partials(root),
+        errors: Vec::new(),
+    };
+    let mut over_limit: Option<GcReason> = None;
+    for (meta, started) in runs {
+        let bytes = dir_size(&root.join(&meta.run_id));
+        if meta.pinned {
+            report.pinned += 1;
+            continue;
+        }
+        let reason = if cutoff.zip(started).is_some_and(|(cutoff, ms)| ms < cutoff) {
+            Some(GcReason::MaxAge)
+        } else if over_limit.is_some() {
+            over_limit
+        } else if policy.max_runs.is_some_and(|max| report.kept >= max) {
+            over_limit = Some(GcReason::MaxRuns);
+            over_limit
+        } else if max_bytes.is_some_and(|max| report.kept_bytes + bytes > max) {
+            over_limit = Some(GcReason::MaxTotalSize);
+            over_limit
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => report.removed.push(GcRun {
+                run_id: meta.run_id,
+                started_at: meta.started_at,
+                bytes,
+                reason,
+            }),
+            None => {
+                report.kept += 1;
+                report.kept_bytes += bytes;
+            }
+        }
+    }
+    report.removed.reverse();
+
+    if !dry_run {
+        let mut failed = Vec::new();
+        for run in &report.removed {
+            if let Err(err) = fs::remove_dir_all(root.join(&run.run_id)) {
+                report.errors.push(format!("{}: {}", run.run_id, err));
+                failed.push(run.run_id.clone());
+            }
+        }
+        report.removed.retain(|run| !failed.contains(&run.run_id));
+        for name in &report.stale_partials {
+            if let Err(err) = fs::remove_dir_all(root.join(name)) {
+                report.errors.push(format!("{}: {}", name, err));
+            }
+        }
+    }
+
+    // Results may list runs saved under another root; only runs this pass
+    // removed, or that the index knew here and are missing now, count as gone.
+    let gone = |run_id: &str| {
+        report.removed.iter().any(|run| run.run_id == run_id)
+            || (indexed.contains(run_id) && !root.join(run_id).is_dir())
+    };
+    let (mut groups, mut batches) = stale_artifacts(root, batches_dir, gone, cutoff);
+    if !dry_run {
+        let mut remove = |artifacts: &mut Vec<GcArtifact>, is_dir: bool| {
+            artifacts.retain(|artifact| {
+                let removed = if is_dir {
+                    fs::remove_dir_all(&artifact.path)
+                } else {
+                    fs::remove_file(&artifact.path)
+                };
+                match removed {
+                    Ok(()) => true,
+                    Err(err) => {
+                        report.errors.push(format!("{}: {}", artifact.path, err));
+                        false
+                    }
+                }
+            });
+        };
+        remove(&mut groups, true);
+        remove(&mut batches, false);
+        refresh_run_index(root).map_err(|e| e.to_string())?;
+    }
+    report.freed_bytes = report.removed.iter().map(|run| run.bytes).sum::<u64>()
+        + groups.iter().chain(&batches).map(|a| a.bytes).sum::<u64>();
+    report.groups = groups;
+    report.batches = batches;
+    Ok(report)
+}
+
+/// Runs the saved policy when it asks to be applied at startup; `None` when
+/// it does not.
+pub fn gc_on_startup(root: &Path) -> Result<Option<GcReport>, String> {
+    let policy = read_retention_policy(root)?;
+    if !policy.gc_on_startup || policy.is_unlimited() {
+        return Ok(None);
+    }
+    gc_runs(root, &policy, false).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama_stream::StreamCapture;
+    use crate::runs::{set_run_pinned, write_stream_run, RunStatus, StreamRunRequest};
+    use chrono::Duration as ChronoDuration;
+    use serde_json::json;
+
+    fn save(root: &Path, days_ago: i64, bytes: usize) -> String {
+        let request = StreamRunRequest {
+            started_at: (Local::now() - ChronoDuration::days(days_ago)).to_rfc3339(),
+            ..StreamRunRequest::test_default()
+        };
+        let capture = StreamCapture {
+            text: "x".repeat(bytes),
+            ..StreamCapture::default()
+        };
+        write_stream_run(root, &request, &capture, RunStatus::Completed)
+            .unwrap()
+            .0
+    }
+
+    fn write_results(path: &Path, run_ids: &[&str]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let lines: Vec<String> = run_ids
+            .iter()
+            .map(|run_id| {
+                json!({"row_id": run_id, "status": "completed", "sha256": null,
+                       "run_id": run_id, "params": {}, "finished_at": ""})
+                .to_string()
+            })
+            .collect();
+        fs::write(path, lines.join("\n")).unwrap();
+    }
+
+    #[test]
+    fn applies_age_count_and_size_limits_sparing_pinned_runs() {
+        let temp = tempfile::tempdir().unwrap();
+        let (root, batches) = (&temp.path().join("runs"), &temp.path().join("batches"));
+        let ancient = save(root, 400, 10);
+        let pinned = save(root, 300, 10);
+        set_run_pinned(root, &pinned, true).unwrap();
+        let old = save(root, 20, 10);
+        let recent = save(root, 2, 10);
+        let newest = save(root, 0, 10);
+        let ids = |report: &GcReport| -> Vec<String> {
+            report.removed.iter().map(|r| r.run_id.clone()).collect()
+        };
+        let sweep = root.join(GROUPS_DIR).join("sweep-old");
+        write_results(&sweep.join("results.jsonl"), &[&ancient, &old]);
+        fs::create_dir_all(root.join(GROUPS_DIR).join("sweep-unrun")).unwrap();
+        write_results(&batches.join("echo.jsonl"), &[&recent, &newest]);
+        // A batch whose runs were saved under another root.
+        write_results(
+            &batches.join("elsewhere.jsonl"),
+            &["01ARZ3NDEKTSV4RRFFQ69G5FAV"],
+        );
+
+        let by_age = RetentionPolicy {
+            max_age_days: Some(30),
+            ..RetentionPolicy::default()
+        };
+        let report = gc_runs_in(root, batches, &by_age, true).unwrap();
+        assert_eq!(ids(&report), [ancient.as_str()]);
+        assert_eq!(report.removed[0].reason, GcReason::MaxAge);
+        assert_eq!((report.kept, report.pinned), (3, 1));
+        assert!(report.groups.is_empty() && report.batches.is_empty());
+        assert!(root.join(&ancient).exists(), "dry run deletes nothing");
+
+        let by_count = RetentionPolicy {
+            max_runs: Some(2),
+            ..by_age.clone()
+        };
+        let report = gc_runs_in(root, batches, &by_count, false).unwrap();
+        assert_eq!(ids(&report), [ancient.as_str(), old.as_str()]);
+        assert_eq!(report.removed[1].reason, GcReason::MaxRuns);
+        assert!(!root.join(&old).exists() && root.join(&pinned).exists());
+        // The sweep's runs are all gone; the batch's are still here, and the
+        // group that never ran is younger than the age limit.
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].reason, GcReason::RunsRemoved);
+        assert!(!sweep.exists() && root.join(GROUPS_DIR).join("sweep-unrun").exists());
+        assert!(report.batches.is_empty() && batches.join("echo.jsonl").exists());
+        assert!(batches.join("elsewhere.jsonl").exists());
+        let indexed = refresh_run_index(root).unwrap();
+        assert_eq!(indexed.runs.len(), 3);
+
+        let size = dir_size(&root.join(&recent)) + dir_size(&root.join(&newest));
+        let by_size = RetentionPolicy {
+            max_total_mb: Some(0),
+            ..RetentionPolicy::default()
+        };
+        let report = gc_runs_in(root, batches, &by_size, true).unwrap();
+        assert_eq!(ids(&report), [recent.as_str(), newest.as_str()]);
+        assert_eq!(report.removed[0].reason, GcReason::MaxTotalSize);
+        assert_eq!(report.batches.len(), 1);
+        let batch_bytes = report.batches[0].bytes;
+        assert_eq!(report.freed_bytes, size + batch_bytes);
+    }
+}
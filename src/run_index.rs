@@ -0,0 +1,469 @@
+//! Browsing and full-text search over saved runs.
+//!
+//! `<runs_root>/index.json` keeps a summary plus the prompt and response text
+//! of every run. Each query first syncs it with the run folders (new runs are
+//! read, deleted ones dropped), so the index is a cache: a missing or outdated
+//! file is simply rebuilt.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::runs::{migrate_legacy_runs, read_run_meta, run_id_millis, run_ids, RunMeta, RunStatus};
+
+pub const RUN_INDEX_FILE: &str = "index.json";
+const RUN_INDEX_VERSION: u32 = 2;
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+/// Characters of context kept on each side of a search match.
+const SNIPPET_CONTEXT: usize = 60;
+
+/// The parts of `run.json` shown in run lists.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub run_id: String,
+    pub status: RunStatus,
+    pub model: String,
+    #[serde(default)]
+    pub recipe_path: Option<String>,
+    pub started_at: String,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub group_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_of: Option<String>,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl From<&RunMeta> for RunSummary {
+    fn from(meta: &RunMeta) -> Self {
+        RunSummary {
+            run_id: meta.run_id.clone(),
+            status: meta.status,
+            model: meta.model.clone(),
+            recipe_path: meta.recipe_path.clone(),
+            started_at: meta.started_at.clone(),
+            duration_ms: meta.duration_ms,
+            group_id: meta.group_id.clone(),
+            replay_of: meta.replay_of.clone(),
+            pinned: meta.pinned,
+        }
+    }
+}
+
+impl RunSummary {
+    /// Unix milliseconds the run started, from `started_at` or else its id.
+    fn started_ms(&self) -> Option<i64> {
+        DateTime::parse_from_rfc3339(&self.started_at)
+            .map(|t| t.timestamp_millis())
+            .ok()
+            .or_else(|| run_id_millis(&self.run_id))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedRun {
+    #[serde(flatten)]
+    pub summary: RunSummary,
+    pub prompt: String,
+    pub response: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunIndex {
+    pub version: u32,
+    /// Run id to its indexed text.
+    pub runs: BTreeMap<String, IndexedRun>,
+}
+
+/// Filters and paging shared by [`list_runs`] and [`search_runs`]; every
+/// filter is optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RunQuery {
+    /// Part of the recipe path, e.g. `demo.sora2`.
+    pub recipe: Option<String>,
+    pub model: Option<String>,
+    pub status: Option<RunStatus>,
+    /// `YYYY-MM-DD` (local time; `until` includes the whole day) or RFC 3339.
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub offset: usize,
+    /// Defaults to [`DEFAULT_PAGE_SIZE`].
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunPage<T> {
+    pub items: Vec<T>,
+    /// Matches before paging.
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunSearchHit {
+    #[serde(flatten)]
+    pub run: RunSummary,
+    /// `prompt` or `response`: where the snippet was taken from.
+    pub field: &'static str,
+    pub snippet: String,
+    /// Occurrences of the query terms in the prompt and response.
+    pub score: usize,
+}
+
+/// Everything saved for one run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunRecord {
+    pub meta: RunMeta,
+    pub prompt: String,
+    pub response: String,
+    /// Parsed `response.json` for schema-constrained runs.
+    pub structured: Option<Value>,
+}
+
+fn read_text(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name)).ok()
+}
+
+/// `response.txt`, or the raw response of runs saved by the UI.
+fn response_text(dir: &Path) -> String {
+    read_text(dir, "response.txt")
+        .or_else(|| read_text(dir, "response.raw.jsonl"))
+        .unwrap_or_default()
+}
+
+pub fn read_run(root: &Path, run_id: &str) -> io::Result<RunRecord> {
+    let dir = root.join(run_id);
+    // Legacy runs may not have been given a `run.json` yet.
+    if !dir.join("run.json").exists() {
+        migrate_legacy_runs(root)?;
+    }
+    let meta = read_run_meta(root, run_id)?;
+    Ok(RunRecord {
+        prompt: read_text(&dir, "prompt.final.txt").unwrap_or_default(),
+        response: response_text(&dir),
+        structured: read_text(&dir, "response.json").and_then(|s| serde_json::from_str(&s).ok()),
+        meta,
+    })
+}
+
+fn index_run(root: &Path, run_id: &str) -> Option<IndexedRun> {
+    let record = read_run(root, run_id).ok()?;
+    Some(IndexedRun {
+        summary: RunSummary::from(&record.meta),
+        prompt: record.prompt,
+        response: record.response,
+    })
+}
+
+/// Loads `index.json`, brings it in line with the run folders and saves it if
+/// anything changed. A missing, unreadable or older-version index is rebuilt.
+pub fn refresh_run_index(root: &Path) -> io::Result<RunIndex> {
+    migrate_legacy_runs(root)?;
+    let loaded = read_text(root, RUN_INDEX_FILE)
+        .and_then(|s| serde_json::from_str::<RunIndex>(&s).ok())
+        .filter(|index| index.version == RUN_INDEX_VERSION);
+    let mut changed = loaded.is_none();
+    let mut index = loaded.unwrap_or(RunIndex {
+        version: RUN_INDEX_VERSION,
+        runs: BTreeMap::new(),
+    });
+
+    let ids: BTreeSet<String> = run_ids(root)?.into_iter().collect();
+    let before = index.runs.len();
+    index.runs.retain(|run_id, _| ids.contains(run_id));
+    changed |= index.runs.len() != before;
+    for run_id in ids {
+        if index.runs.contains_key(&run_id) {
+            continue;
+        }
+        if let Some(run) = index_run(root, &run_id) {
+            index.runs.insert(run_id, run);
+            changed = true;
+        }
+    }
+
+    if changed && root.is_dir() {
+        save_index(root, &index)?;
+    }
+    Ok(index)
+}
+
+/// Run ids in `index.json` as last saved, without syncing it with the run
+/// folders; empty when there is no usable index.
+pub fn indexed_run_ids(root: &Path) -> BTreeSet<String> {
+    read_text(root, RUN_INDEX_FILE)
+        .and_then(|s| serde_json::from_str::<RunIndex>(&s).ok())
+        .filter(|index| index.version == RUN_INDEX_VERSION)
+        .map(|index| index.runs.into_keys().collect())
+        .unwrap_or_default()
+}
+
+fn save_index(root: &Path, index: &RunIndex) -> io::Result<()> {
+    let path = root.join(RUN_INDEX_FILE);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec(index).map_err(io::Error::other)?)?;
+    fs::rename(&tmp, &path)
+}
+
+/// Re-reads run `run_id` into the index after its `run.json` was edited in
+/// place (the sync in [`refresh_run_index`] only notices added and removed runs).
+pub fn reindex_run(root: &Path, run_id: &str) -> io::Result<()> {
+    let mut index = refresh_run_index(root)?;
+    match index_run(root, run_id) {
+        Some(run) => index.runs.insert(run_id.to_string(), run),
+        None => index.runs.remove(run_id),
+    };
+    save_index(root, &index)
+}
+
+/// Parses a `since`/`until` bound into Unix milliseconds.
+fn parse_bound(value: &str, end_of_day: bool) -> Result<i64, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.timestamp_millis());
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("invalid date `{}` (use YYYY-MM-DD or RFC 3339)", value))?;
+    let day = if end_of_day {
+        date.succ_opt().unwrap_or(date)
+    } else {
+        date
+    };
+    let midnight = Local
+        .from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap_or_default())
+        .earliest()
+        .ok_or_else(|| format!("invalid date `{}`", value))?;
+    Ok(midnight.timestamp_millis() - i64::from(end_of_day))
+}
+
+/// Runs matching `query`'s filters, newest first.
+fn filtered<'a>(index: &'a RunIndex, query: &RunQuery) -> Result<Vec<&'a IndexedRun>, String> {
+    let since = query
+        .since
+        .as_deref()
+        .map(|v| parse_bound(v, false))
+        .transpose()?;
+    let until = query
+        .until
+        .as_deref()
+        .map(|v| parse_bound(v, true))
+        .transpose()?;
+    let mut runs: Vec<&IndexedRun> = index
+        .runs
+        .values()
+        .filter(|run| {
+            let s = &run.summary;
+            let started = s.started_ms();
+            query.recipe.as_deref().is_none_or(|recipe| {
+                s.recipe_path
+                    .as_deref()
+                    .is_some_and(|path| path.contains(recipe))
+            }) && query.model.as_deref().is_none_or(|model| s.model == model)
+                && query.status.is_none_or(|status| s.status == status)
+                && since.is_none_or(|since| started.is_some_and(|t| t >= since))
+                && until.is_none_or(|until| started.is_some_and(|t| t <= until))
+        })
+        .collect();
+    runs.sort_by_key(|run| {
+        std::cmp::Reverse((run.summary.started_ms(), run.summary.run_id.clone()))
+    });
+    Ok(runs)
+}
+
+fn page<T>(items: Vec<T>, query: &RunQuery) -> RunPage<T> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let total = items.len();
+    RunPage {
+        items: items.into_iter().skip(query.offset).take(limit).collect(),
+        total,
+        offset: query.offset,
+        limit,
+    }
+}
+
+/// One page of runs matching `query`, newest first.
+pub fn list_runs(root: &Path, query: &RunQuery) -> Result<RunPage<RunSummary>, String> {
+    let index = refresh_run_index(root).map_err(|e| e.to_string())?;
+    let runs = filtered(&index, query)?
+        .into_iter()
+        .map(|run| run.summary.clone())
+        .collect();
+    Ok(page(runs, query))
+}
+
+/// Lowercases char by char so positions line up with the original text.
+fn fold(text: &str) -> Vec<char> {
+    text.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+/// Start positions (in chars) of `term` in `text`.
+fn occurrences(text: &[char], term: &[char]) -> Vec<usize> {
+    if term.is_empty() || term.len() > text.len() {
+        return Vec::new();
+    }
+    (0..=text.len() - term.len())
+        .filter(|&i| text[i..i + term.len()] == *term)
+        .collect()
+}
+
+fn snippet(text: &str, at: usize, len: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let start = at.saturating_sub(SNIPPET_CONTEXT);
+    let end = (at + len + SNIPPET_CONTEXT).min(chars.len());
+    let mut out: String = chars[start..end].iter().collect();
+    out = out.split_whitespace().collect::<Vec<_>>().join(" ");
+    if start > 0 {
+        out.insert(0, '…');
+    }
+    if end < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+/// Runs whose prompt or response contains every whitespace-separated term of
+/// `text` (case-insensitive), best matches first, then newest.
+pub fn search_runs(
+    root: &Path,
+    text: &str,
+    query: &RunQuery,
+) -> Result<RunPage<RunSearchHit>, String> {
+    let terms: Vec<Vec<char>> = text.split_whitespace().map(fold).collect();
+    if terms.is_empty() {
+        return Err("search text is empty".into());
+    }
+    let index = refresh_run_index(root).map_err(|e| e.to_string())?;
+    let mut hits: Vec<RunSearchHit> = filtered(&index, query)?
+        .into_iter()
+        .filter_map(|run| {
+            let fields = [
+                ("response", &run.response, fold(&run.response)),
+                ("prompt", &run.prompt, fold(&run.prompt)),
+            ];
+            let mut score = 0;
+            for term in &terms {
+                let found: usize = fields
+                    .iter()
+                    .map(|(_, _, folded)| occurrences(folded, term).len())
+                    .sum();
+                if found == 0 {
+                    return None;
+                }
+                score += found;
+            }
+            let (field, original, at) = fields.iter().find_map(|(field, original, folded)| {
+                occurrences(folded, &terms[0])
+                    .first()
+                    .map(|&at| (*field, *original, at))
+            })?;
+            Some(RunSearchHit {
+                run: run.summary.clone(),
+                field,
+                snippet: snippet(original, at, terms[0].len()),
+                score,
+            })
+        })
+        .collect();
+    // `filtered` already sorted newest first; the stable sort keeps that order
+    // among equal scores.
+    hits.sort_by_key(|hit| std::cmp::Reverse(hit.score));
+    Ok(page(hits, query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama_stream::StreamCapture;
+    use crate::runs::{write_stream_run, StreamRunRequest};
+
+    fn save(root: &Path, model: &str, recipe: &str, prompt: &str, response: &str) -> String {
+        let request = StreamRunRequest {
+            model: model.into(),
+            recipe_path: Some(recipe.into()),
+            system_text: prompt.into(),
+            ..StreamRunRequest::test_default()
+        };
+        let capture = StreamCapture {
+            text: response.into(),
+            ..StreamCapture::default()
+        };
+        write_stream_run(root, &request, &capture, RunStatus::Completed)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn lists_filters_and_searches_runs() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let root = temp.path();
+        let first = save(
+            root,
+            "llama3:8b",
+            "recipes/storyboard.yaml",
+            "Plan a storyboard",
+            "Shot 1: a quiet harbour at dawn",
+        );
+        let second = save(
+            root,
+            "qwen2:7b",
+            "recipes/demo.sora2.yaml",
+            "Describe the harbour",
+            "Harbour lights",
+        );
+
+        let all = list_runs(root, &RunQuery::default()).unwrap();
+        assert_eq!(all.total, 2);
+        assert_eq!(all.items[0].run_id, second);
+        assert!(root.join(RUN_INDEX_FILE).is_file());
+
+        let query = RunQuery {
+            recipe: Some("storyboard".into()),
+            since: Some(Local::now().format("%Y-%m-%d").to_string()),
+            until: Some(Local::now().format("%Y-%m-%d").to_string()),
+            ..RunQuery::default()
+        };
+        let page = list_runs(root, &query).unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].run_id, first);
+        let paged = RunQuery {
+            offset: 1,
+            limit: Some(1),
+            ..RunQuery::default()
+        };
+        assert_eq!(list_runs(root, &paged).unwrap().items[0].run_id, first);
+        let bad = RunQuery {
+            since: Some("last tuesday".into()),
+            ..RunQuery::default()
+        };
+        assert!(list_runs(root, &bad).is_err());
+
+        let hits = search_runs(root, "HARBOUR dawn", &RunQuery::default()).unwrap();
+        assert_eq!(hits.total, 1);
+        assert_eq!(hits.items[0].run.run_id, first);
+        assert_eq!(hits.items[0].field, "response");
+        assert!(hits.items[0].snippet.contains("harbour at dawn"));
+        let hits = search_runs(root, "harbour", &RunQuery::default()).unwrap();
+        assert_eq!(hits.total, 2);
+        assert_eq!(hits.items[0].run.run_id, second);
+
+        // The index is a cache: deleted runs drop out, a lost index is rebuilt.
+        fs::remove_dir_all(root.join(&second)).unwrap();
+        fs::remove_file(root.join(RUN_INDEX_FILE)).unwrap();
+        let record = read_run(root, &first).unwrap();
+        assert_eq!(record.response, "Shot 1: a quiet harbour at dawn");
+        let all = list_runs(root, &RunQuery::default()).unwrap();
+        assert_eq!(all.total, 1);
+        assert_eq!(all.items[0].run_id, first);
+        assert!(root.join(RUN_INDEX_FILE).is_file());
+    }
+}
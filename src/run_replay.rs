@@ -0,0 +1,343 @@
+//! Re-running a saved run: the recipe is re-composed from the recorded path
+//! and params and sent with the recorded profile (model, options, seed). The
+//! new run's `run.json` names the original in `replay_of`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::chat::{spawn_chat_stream, ChatStreamJob};
+use crate::compose::{compose_prompt_in, split_final_prompt, ComposeResult, FragmentSource};
+use crate::ollama_stream::{EventSink, StreamState};
+use crate::profiles::{resolve_profile_in, Profile};
+use crate::run_index::{read_run, RunRecord};
+use crate::runs::RunStatus;
+
+/// What to change on replay; empty overrides reproduce the original.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ReplayOverrides {
+    /// Model or profile to run instead. Only the model and its backend change;
+    /// the recorded temperature, seed and other options are kept.
+    pub model: Option<String>,
+    /// Merged over the recorded params.
+    pub params: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReplayReport {
+    pub original_run_id: String,
+    pub run_id: Option<String>,
+    pub status: RunStatus,
+    pub response: String,
+    /// Fragment drift and other reasons the replay may not match the original.
+    pub warnings: Vec<String>,
+    pub save_error: Option<String>,
+}
+
+/// A replay ready to stream, and what the caller should be told first.
+pub struct PreparedReplay {
+    pub job: ChatStreamJob,
+    pub warnings: Vec<String>,
+}
+
+fn short(sha: &str) -> &str {
+    sha.get(..12).unwrap_or(sha)
+}
+
+/// Recorded fragments whose file is gone or changed, and fragments the recipe
+/// gained since.
+fn fragment_warnings(recorded: &[FragmentSource], current: &[FragmentSource]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for old in recorded {
+        match current.iter().find(|f| f.id == old.id) {
+            None => warnings.push(format!("fragment `{}` is no longer in the recipe", old.id)),
+            Some(new) if new.sha256 != old.sha256 => warnings.push(format!(
+                "fragment `{}` changed since the run ({}: {} -> {})",
+                old.id,
+                new.path,
+                short(&old.sha256),
+                short(&new.sha256)
+            )),
+            Some(_) => {}
+        }
+    }
+    for new in current {
+        if !recorded.iter().any(|f| f.id == new.id) {
+            warnings.push(format!("fragment `{}` was added to the recipe", new.id));
+        }
+    }
+    warnings
+}
+
+/// The system and user text run `record` sent, split where its `run.json`
+/// says; `None` when it did not record that.
+fn recorded_prompt(record: &RunRecord) -> Option<(String, String)> {
+    let at = record.meta.system_len?;
+    if !record.prompt.is_char_boundary(at) {
+        return None;
+    }
+    let (system_text, user_text) = record.prompt.split_at(at);
+    Some((system_text.to_string(), user_text.to_string()))
+}
+
+/// The recorded profile with `model`'s model, backend and URL swapped in.
+fn override_model(data_dir: &Path, recorded: Profile, model: &str) -> Result<Profile, String> {
+    let other = resolve_profile_in(data_dir, model).map_err(|e| e.to_string())?;
+    Ok(Profile {
+        model: other.model,
+        backend: other.backend,
+        base_url: other.base_url,
+        ..recorded
+    })
+}
+
+/// Reads run `run_id` from `runs_root` and builds the job that replays it
+/// against the recipes in `data_dir`.
+pub fn prepare_replay(
+    data_dir: &Path,
+    runs_root: &Path,
+    run_id: &str,
+    overrides: &ReplayOverrides,
+) -> Result<PreparedReplay, String> {
+    let record = read_run(runs_root, run_id).map_err(|e| format!("{}: {}", run_id, e))?;
+    let meta = &record.meta;
+    let mut warnings = Vec::new();
+
+    let recipe = meta.recipe_path.clone().filter(|p| !p.is_empty());
+    let composed: Option<ComposeResult> = match (&recipe, &meta.params) {
+        (Some(recipe), Value::Object(recorded)) => {
+            let mut params = recorded.clone();
+            params.extend(overrides.params.clone());
+            let composed = compose_prompt_in(data_dir, recipe, Some(Value::Object(params)))
+                .map_err(|e| format!("{}: {}", recipe, e))?;
+            if meta.fragments.is_empty() {
+                warnings.push("the run recorded no fragment hashes; drift is not checked".into());
+            }
+            warnings.extend(fragment_warnings(&meta.fragments, &composed.fragments));
+            let reproduced = meta.prompt_sha256.as_deref() == Some(composed.sha256.as_str());
+            if overrides.params.is_empty() && !reproduced {
+                warnings.push("the re-composed prompt differs from the recorded one".into());
+            }
+            Some(composed)
+        }
+        _ if !overrides.params.is_empty() => {
+            return Err(format!(
+                "run {} has no recorded recipe params to override",
+                run_id
+            ))
+        }
+        _ => {
+            warnings.push("the run recorded no recipe params; re-sending its saved prompt".into());
+            None
+        }
+    };
+
+    let recorded_profile = match &meta.profile {
+        Some(profile) => profile.clone(),
+        None => {
+            warnings.push(format!(
+                "the run recorded no profile; using the current one for `{}`",
+                meta.model
+            ));
+            resolve_profile_in(data_dir, &meta.model).map_err(|e| e.to_string())?
+        }
+    };
+    let profile = match &overrides.model {
+        Some(model) => override_model(data_dir, recorded_profile, model)?,
+        None => recorded_profile,
+    };
+    if !meta.images.is_empty() {
+        warnings.push("images are not stored with runs; replaying without them".into());
+    }
+
+    let (system_text, user_text) = match &composed {
+        Some(composed) => split_final_prompt(&composed.final_prompt),
+        None => recorded_prompt(&record).unwrap_or_else(|| {
+            warnings.push(
+                "the run did not record where its system prompt ends; splitting at USER_INPUT"
+                    .into(),
+            );
+            split_final_prompt(&record.prompt)
+        }),
+    };
+    let schema = fs::read_to_string(runs_root.join(run_id).join("schema.json"))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .or_else(|| composed.as_ref().and_then(|c| c.output_schema.clone()));
+    let mut job = ChatStreamJob::new(&profile, system_text, user_text, recipe, Vec::new(), schema);
+    if let Some(composed) = &composed {
+        job = job.with_composition(composed);
+    }
+    job.run_request.replay_of = Some(meta.run_id.clone());
+    job.runs_root = runs_root.to_path_buf();
+    Ok(PreparedReplay { job, warnings })
+}
+
+/// Replays run `run_id` and waits for the new run to be saved; chunks are
+/// reported through `sink` as for any other stream.
+pub async fn replay_run(
+    sink: Arc<dyn EventSink>,
+    state: &StreamState,
+    data_dir: &Path,
+    runs_root: PathBuf,
+    run_id: &str,
+    overrides: &ReplayOverrides,
+) -> Result<ReplayReport, String> {
+    let PreparedReplay { job, warnings } = prepare_replay(data_dir, &runs_root, run_id, overrides)?;
+    let (_, done) = spawn_chat_stream(sink, state, job).await?;
+    let outcome = done
+        .await
+        .map_err(|_| "stream ended without an outcome".to_string())?;
+    Ok(ReplayReport {
+        original_run_id: run_id.to_string(),
+        run_id: outcome.run_id,
+        status: outcome.status,
+        response: outcome.partial_text,
+        warnings,
+        save_error: outcome.save_error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ollama::{MockConfig, MockOllama};
+    use crate::ollama_stream::ChannelSink;
+    use crate::runs::read_run_meta;
+    use crate::test_support::{data_fixture, write};
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn replays_with_recorded_settings_and_flags_drift() {
+        let temp = tempfile::tempdir().unwrap();
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        let data = data_fixture(temp.path(), &mock.url());
+        let runs_root = temp.path().join("runs");
+        write(
+            &data,
+            "profiles/mock.yaml",
+            &format!(
+                "model: llama3:8b\nbase_url: {}\ntemperature: 0.3\nseed: 11\n",
+                mock.url()
+            ),
+        );
+        let state = StreamState::default();
+        let (tx, _events) = mpsc::unbounded_channel();
+        let sink: Arc<dyn EventSink> = Arc::new(ChannelSink(tx));
+
+        // An original run, as the app would save it.
+        let composed = compose_prompt_in(
+            &data,
+            "recipes/echo.yaml",
+            Some(json!({"tone": "dry", "user_input": "hi"})),
+        )
+        .unwrap();
+        let profile = resolve_profile_in(&data, "mock").unwrap();
+        let (system_text, user_text) = split_final_prompt(&composed.final_prompt);
+        let mut job = ChatStreamJob::new(
+            &profile,
+            system_text,
+            user_text,
+            Some("recipes/echo.yaml".into()),
+            Vec::new(),
+            None,
+        )
+        .with_composition(&composed);
+        job.runs_root = runs_root.clone();
+        let (_, done) = spawn_chat_stream(sink.clone(), &state, job).await.unwrap();
+        let original = done.await.unwrap().run_id.unwrap();
+
+        // The profile changes afterwards; the replay keeps the recorded one.
+        write(
+            &data,
+            "profiles/mock.yaml",
+            &format!("model: other:1b\nbase_url: {}\n", mock.url()),
+        );
+        let report = replay_run(
+            sink.clone(),
+            &state,
+            &data,
+            runs_root.clone(),
+            &original,
+            &ReplayOverrides::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.status, RunStatus::Completed);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        let chats = mock.requests_to("/api/chat");
+        assert_eq!(chats[1].body["messages"], chats[0].body["messages"]);
+        assert_eq!(chats[1].body["model"], "llama3:8b");
+        assert_eq!(chats[1].body["options"]["seed"], 11);
+        let meta = read_run_meta(&runs_root, report.run_id.as_deref().unwrap()).unwrap();
+        assert_eq!(meta.replay_of.as_deref(), Some(original.as_str()));
+        assert_eq!(meta.params["tone"], "dry");
+
+        // Overrides, with a fragment edited since the original run.
+        write(
+            &data,
+            "fragments/task/echo.yaml",
+            "id: task.echo\nkind: task\ncontent: \"Reply in a {{tone}} tone.\"\n",
+        );
+        let overrides = ReplayOverrides {
+            model: Some("qwen2:7b".into()),
+            params: Map::from_iter([("tone".to_string(), json!("warm"))]),
+        };
+        let prepared = prepare_replay(&data, &runs_root, &original, &overrides).unwrap();
+        assert_eq!(prepared.warnings.len(), 1);
+        assert!(prepared.warnings[0].starts_with("fragment `task.echo` changed"));
+        assert_eq!(prepared.job.request.model, "qwen2:7b");
+        assert!(prepared.job.run_request.system_text.contains("warm"));
+        assert_eq!(prepared.job.run_request.profile.unwrap().seed, Some(11));
+    }
+
+    #[tokio::test]
+    async fn replays_raw_prompts_with_the_recorded_system_and_user_text() {
+        let temp = tempfile::tempdir().unwrap();
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        let data = data_fixture(temp.path(), &mock.url());
+        let runs_root = temp.path().join("runs");
+        let state = StreamState::default();
+        let (tx, _events) = mpsc::unbounded_channel();
+        let sink: Arc<dyn EventSink> = Arc::new(ChannelSink(tx));
+
+        // A chat without a recipe, as the proxy sends it: no USER_INPUT
+        // delimiter between the system prompt and the user message.
+        let profile = resolve_profile_in(&data, "mock").unwrap();
+        let mut job = ChatStreamJob::new(
+            &profile,
+            "Be terse.".into(),
+            "Name a colour.".into(),
+            None,
+            Vec::new(),
+            None,
+        );
+        job.runs_root = runs_root.clone();
+        let (_, done) = spawn_chat_stream(sink.clone(), &state, job).await.unwrap();
+        let original = done.await.unwrap().run_id.unwrap();
+
+        let report = replay_run(
+            sink,
+            &state,
+            &data,
+            runs_root,
+            &original,
+            &ReplayOverrides::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.status, RunStatus::Completed);
+        assert_eq!(
+            report.warnings,
+            ["the run recorded no recipe params; re-sending its saved prompt"]
+        );
+        let chats = mock.requests_to("/api/chat");
+        assert_eq!(chats[1].body["messages"], chats[0].body["messages"]);
+        assert_eq!(chats[1].body["messages"][0]["content"], "Be terse.");
+    }
+}
@@ -0,0 +1,822 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::attachments::ImageAttachment;
+use crate::compose::{matching_composition, sha256_hex, split_final_prompt, FragmentSource};
+use crate::ollama_client::OllamaError;
+use crate::ollama_stream::{GenerationStats, StreamCapture};
+use crate::profiles::{resolve_profile_in, Profile};
+
+pub const RUNS_DIR: &str = "runs";
+pub const RUNS_DIR_ENV: &str = "PROMPTFORGE_RUNS_DIR";
+/// Sweep and batch group folders live here, next to the runs.
+pub const GROUPS_DIR: &str = "groups";
+pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Matches `identifier` in `tauri.conf.json`, so the CLI and the app share
+/// the same data dir.
+pub const APP_IDENTIFIER: &str = "com.promptforge.app";
+/// Folder names of runs saved before run ids became ULIDs.
+const LEGACY_RUN_ID_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Completed,
+    Aborted,
+    Failed,
+    /// The reply never matched the requested JSON schema, even after repair.
+    SchemaInvalid,
+}
+
+/// Metadata written to `run.json` next to the prompt and response files: enough
+/// to tell later which recipe, fragments, settings and server produced a
+/// response. Fields added after the first release are optional so older runs
+/// still load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunMeta {
+    pub run_id: String,
+    pub status: RunStatus,
+    pub model: String,
+    #[serde(default)]
+    pub recipe_path: Option<String>,
+    pub started_at: String,
+    pub finished_at: String,
+    #[serde(default)]
+    pub error: Option<OllamaError>,
+    #[serde(default)]
+    pub stats: Option<GenerationStats>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageAttachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schema_errors: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub repair_attempts: u32,
+    /// Sweep or batch this run belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+    /// The profile `model` resolved to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+    /// Options sent with the request (see `Profile::ollama_options`).
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub options: Value,
+    /// Recipe params after inline overrides; absent for raw prompts.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+    /// sha256 of `prompt.final.txt`.
+    #[serde(default)]
+    pub prompt_sha256: Option<String>,
+    /// Bytes of `prompt.final.txt` sent as the system prompt; the rest was the
+    /// user message. Absent for runs saved before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_len: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fragments: Vec<FragmentSource>,
+    /// Wall time from `started_at` to `finished_at`.
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub app_version: Option<String>,
+    /// As reported by `/api/version`; `None` for other backends or when the
+    /// server did not answer.
+    #[serde(default)]
+    pub ollama_version: Option<String>,
+    /// The run this one replayed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_of: Option<String>,
+    /// Pinned runs are never removed by the retention policy.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub pinned: bool,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// What a streamed run was asked to do; captured before the request is sent.
+#[derive(Debug, Clone)]
+pub struct StreamRunRequest {
+    pub model: String,
+    pub recipe_path: Option<String>,
+    pub system_text: String,
+    pub user_text: String,
+    pub images: Vec<ImageAttachment>,
+    pub output_schema: Option<Value>,
+    pub started_at: String,
+    pub group_id: Option<String>,
+    pub profile: Option<Profile>,
+    /// Merged params and fragment hashes, when the prompt came from a recipe.
+    pub params: Value,
+    pub fragments: Vec<FragmentSource>,
+    pub ollama_version: Option<String>,
+    pub replay_of: Option<String>,
+}
+
+impl StreamRunRequest {
+    /// Describes a prompt the UI composed and ran itself, for [`save_run`].
+    /// `model` defaults to the recipe's profile; params and fragment hashes are
+    /// recorded when re-composing with `params` reproduces `final_prompt`.
+    pub fn for_saved_prompt(
+        sandbox: &Path,
+        recipe_path: &str,
+        final_prompt: &str,
+        model: Option<String>,
+        params: Option<Value>,
+    ) -> Self {
+        let composed =
+            params.and_then(|p| matching_composition(sandbox, recipe_path, p, final_prompt));
+        let model = model.or_else(|| composed.as_ref().map(|c| c.model.clone()));
+        let profile = model.and_then(|m| resolve_profile_in(sandbox, &m).ok());
+        let (system_text, user_text) = split_final_prompt(final_prompt);
+        StreamRunRequest {
+            model: profile
+                .as_ref()
+                .map(|p| p.model.clone())
+                .unwrap_or_default(),
+            recipe_path: Some(recipe_path.to_string()),
+            system_text,
+            user_text,
+            images: Vec::new(),
+            output_schema: None,
+            started_at: Local::now().to_rfc3339(),
+            group_id: None,
+            profile,
+            params: composed
+                .as_ref()
+                .map(|c| c.params.clone())
+                .unwrap_or_default(),
+            fragments: composed.map(|c| c.fragments).unwrap_or_default(),
+            ollama_version: None,
+            replay_of: None,
+        }
+    }
+
+    /// The manifest for this request, finishing now.
+    fn meta(&self, run_id: String, status: RunStatus) -> RunMeta {
+        let finished = Local::now();
+        let duration_ms = DateTime::parse_from_rfc3339(&self.started_at)
+            .ok()
+            .and_then(|started| (finished.fixed_offset() - started).to_std().ok())
+            .map(|d| d.as_millis() as u64);
+        RunMeta {
+            run_id,
+            status,
+            model: self.model.clone(),
+            recipe_path: self.recipe_path.clone(),
+            started_at: self.started_at.clone(),
+            finished_at: finished.to_rfc3339(),
+            error: None,
+            stats: None,
+            images: self.images.clone(),
+            schema_errors: Vec::new(),
+            repair_attempts: 0,
+            group_id: self.group_id.clone(),
+            profile: self.profile.clone(),
+            options: self
+                .profile
+                .as_ref()
+                .map(Profile::ollama_options)
+                .unwrap_or_default(),
+            params: self.params.clone(),
+            prompt_sha256: Some(sha256_hex(
+                format!("{}{}", self.system_text, self.user_text).as_bytes(),
+            )),
+            system_len: Some(self.system_text.len()),
+            fragments: self.fragments.clone(),
+            duration_ms,
+            app_version: Some(APP_VERSION.to_string()),
+            ollama_version: self.ollama_version.clone(),
+            replay_of: self.replay_of.clone(),
+            pinned: false,
+        }
+    }
+}
+
+/// Where runs are saved: `PROMPTFORGE_RUNS_DIR`, else `runs/` in the app data
+/// dir (the same folder Tauri's `app_data_dir` points at), else `./runs`.
+pub fn runs_root() -> PathBuf {
+    env::var_os(RUNS_DIR_ENV)
+        .map(PathBuf::from)
+        .or_else(|| dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER).join(RUNS_DIR)))
+        .unwrap_or_else(|| PathBuf::from(RUNS_DIR))
+}
+
+/// Copies `from` into a hidden staging folder next to `to` and renames it into
+/// place, for moves that `fs::rename` cannot do (another drive).
+fn copy_dir_into(from: &Path, to: &Path) -> io::Result<()> {
+    fn copy_all(from: &Path, to: &Path) -> io::Result<()> {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let target = to.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                copy_all(&entry.path(), &target)?;
+            } else {
+                fs::copy(entry.path(), &target)?;
+            }
+        }
+        Ok(())
+    }
+    let name = to.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let staging = to.with_file_name(format!(".{}.partial", name));
+    let copied = copy_all(from, &staging).and_then(|()| fs::rename(&staging, to));
+    if copied.is_err() {
+        let _ = fs::remove_dir_all(&staging);
+    }
+    copied
+}
+
+fn move_dir(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_dir_into(from, to)?;
+    fs::remove_dir_all(from)
+}
+
+/// A `YYYYMMDD-HHMMSS` folder holding the files a run was saved with.
+fn looks_like_legacy_run(dir: &Path, name: &str) -> bool {
+    is_legacy_run_id(name)
+        && (dir.join("prompt.final.txt").is_file() || dir.join("recipe.path.txt").is_file())
+}
+
+/// Moves the legacy `YYYYMMDD-HHMMSS` run folders and the sweep groups under
+/// `from` (where runs were saved before they moved to the app data dir) into
+/// `root`, keeping their ids. Only folders that hold a saved prompt or recipe
+/// path count as runs, and only groups with a `group.json`; anything else
+/// stays, as does a folder whose name is already taken in `root`. Returns the
+/// run ids moved.
+pub fn import_legacy_runs(from: &Path, root: &Path) -> io::Result<Vec<String>> {
+    let same = match (from.canonicalize(), root.canonicalize()) {
+        (Ok(from), Ok(root)) => from == root,
+        (Err(_), _) => return Ok(Vec::new()),
+        _ => false,
+    };
+    if same {
+        return Ok(Vec::new());
+    }
+    let mut moved = Vec::new();
+    for name in child_dirs(from)? {
+        if !looks_like_legacy_run(&from.join(&name), &name) || root.join(&name).exists() {
+            continue;
+        }
+        fs::create_dir_all(root)?;
+        move_dir(&from.join(&name), &root.join(&name))?;
+        moved.push(name);
+    }
+    let groups = from.join(GROUPS_DIR);
+    for name in child_dirs(&groups)? {
+        let target = root.join(GROUPS_DIR).join(&name);
+        if groups.join(&name).join("group.json").is_file() && !target.exists() {
+            fs::create_dir_all(root.join(GROUPS_DIR))?;
+            move_dir(&groups.join(&name), &target)?;
+        }
+    }
+    // Only removed once empty; anything else the user keeps there stays.
+    let _ = fs::remove_dir(&groups);
+    let _ = fs::remove_dir(from);
+    moved.sort();
+    Ok(moved)
+}
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ULID_LEN: usize = 26;
+const ULID_RANDOM_BITS: u32 = 80;
+
+/// A new ULID: a 48-bit millisecond timestamp and 80 random bits in Crockford
+/// base32, so ids sort by creation time. Ids made within one millisecond
+/// increment the random part instead of drawing a new one, keeping their order.
+pub fn new_run_id() -> String {
+    static LAST: Mutex<(u64, u128)> = Mutex::new((0, 0));
+    let now = Utc::now().timestamp_millis().max(0) as u64;
+    let mut last = LAST.lock().unwrap_or_else(PoisonError::into_inner);
+    let mask = (1u128 << ULID_RANDOM_BITS) - 1;
+    *last = if now > last.0 {
+        (now, rand::random::<u128>() & mask)
+    } else {
+        (last.0, (last.1 + 1) & mask)
+    };
+    let value = (u128::from(last.0) << ULID_RANDOM_BITS) | last.1;
+    (0..ULID_LEN)
+        .map(|i| CROCKFORD[((value >> (5 * (ULID_LEN - 1 - i))) & 0x1f) as usize] as char)
+        .collect()
+}
+
+/// When a run id was made, in Unix milliseconds: decoded from a ULID, or read
+/// from a legacy `YYYYMMDD-HHMMSS[-n]` folder name as local time.
+pub fn run_id_millis(run_id: &str) -> Option<i64> {
+    if run_id.len() == ULID_LEN {
+        return run_id[..10].bytes().try_fold(0i64, |acc, b| {
+            let digit = CROCKFORD.iter().position(|&c| c == b)?;
+            Some(acc << 5 | digit as i64)
+        });
+    }
+    let stamp = NaiveDateTime::parse_from_str(run_id.get(..15)?, LEGACY_RUN_ID_FORMAT).ok()?;
+    let suffix = &run_id[15..];
+    if !(suffix.is_empty() || suffix.starts_with('-')) {
+        return None;
+    }
+    Local
+        .from_local_datetime(&stamp)
+        .earliest()
+        .map(|t| t.timestamp_millis())
+}
+
+fn is_legacy_run_id(run_id: &str) -> bool {
+    run_id.len() != ULID_LEN && run_id_millis(run_id).is_some()
+}
+
+/// A run folder being written. Files go into a hidden `.<run_id>.partial`
+/// folder that [`PendingRun::commit`] renames into place, so a run folder
+/// never appears half written; dropping it uncommitted removes the files.
+pub struct PendingRun {
+    run_id: String,
+    staging: PathBuf,
+    dir: PathBuf,
+    committed: bool,
+}
+
+impl PendingRun {
+    pub fn create(root: &Path) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+        loop {
+            let run_id = new_run_id();
+            let dir = root.join(&run_id);
+            if dir.exists() {
+                continue;
+            }
+            let staging = root.join(format!(".{}.partial", run_id));
+            match fs::create_dir(&staging) {
+                Ok(()) => {
+                    return Ok(Self {
+                        run_id,
+                        staging,
+                        dir,
+                        committed: false,
+                    })
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Where to write the run's files until it is committed.
+    pub fn path(&self) -> &Path {
+        &self.staging
+    }
+
+    /// Moves the folder to `root/<run_id>` and returns its id and path.
+    pub fn commit(mut self) -> io::Result<(String, PathBuf)> {
+        fs::rename(&self.staging, &self.dir)?;
+        self.committed = true;
+        Ok((self.run_id.clone(), self.dir.clone()))
+    }
+}
+
+impl Drop for PendingRun {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_dir_all(&self.staging);
+        }
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(value).map_err(io::Error::other)?;
+    fs::write(path, json)
+}
+
+/// Writes through a sibling temp file so readers never see a partial file.
+fn replace_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    write_json(&tmp, value)?;
+    fs::rename(&tmp, path)
+}
+
+/// Run ids are folder names; anything else could escape the runs root.
+fn is_run_id(run_id: &str) -> bool {
+    !run_id.is_empty()
+        && run_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn read_run_meta(root: &Path, run_id: &str) -> io::Result<RunMeta> {
+    if !is_run_id(run_id) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid run id `{}`", run_id),
+        ));
+    }
+    let json = fs::read_to_string(root.join(run_id).join("run.json"))?;
+    serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Names of the folders directly under `root`; empty when it does not exist.
+fn child_dirs(root: &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect())
+}
+
+/// Pins or unpins run `run_id` in its `run.json`.
+pub fn set_run_pinned(root: &Path, run_id: &str, pinned: bool) -> io::Result<RunMeta> {
+    if !root.join(run_id).join("run.json").exists() {
+        migrate_legacy_runs(root)?;
+    }
+    let meta = RunMeta {
+        pinned,
+        ..read_run_meta(root, run_id)?
+    };
+    replace_json(&root.join(run_id).join("run.json"), &meta)?;
+    Ok(meta)
+}
+
+/// Run folders under `root`, skipping `groups/` and unfinished (hidden) runs.
+pub fn run_ids(root: &Path) -> io::Result<Vec<String>> {
+    Ok(child_dirs(root)?
+        .into_iter()
+        .filter(|name| name != GROUPS_DIR && is_run_id(name))
+        .collect())
+}
+
+/// Every run with a readable `run.json`, newest first. ULIDs and legacy
+/// timestamp ids are ordered by the time they encode.
+pub fn list_run_metas(root: &Path) -> io::Result<Vec<RunMeta>> {
+    let mut runs: Vec<RunMeta> = run_ids(root)?
+        .iter()
+        .filter_map(|run_id| read_run_meta(root, run_id).ok())
+        .collect();
+    runs.sort_by_key(|run| std::cmp::Reverse((run_id_millis(&run.run_id), run.run_id.clone())));
+    Ok(runs)
+}
+
+/// Gives every legacy `YYYYMMDD-HHMMSS` run folder under `root` that lacks one
+/// a `run.json` rebuilt from its files, so older runs list and load like new
+/// ones. Folders are left where they are (batch results and sweep groups refer
+/// to them by id); `groups/` is skipped. Returns the ids indexed.
+pub fn migrate_legacy_runs(root: &Path) -> io::Result<Vec<String>> {
+    let mut migrated = Vec::new();
+    for run_id in child_dirs(root)? {
+        let dir = root.join(&run_id);
+        if !is_legacy_run_id(&run_id) || dir.join("run.json").exists() {
+            continue;
+        }
+        let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+        let started_at = run_id_millis(&run_id)
+            .and_then(|ms| Local.timestamp_millis_opt(ms).single())
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default();
+        let meta = RunMeta {
+            run_id: run_id.clone(),
+            status: RunStatus::Completed,
+            model: String::new(),
+            recipe_path: read("recipe.path.txt").filter(|p| !p.is_empty()),
+            started_at: started_at.clone(),
+            finished_at: started_at,
+            error: None,
+            stats: read("stats.json").and_then(|s| serde_json::from_str(&s).ok()),
+            images: Vec::new(),
+            schema_errors: Vec::new(),
+            repair_attempts: 0,
+            group_id: None,
+            profile: None,
+            options: Value::Null,
+            params: Value::Null,
+            prompt_sha256: read("prompt.final.txt").map(|p| sha256_hex(p.as_bytes())),
+            system_len: None,
+            fragments: Vec::new(),
+            duration_ms: None,
+            app_version: None,
+            ollama_version: None,
+            replay_of: None,
+            pinned: false,
+        };
+        replace_json(&dir.join("run.json"), &meta)?;
+        migrated.push(run_id);
+    }
+    migrated.sort();
+    Ok(migrated)
+}
+
+/// Writes the prompt, whatever response arrived and `run.json` for a streamed
+/// run. Schema-constrained runs also get `schema.json`, the parsed
+/// `response.json` and, when repairs ran, `response.repaired.txt`.
+pub fn write_stream_run(
+    root: &Path,
+    request: &StreamRunRequest,
+    capture: &StreamCapture,
+    status: RunStatus,
+) -> io::Result<(String, PathBuf)> {
+    write_stream_run_with(root, request, capture, status, |_| Ok(()))
+}
+
+/// [`write_stream_run`], letting `extra` add files (LLM recordings) before
+/// the run folder appears.
+pub fn write_stream_run_with(
+    root: &Path,
+    request: &StreamRunRequest,
+    capture: &StreamCapture,
+    status: RunStatus,
+    extra: impl FnOnce(&Path) -> io::Result<()>,
+) -> io::Result<(String, PathBuf)> {
+    let pending = PendingRun::create(root)?;
+    let dir = pending.path();
+    if let Some(recipe_path) = &request.recipe_path {
+        fs::write(dir.join("recipe.path.txt"), recipe_path)?;
+    }
+    fs::write(
+        dir.join("prompt.final.txt"),
+        format!("{}{}", request.system_text, request.user_text),
+    )?;
+    fs::write(dir.join("response.raw.jsonl"), &capture.raw)?;
+    fs::write(dir.join("response.txt"), &capture.text)?;
+    if let Some(schema) = &request.output_schema {
+        write_json(&dir.join("schema.json"), schema)?;
+    }
+    let structured = capture.structured.as_ref();
+    if let Some(value) = structured.and_then(|s| s.value.as_ref()) {
+        write_json(&dir.join("response.json"), value)?;
+    }
+    if let Some(repaired) = structured.filter(|s| s.repair_attempts > 0) {
+        fs::write(dir.join("response.repaired.txt"), &repaired.text)?;
+    }
+
+    let meta = RunMeta {
+        error: capture.error.clone(),
+        stats: capture.stats.clone(),
+        schema_errors: structured.map(|s| s.errors.clone()).unwrap_or_default(),
+        repair_attempts: structured.map(|s| s.repair_attempts).unwrap_or_default(),
+        ..request.meta(pending.run_id.clone(), status)
+    };
+    write_json(&dir.join("run.json"), &meta)?;
+    extra(dir)?;
+    pending.commit()
+}
+
+/// Saves a run the UI assembled itself (prompt, raw response, optional stats)
+/// and returns its folder. `request` describes how the prompt was made; its
+/// text fields should hold the saved prompt.
+pub fn save_run(
+    root: &Path,
+    request: &StreamRunRequest,
+    response_text: &str,
+    stats: Option<&GenerationStats>,
+) -> io::Result<PathBuf> {
+    let pending = PendingRun::create(root)?;
+    let dir = pending.path();
+    let recipe_path = request.recipe_path.as_deref().unwrap_or_default();
+    fs::write(dir.join("recipe.path.txt"), recipe_path)?;
+    fs::write(
+        dir.join("prompt.final.txt"),
+        format!("{}{}", request.system_text, request.user_text),
+    )?;
+    fs::write(dir.join("response.raw.jsonl"), response_text)?;
+    if let Some(stats) = stats {
+        write_json(&dir.join("stats.json"), stats)?;
+    }
+    let meta = RunMeta {
+        stats: stats.cloned(),
+        ..request.meta(pending.run_id.clone(), RunStatus::Completed)
+    };
+    write_json(&dir.join("run.json"), &meta)?;
+    Ok(pending.commit()?.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama_client::OllamaErrorKind;
+    use crate::structured_output::StructuredOutput;
+
+    fn request() -> StreamRunRequest {
+        StreamRunRequest {
+            model: "llama3:8b".into(),
+            recipe_path: Some("data/recipes/demo.sora2.yaml".into()),
+            system_text: "SYS".into(),
+            user_text: "\n---\nUSER_INPUT".into(),
+            images: vec![ImageAttachment {
+                path: "project/refs/frame01.png".into(),
+                size_bytes: 8,
+                sha256: "ab".repeat(32),
+                base64: "iVBORw0KGgo=".into(),
+            }],
+            started_at: "2025-01-01T00:00:00+09:00".into(),
+            profile: Some(Profile {
+                temperature: Some(0.2),
+                seed: Some(7),
+                ..Profile::for_model("llama3:8b")
+            }),
+            params: serde_json::json!({"steps": 4}),
+            fragments: vec![FragmentSource {
+                id: "task.video_prompting".into(),
+                path: "fragments/task/video_prompting.yaml".into(),
+                sha256: "cd".repeat(32),
+            }],
+            ollama_version: Some("0.5.7".into()),
+            ..StreamRunRequest::test_default()
+        }
+    }
+
+    #[test]
+    fn writes_partial_stream_run() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let capture = StreamCapture {
+            text: "Hel".into(),
+            raw: "{\"response\":\"Hel\"}\n".into(),
+            stats: None,
+            error: Some(OllamaError::new(OllamaErrorKind::Timeout, "idle")),
+            structured: None,
+        };
+        let (run_id, dir) =
+            write_stream_run(temp.path(), &request(), &capture, RunStatus::Failed).expect("write");
+
+        assert_eq!(dir, temp.path().join(&run_id));
+        assert_eq!(
+            fs::read_to_string(dir.join("prompt.final.txt")).unwrap(),
+            "SYS\n---\nUSER_INPUT"
+        );
+        assert_eq!(fs::read_to_string(dir.join("response.txt")).unwrap(), "Hel");
+        assert_eq!(
+            fs::read_to_string(dir.join("recipe.path.txt")).unwrap(),
+            "data/recipes/demo.sora2.yaml"
+        );
+        let meta: RunMeta =
+            serde_json::from_str(&fs::read_to_string(dir.join("run.json")).unwrap()).unwrap();
+        assert_eq!(meta.status, RunStatus::Failed);
+        assert_eq!(meta.error.map(|e| e.kind), Some(OllamaErrorKind::Timeout));
+        assert_eq!(meta.images[0].sha256, "ab".repeat(32));
+        assert!(meta.images[0].base64.is_empty());
+
+        assert_eq!(
+            meta.options,
+            serde_json::json!({"temperature": 0.2, "seed": 7})
+        );
+        assert_eq!(meta.params["steps"], 4);
+        assert_eq!(meta.fragments[0].id, "task.video_prompting");
+        assert_eq!(
+            meta.prompt_sha256,
+            Some(sha256_hex("SYS\n---\nUSER_INPUT".as_bytes()))
+        );
+        assert_eq!(meta.app_version.as_deref(), Some(APP_VERSION));
+        assert_eq!(meta.ollama_version.as_deref(), Some("0.5.7"));
+        assert!(meta.duration_ms.is_some());
+
+        assert_eq!(list_run_metas(temp.path()).unwrap()[0].run_id, run_id);
+        assert!(read_run_meta(temp.path(), "../outside").is_err());
+    }
+
+    #[test]
+    fn records_schema_failures() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let request = StreamRunRequest {
+            output_schema: Some(serde_json::json!({"type": "array"})),
+            ..request()
+        };
+        let capture = StreamCapture {
+            text: "{}".into(),
+            structured: Some(StructuredOutput {
+                text: "still {}".into(),
+                value: None,
+                errors: vec!["/: expected array, got object".into()],
+                repair_attempts: 2,
+            }),
+            ..StreamCapture::default()
+        };
+        assert_eq!(capture.status(), RunStatus::SchemaInvalid);
+        let (_, dir) =
+            write_stream_run(temp.path(), &request, &capture, capture.status()).expect("write");
+
+        assert!(dir.join("schema.json").is_file());
+        assert!(!dir.join("response.json").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("response.repaired.txt")).unwrap(),
+            "still {}"
+        );
+        let meta: RunMeta =
+            serde_json::from_str(&fs::read_to_string(dir.join("run.json")).unwrap()).unwrap();
+        assert_eq!(meta.status, RunStatus::SchemaInvalid);
+        assert_eq!(meta.repair_attempts, 2);
+        assert_eq!(meta.schema_errors.len(), 1);
+    }
+
+    #[test]
+    fn saved_runs_get_a_manifest() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let dir = save_run(temp.path(), &request(), "{\"response\":\"ok\"}", None).expect("save");
+        assert_eq!(
+            fs::read_to_string(dir.join("recipe.path.txt")).unwrap(),
+            "data/recipes/demo.sora2.yaml"
+        );
+        let meta: RunMeta =
+            serde_json::from_str(&fs::read_to_string(dir.join("run.json")).unwrap()).unwrap();
+        assert_eq!(meta.status, RunStatus::Completed);
+        assert_eq!(meta.model, "llama3:8b");
+        assert_eq!(meta.profile.unwrap().seed, Some(7));
+
+        // Manifests written before these fields existed still load.
+        let old = r#"{"run_id":"20240101-000000","status":"completed","model":"m","started_at":"","finished_at":""}"#;
+        let meta: RunMeta = serde_json::from_str(old).unwrap();
+        assert!(meta.params.is_null() && meta.app_version.is_none());
+    }
+
+    #[test]
+    fn run_ids_are_unique_and_sortable() {
+        let ids: Vec<String> = (0..64).map(|_| new_run_id()).collect();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted, ids);
+        assert!(ids.iter().all(|id| id.len() == 26 && is_run_id(id)));
+        let age = Utc::now().timestamp_millis() - run_id_millis(&ids[0]).unwrap();
+        assert!((0..5_000).contains(&age));
+        assert!(run_id_millis("20240101-120000-2").is_some());
+        assert!(run_id_millis("groups").is_none());
+    }
+
+    #[test]
+    fn imports_runs_from_the_old_working_directory_folder() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let (old, root) = (temp.path().join("cwd/runs"), temp.path().join("data/runs"));
+        for name in ["20240101-120000", "20240101-120000-2", "notes"] {
+            fs::create_dir_all(old.join(name)).unwrap();
+            fs::write(old.join(name).join("prompt.final.txt"), "SYS").unwrap();
+        }
+        // Timestamp-named, but not a run.
+        fs::create_dir_all(old.join("20240102-090000/photos")).unwrap();
+        fs::create_dir_all(old.join(GROUPS_DIR).join("sweep-1")).unwrap();
+        fs::write(old.join(GROUPS_DIR).join("sweep-1/group.json"), "{}").unwrap();
+        fs::create_dir_all(old.join(GROUPS_DIR).join("misc")).unwrap();
+        fs::create_dir_all(root.join("20240101-120000-2")).unwrap();
+
+        assert_eq!(
+            import_legacy_runs(&old, &root).unwrap(),
+            ["20240101-120000"]
+        );
+        assert!(!old.join("20240101-120000").exists());
+        assert!(old.join("20240101-120000-2").exists(), "name taken in root");
+        assert!(old.join("notes").exists());
+        assert!(old.join("20240102-090000/photos").exists());
+        assert!(root.join(GROUPS_DIR).join("sweep-1/group.json").exists());
+        assert!(
+            old.join(GROUPS_DIR).join("misc").exists(),
+            "not a sweep group"
+        );
+        assert_eq!(
+            migrate_legacy_runs(&root).unwrap(),
+            ["20240101-120000", "20240101-120000-2"]
+        );
+        assert_eq!(list_run_metas(&root).unwrap().len(), 2);
+
+        let target = temp.path().join("copy/20240101-120000");
+        copy_dir_into(&root.join("20240101-120000"), &target).unwrap();
+        assert!(target.join("run.json").exists());
+        assert!(import_legacy_runs(&root, &root).unwrap().is_empty());
+    }
+
+    #[test]
+    fn migrates_legacy_timestamp_folders() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let root = temp.path();
+        let legacy = root.join("20240101-120000");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("recipe.path.txt"), "recipes/demo.yaml").unwrap();
+        fs::write(legacy.join("prompt.final.txt"), "SYS").unwrap();
+        fs::create_dir_all(root.join(GROUPS_DIR).join("20240101-120001")).unwrap();
+        fs::create_dir_all(root.join(".01ARZ3NDEKTSV4RRFFQ69G5FAV.partial")).unwrap();
+        let dir = save_run(root, &request(), "ok", None).expect("save");
+
+        assert_eq!(migrate_legacy_runs(root).unwrap(), ["20240101-120000"]);
+        assert!(migrate_legacy_runs(root).unwrap().is_empty());
+        assert!(!root
+            .join(GROUPS_DIR)
+            .join("20240101-120001/run.json")
+            .exists());
+
+        let runs = list_run_metas(root).unwrap();
+        let ids: Vec<_> = runs.iter().map(|r| r.run_id.as_str()).collect();
+        let new_id = dir.file_name().unwrap().to_str().unwrap();
+        assert_eq!(ids, [new_id, "20240101-120000"]);
+        assert_eq!(runs[1].recipe_path.as_deref(), Some("recipes/demo.yaml"));
+        assert_eq!(runs[1].prompt_sha256, Some(sha256_hex(b"SYS")));
+    }
+}
@@ -0,0 +1,41 @@
+//! Path checks shared by every command that touches the filesystem.
+
+use std::io;
+use std::path::Path;
+
+/// Fails with `PermissionDenied` ("path out of sandbox") unless `target`, or
+/// its nearest existing ancestor, resolves inside `base`.
+pub fn ensure_under(base: &Path, target: &Path) -> Result<(), io::Error> {
+    let base = base.canonicalize()?;
+    let permission_error =
+        || io::Error::new(io::ErrorKind::PermissionDenied, "path out of sandbox");
+    match target.canonicalize() {
+        Ok(target) => {
+            if target.starts_with(&base) {
+                Ok(())
+            } else {
+                Err(permission_error())
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let mut ancestor = target;
+            while let Some(parent) = ancestor.parent() {
+                match parent.canonicalize() {
+                    Ok(parent) => {
+                        if parent.starts_with(&base) {
+                            return Ok(());
+                        }
+                        return Err(permission_error());
+                    }
+                    Err(parent_err) if parent_err.kind() == io::ErrorKind::NotFound => {
+                        ancestor = parent;
+                        continue;
+                    }
+                    Err(parent_err) => return Err(parent_err),
+                }
+            }
+            Err(err)
+        }
+        Err(err) => Err(err),
+    }
+}
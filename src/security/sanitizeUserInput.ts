@@ -1,3 +1,5 @@
+import maskPatterns from './maskPatterns.json'
+
 export type SanitizedUserInput = {
   sanitized: string
   maskedTypes: string[]
@@ -9,28 +11,11 @@ const MAX_LENGTH = 40000
 type MaskPattern = { type: SanitizedType; regex: RegExp }
 type SanitizedType = 'API_KEY' | 'PEM_KEY' | 'GOOGLE_API_KEY' | 'AWS_ACCESS_KEY' | 'AWS_SECRET_KEY'
 
-const MASK_PATTERNS: readonly MaskPattern[] = [
-  {
-    type: 'API_KEY',
-    regex: /(?:api[_-]?key|token|secret)\s*[:=]\s*['\"]?[A-Za-z0-9_\-]{16,}['\"]?/gi
-  },
-  {
-    type: 'PEM_KEY',
-    regex: /-----BEGIN [^-]+? KEY-----[\s\S]+?-----END [^-]+? KEY-----/g
-  },
-  {
-    type: 'GOOGLE_API_KEY',
-    regex: /(AIza[0-9A-Za-z\-_]{35})/g
-  },
-  {
-    type: 'AWS_ACCESS_KEY',
-    regex: /(AKIA|ASIA)[0-9A-Z]{16}/g
-  },
-  {
-    type: 'AWS_SECRET_KEY',
-    regex: /aws(.{0,20})?(secret|access).{0,20}?([A-Za-z0-9/+=]{40})/gi
-  }
-]
+// Shared with the Rust side (`src/redact.rs`), which masks run exports.
+const MASK_PATTERNS: readonly MaskPattern[] = maskPatterns.map(({ type, pattern, flags }) => ({
+  type: type as SanitizedType,
+  regex: new RegExp(pattern, flags)
+}))
 
 export const sanitizeUserInput = (text: string): SanitizedUserInput => {
   if (!text) {
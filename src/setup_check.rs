@@ -1,28 +1,11 @@
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock_ollama::{MockConfig, MockOllama, MockReply};
     use reqwest::Client;
-    use tokio::{
-        io::{AsyncReadExt, AsyncWriteExt},
-        net::TcpListener,
-        task::JoinHandle,
-    };
 
-    async fn spawn_json_server(body: &'static str) -> (JoinHandle<()>, String) {
-        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let addr = listener.local_addr().unwrap();
-        let handle = tokio::spawn(async move {
-            let (mut socket, _) = listener.accept().await.unwrap();
-            let mut buf = [0_u8; 1024];
-            let _ = socket.read(&mut buf).await;
-            let response = format!(
-                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-                body.len(),
-                body
-            );
-            socket.write_all(response.as_bytes()).await.unwrap();
-        });
-        (handle, format!("http://{}", addr))
+    async fn check(mock: &MockOllama, request: SetupCheckRequest<'_>) -> SetupCheckOutcome {
+        check_ollama_setup_state(&Client::new(), &mock.url(), &request).await
     }
 
     async fn assert_status(
@@ -30,10 +13,13 @@ mod tests {
         required_model: Option<&str>,
         expected: SetupStatus,
     ) {
-        let (server, base_url) = spawn_json_server(body).await;
-        let result = check_ollama_setup_state(&Client::new(), &base_url, required_model).await;
-        server.await.unwrap();
-        assert_eq!(result.status, expected);
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        mock.push("/api/tags", MockReply::lines([body]));
+        let request = SetupCheckRequest {
+            required_model,
+            ..SetupCheckRequest::default()
+        };
+        assert_eq!(check(&mock, request).await.status, expected);
     }
 
     #[tokio::test]
@@ -55,9 +41,15 @@ mod tests {
                 SetupStatus::ModelMissing,
             ),
             (r#"{"models":[]}"#, None, SetupStatus::ModelMissing),
+            (
+                r#"<html>not ollama</html>"#,
+                None,
+                SetupStatus::ProtocolError,
+            ),
         ] {
             assert_status(body, required_model, expected).await;
         }
+        // A refused connection needs a port nothing listens on, not the mock.
         let port = {
             let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
             let port = listener.local_addr().unwrap().port();
@@ -66,55 +58,241 @@ mod tests {
         };
         let url = format!("http://127.0.0.1:{}", port);
         assert_eq!(
-            check_ollama_setup_state(&Client::new(), &url, None)
+            check_ollama_setup_state(&Client::new(), &url, &SetupCheckRequest::default())
                 .await
                 .status,
             SetupStatus::ServerUnavailable
         );
     }
+
+    #[tokio::test]
+    async fn rejects_old_servers() {
+        let mock = MockOllama::start(MockConfig {
+            version: "0.1.20".into(),
+            ..MockConfig::default()
+        })
+        .await
+        .unwrap();
+        let outcome = check(
+            &mock,
+            SetupCheckRequest {
+                locale: Locale::En,
+                ..SetupCheckRequest::default()
+            },
+        )
+        .await;
+        assert_eq!(outcome.status, SetupStatus::VersionUnsupported);
+        assert_eq!(
+            outcome.diagnostics.server_version.as_deref(),
+            Some("0.1.20")
+        );
+        assert_eq!(outcome.guidance, outcome.localized_guidance.en);
+        assert!(outcome.guidance.contains(MIN_OLLAMA_VERSION));
+    }
+
+    #[tokio::test]
+    async fn compares_context_length_and_probes() {
+        // The mock reports llama3:8b with an 8192-token context.
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        let outcome = check(
+            &mock,
+            SetupCheckRequest {
+                required_model: Some("llama3:8b"),
+                profile_num_ctx: Some(16384),
+                ..SetupCheckRequest::default()
+            },
+        )
+        .await;
+        assert_eq!(outcome.status, SetupStatus::ContextMismatch);
+        assert_eq!(outcome.diagnostics.model_context_length, Some(8192));
+        assert!(outcome.localized_guidance.ja.contains("16384"));
+
+        let outcome = check(
+            &mock,
+            SetupCheckRequest {
+                required_model: Some("llama3:8b"),
+                profile_num_ctx: Some(4096),
+                probe: true,
+                ..SetupCheckRequest::default()
+            },
+        )
+        .await;
+        assert_eq!(outcome.status, SetupStatus::Ready);
+        assert!(outcome.diagnostics.probe_latency_ms.is_some());
+
+        mock.push("/api/generate", MockReply::status(500, "out of memory"));
+        let outcome = check(
+            &mock,
+            SetupCheckRequest {
+                required_model: Some("llama3:8b"),
+                probe: true,
+                ..SetupCheckRequest::default()
+            },
+        )
+        .await;
+        assert_eq!(outcome.status, SetupStatus::ProbeFailed);
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert!(version_at_least("0.5.7", "0.3.0"));
+        assert!(version_at_least("v0.3.0-rc1", "0.3.0"));
+        assert!(!version_at_least("0.2.9", "0.3.0"));
+        // Source builds report unparseable versions; do not block them.
+        assert!(version_at_least("main", "0.3.0"));
+    }
 }
 
-use reqwest::Client;
+use std::time::Instant;
+
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+use crate::ollama_client::OllamaClientConfig;
+use crate::profiles::resolve_profile;
+
+/// Oldest server that has `/api/ps` and `model_info` in `/api/show`.
+pub const MIN_OLLAMA_VERSION: &str = "0.3.0";
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SetupStatus {
     Ready,
     ServerUnavailable,
+    ProtocolError,
+    VersionUnsupported,
     ModelMissing,
+    ContextMismatch,
+    ProbeFailed,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    Ja,
+    En,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LocalizedGuidance {
+    pub ja: String,
+    pub en: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SetupDiagnostics {
+    pub server_version: Option<String>,
+    pub min_supported_version: String,
+    pub model_context_length: Option<u64>,
+    pub profile_num_ctx: Option<u64>,
+    pub probe_latency_ms: Option<u64>,
+    /// Underlying error text for protocol and probe failures.
+    pub detail: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct SetupCheckOutcome {
     pub status: SetupStatus,
+    /// Guidance in the requested locale.
     pub guidance: String,
+    pub localized_guidance: LocalizedGuidance,
+    pub diagnostics: SetupDiagnostics,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SetupCheckRequest<'a> {
+    pub required_model: Option<&'a str>,
+    pub profile_num_ctx: Option<u64>,
+    /// Run a one-token generation to measure latency (loads the model).
+    pub probe: bool,
+    pub locale: Locale,
+}
+
+fn guidance(status: SetupStatus, diag: &SetupDiagnostics) -> LocalizedGuidance {
+    let (ja, en) = match status {
+        SetupStatus::Ready => match diag.probe_latency_ms {
+            Some(ms) => (
+                format!("Ollama サーバーは利用可能です（応答 {} ms）。", ms),
+                format!("Ollama server is available ({} ms probe).", ms),
+            ),
+            None => (
+                "Ollama サーバーは利用可能です。".to_string(),
+                "Ollama server is available.".to_string(),
+            ),
+        },
+        SetupStatus::ServerUnavailable => (
+            "Ollama サーバーに接続できません。サービスを起動してください。".to_string(),
+            "Cannot reach the Ollama server. Please start the service.".to_string(),
+        ),
+        SetupStatus::ProtocolError => (
+            "Ollama サーバーの応答を解釈できません。接続先 URL が Ollama を指しているか確認してください。"
+                .to_string(),
+            "The server response could not be understood. Check that the URL points to Ollama."
+                .to_string(),
+        ),
+        SetupStatus::VersionUnsupported => {
+            let version = diag.server_version.as_deref().unwrap_or("?");
+            (
+                format!(
+                    "Ollama {} は古すぎます。{} 以上に更新してください。",
+                    version, diag.min_supported_version
+                ),
+                format!(
+                    "Ollama {} is too old. Please upgrade to {} or later.",
+                    version, diag.min_supported_version
+                ),
+            )
+        }
+        SetupStatus::ModelMissing => (
+            "Ollama でモデルを pull してから再度お試しください。".to_string(),
+            "Pull the model in Ollama and try again.".to_string(),
+        ),
+        SetupStatus::ContextMismatch => {
+            let num_ctx = diag.profile_num_ctx.unwrap_or_default();
+            let limit = diag.model_context_length.unwrap_or_default();
+            (
+                format!(
+                    "プロファイルの num_ctx ({}) がモデルのコンテキスト長 ({}) を超えています。num_ctx を下げてください。",
+                    num_ctx, limit
+                ),
+                format!(
+                    "The profile's num_ctx ({}) exceeds the model's context length ({}). Lower num_ctx.",
+                    num_ctx, limit
+                ),
+            )
+        }
+        SetupStatus::ProbeFailed => {
+            let detail = diag.detail.as_deref().unwrap_or("");
+            (
+                format!("モデルの試験生成に失敗しました。{}", detail),
+                format!("The test generation failed. {}", detail),
+            )
+        }
+    };
+    LocalizedGuidance { ja, en }
 }
 
 impl SetupCheckOutcome {
-    fn new(status: SetupStatus, guidance: &'static str) -> Self {
+    fn new(status: SetupStatus, diagnostics: SetupDiagnostics, locale: Locale) -> Self {
+        let localized_guidance = guidance(status, &diagnostics);
+        let guidance = match locale {
+            Locale::Ja => localized_guidance.ja.clone(),
+            Locale::En => localized_guidance.en.clone(),
+        };
         Self {
             status,
-            guidance: guidance.to_string(),
+            guidance,
+            localized_guidance,
+            diagnostics,
         }
     }
-    fn ready() -> Self {
-        Self::new(SetupStatus::Ready, "Ollama サーバーは利用可能です。")
-    }
-    fn server_unavailable() -> Self {
-        Self::new(
-            SetupStatus::ServerUnavailable,
-            "Ollama サーバーに接続できません。サービスを起動してください。",
-        )
-    }
-    fn model_missing() -> Self {
-        Self::new(
-            SetupStatus::ModelMissing,
-            "Ollama でモデルを pull してから再度お試しください。",
-        )
-    }
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionResponse {
+    version: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -131,45 +309,169 @@ struct TaggedModel {
     name: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct ShowResponse {
+    #[serde(default)]
+    model_info: serde_json::Map<String, serde_json::Value>,
+}
+
+enum FetchError {
+    Unreachable,
+    Status(reqwest::StatusCode),
+    Protocol(String),
+}
+
+async fn fetch_json<T: for<'de> Deserialize<'de>>(
+    request: RequestBuilder,
+) -> Result<T, FetchError> {
+    let resp = request.send().await.map_err(|_| FetchError::Unreachable)?;
+    if !resp.status().is_success() {
+        return Err(FetchError::Status(resp.status()));
+    }
+    let body = resp.text().await.map_err(|_| FetchError::Unreachable)?;
+    serde_json::from_str(&body).map_err(|err| FetchError::Protocol(err.to_string()))
+}
+
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.trim().trim_start_matches('v');
+    let core = core.split(['-', '+']).next()?;
+    let mut parts = core.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().unwrap_or("0").parse().ok()?;
+    let patch = parts.next().unwrap_or("0").parse().ok()?;
+    Some((major, minor, patch))
+}
+
+/// Unparseable versions (source builds) are given the benefit of the doubt.
+fn version_at_least(version: &str, minimum: &str) -> bool {
+    match (parse_version(version), parse_version(minimum)) {
+        (Some(actual), Some(required)) => actual >= required,
+        _ => true,
+    }
+}
+
 pub async fn check_ollama_setup_state(
     client: &Client,
     base_url: &str,
-    required_model: Option<&str>,
+    request: &SetupCheckRequest<'_>,
 ) -> SetupCheckOutcome {
-    let url = format!("{}/api/tags", base_url.trim_end_matches('/'));
-    match client.get(url).send().await {
-        Ok(resp) if resp.status().is_success() => match resp.json::<TagsResponse>().await {
-            Ok(tags) => {
-                let has_any_model = tags
-                    .models
-                    .iter()
-                    .any(|m| m.model.as_ref().or(m.name.as_ref()).is_some());
-                if let Some(required) = required_model {
-                    let has_required_model = tags.models.iter().any(|m| {
-                        m.model.as_deref() == Some(required) || m.name.as_deref() == Some(required)
-                    });
-                    if !has_required_model {
-                        return SetupCheckOutcome::model_missing();
-                    }
-                }
-                if has_any_model {
-                    SetupCheckOutcome::ready()
-                } else {
-                    SetupCheckOutcome::model_missing()
-                }
+    let base = base_url.trim_end_matches('/');
+    let mut diag = SetupDiagnostics {
+        min_supported_version: MIN_OLLAMA_VERSION.to_string(),
+        profile_num_ctx: request.profile_num_ctx,
+        ..SetupDiagnostics::default()
+    };
+    let finish = |status: SetupStatus, diag: SetupDiagnostics| {
+        SetupCheckOutcome::new(status, diag, request.locale)
+    };
+
+    match fetch_json::<VersionResponse>(client.get(format!("{}/api/version", base))).await {
+        Ok(v) => {
+            let supported = version_at_least(&v.version, MIN_OLLAMA_VERSION);
+            diag.server_version = Some(v.version);
+            if !supported {
+                return finish(SetupStatus::VersionUnsupported, diag);
             }
-            Err(_) => SetupCheckOutcome::server_unavailable(),
-        },
-        _ => SetupCheckOutcome::server_unavailable(),
+        }
+        // Servers predating `/api/version` answer 404.
+        Err(FetchError::Status(status)) if status == reqwest::StatusCode::NOT_FOUND => {
+            return finish(SetupStatus::VersionUnsupported, diag);
+        }
+        Err(FetchError::Protocol(detail)) => {
+            diag.detail = Some(detail);
+            return finish(SetupStatus::ProtocolError, diag);
+        }
+        Err(_) => return finish(SetupStatus::ServerUnavailable, diag),
+    }
+
+    let tags = match fetch_json::<TagsResponse>(client.get(format!("{}/api/tags", base))).await {
+        Ok(tags) => tags,
+        Err(FetchError::Protocol(detail)) => {
+            diag.detail = Some(detail);
+            return finish(SetupStatus::ProtocolError, diag);
+        }
+        Err(_) => return finish(SetupStatus::ServerUnavailable, diag),
+    };
+    let has_any_model = tags
+        .models
+        .iter()
+        .any(|m| m.model.as_ref().or(m.name.as_ref()).is_some());
+    if !has_any_model {
+        return finish(SetupStatus::ModelMissing, diag);
+    }
+    let Some(required) = request.required_model else {
+        return finish(SetupStatus::Ready, diag);
+    };
+    let has_required_model = tags
+        .models
+        .iter()
+        .any(|m| m.model.as_deref() == Some(required) || m.name.as_deref() == Some(required));
+    if !has_required_model {
+        return finish(SetupStatus::ModelMissing, diag);
     }
+
+    let show = client
+        .post(format!("{}/api/show", base))
+        .json(&json!({ "model": required }));
+    if let Ok(show) = fetch_json::<ShowResponse>(show).await {
+        diag.model_context_length = show
+            .model_info
+            .iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64());
+    }
+    if let (Some(num_ctx), Some(limit)) = (diag.profile_num_ctx, diag.model_context_length) {
+        if num_ctx > limit {
+            return finish(SetupStatus::ContextMismatch, diag);
+        }
+    }
+
+    if request.probe {
+        let probe = client.post(format!("{}/api/generate", base)).json(&json!({
+            "model": required,
+            "prompt": "ping",
+            "stream": false,
+            "options": { "num_predict": 1 },
+        }));
+        let started = Instant::now();
+        match fetch_json::<serde_json::Value>(probe).await {
+            Ok(_) => diag.probe_latency_ms = Some(started.elapsed().as_millis() as u64),
+            Err(err) => {
+                diag.detail = Some(match err {
+                    FetchError::Unreachable => "connection lost".to_string(),
+                    FetchError::Status(status) => status.to_string(),
+                    FetchError::Protocol(detail) => detail,
+                });
+                return finish(SetupStatus::ProbeFailed, diag);
+            }
+        }
+    }
+
+    finish(SetupStatus::Ready, diag)
 }
 
-#[tauri::command]
 pub async fn check_ollama_setup(
     base_url: Option<String>,
     model: Option<String>,
+    profile: Option<String>,
+    probe: Option<bool>,
+    locale: Option<Locale>,
 ) -> Result<SetupCheckOutcome, String> {
-    let client = Client::new();
-    let url = base_url.unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string());
-    Ok(check_ollama_setup_state(&client, &url, model.as_deref()).await)
+    let profile = profile
+        .map(|name| resolve_profile(&name))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let model = model.or_else(|| profile.as_ref().map(|p| p.model.clone()));
+    let mut config = OllamaClientConfig::from_env();
+    if let Some(url) = base_url {
+        config.base_url = url;
+    }
+    let client = config.build_client().map_err(|e| e.to_string())?;
+    let request = SetupCheckRequest {
+        required_model: model.as_deref(),
+        profile_num_ctx: profile.as_ref().and_then(|p| p.num_ctx),
+        probe: probe.unwrap_or(false),
+        locale: locale.unwrap_or_default(),
+    };
+    Ok(check_ollama_setup_state(&client, &config.base_url, &request).await)
 }
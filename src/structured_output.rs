@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::json_schema::validate;
+use crate::llm_backend::{ChatMessage, ChatRequest, LlmBackend};
+use crate::ollama_client::OllamaError;
+
+/// Re-prompts allowed after the first reply fails validation.
+pub const MAX_REPAIR_ATTEMPTS: u32 = 2;
+
+/// Result of checking a reply against the requested schema.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredOutput {
+    /// Text the value was parsed from; the last repair reply when repairs ran.
+    pub text: String,
+    pub value: Option<Value>,
+    /// Violations of the final attempt; empty when `value` is set.
+    pub errors: Vec<String>,
+    pub repair_attempts: u32,
+}
+
+impl StructuredOutput {
+    pub fn is_valid(&self) -> bool {
+        self.value.is_some()
+    }
+}
+
+/// Models sometimes wrap JSON in a Markdown fence even when `format` is set.
+fn json_body(text: &str) -> &str {
+    let trimmed = text.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    let rest = rest.strip_prefix("json").unwrap_or(rest);
+    rest.strip_suffix("```").unwrap_or(rest).trim()
+}
+
+/// Parses `text` as JSON and validates it against `schema`.
+pub fn check(schema: &Value, text: &str) -> Result<Value, Vec<String>> {
+    let value: Value = serde_json::from_str(json_body(text))
+        .map_err(|err| vec![format!("invalid JSON: {}", err)])?;
+    validate(schema, &value)?;
+    Ok(value)
+}
+
+fn repair_request(request: &ChatRequest, text: &str, errors: &[String]) -> ChatRequest {
+    let mut repair = request.clone();
+    repair.messages.push(ChatMessage::new("assistant", text));
+    repair.messages.push(ChatMessage::new(
+        "user",
+        format!(
+            "The reply did not match the required JSON schema:\n- {}\nReply again with only the corrected JSON.",
+            errors.join("\n- ")
+        ),
+    ));
+    repair
+}
+
+/// Validates `text` and, while it fails, asks the model to correct it up to
+/// [`MAX_REPAIR_ATTEMPTS`] times. Transport errors during repair are returned
+/// as-is; a reply that never validates is reported through `errors`.
+pub async fn validate_with_repair(
+    backend: &dyn LlmBackend,
+    request: &ChatRequest,
+    schema: &Value,
+    mut text: String,
+) -> Result<StructuredOutput, OllamaError> {
+    let mut repair_attempts = 0;
+    loop {
+        let errors = match check(schema, &text) {
+            Ok(value) => {
+                return Ok(StructuredOutput {
+                    text,
+                    value: Some(value),
+                    errors: Vec::new(),
+                    repair_attempts,
+                })
+            }
+            Err(errors) => errors,
+        };
+        if repair_attempts >= MAX_REPAIR_ATTEMPTS {
+            return Ok(StructuredOutput {
+                text,
+                value: None,
+                errors,
+                repair_attempts,
+            });
+        }
+        repair_attempts += 1;
+        text = backend
+            .chat(&repair_request(request, &text, &errors))
+            .await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_backend::{BackendKind, LineSink};
+    use futures_util::future::BoxFuture;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Replies with canned texts and remembers what it was asked.
+    struct ScriptedBackend {
+        replies: Mutex<Vec<&'static str>>,
+        requests: Mutex<Vec<ChatRequest>>,
+    }
+
+    impl ScriptedBackend {
+        fn new(mut replies: Vec<&'static str>) -> Self {
+            replies.reverse();
+            Self {
+                replies: Mutex::new(replies),
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl LlmBackend for ScriptedBackend {
+        fn kind(&self) -> BackendKind {
+            BackendKind::Ollama
+        }
+
+        fn chat<'a>(
+            &'a self,
+            request: &'a ChatRequest,
+        ) -> BoxFuture<'a, Result<String, OllamaError>> {
+            self.requests.lock().unwrap().push(request.clone());
+            let reply = self.replies.lock().unwrap().pop().unwrap_or("");
+            Box::pin(async move { Ok(reply.to_string()) })
+        }
+
+        fn stream<'a>(
+            &'a self,
+            _request: &'a ChatRequest,
+            _on_line: &'a mut LineSink<'_>,
+        ) -> BoxFuture<'a, Result<(), OllamaError>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn list_models(&self) -> BoxFuture<'_, Result<Vec<String>, OllamaError>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn health(&self) -> BoxFuture<'_, Result<(), OllamaError>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "required": ["steps"],
+            "properties": { "steps": { "type": "array", "minItems": 2 } }
+        })
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest {
+            model: "llama3:8b".into(),
+            messages: vec![ChatMessage::new("user", "storyboard")],
+            options: json!({}),
+            format: Some(schema()),
+        }
+    }
+
+    #[test]
+    fn accepts_fenced_json() {
+        let value = check(&schema(), "```json\n{\"steps\": [1, 2]}\n```").unwrap();
+        assert_eq!(value, json!({"steps": [1, 2]}));
+        assert!(check(&schema(), "Sure! Here it is").unwrap_err()[0].starts_with("invalid JSON"));
+    }
+
+    #[tokio::test]
+    async fn repairs_until_valid() {
+        let backend = ScriptedBackend::new(vec![r#"{"steps": [1, 2, 3]}"#]);
+        let output =
+            validate_with_repair(&backend, &request(), &schema(), r#"{"steps": [1]}"#.into())
+                .await
+                .unwrap();
+        assert!(output.is_valid());
+        assert_eq!(output.repair_attempts, 1);
+        assert_eq!(output.value, Some(json!({"steps": [1, 2, 3]})));
+
+        let sent = backend.requests.lock().unwrap();
+        let messages = &sent[0].messages;
+        assert_eq!(messages[1].role, "assistant");
+        assert!(messages[2].content.contains("expected at least 2 items"));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_bounded_attempts() {
+        let backend = ScriptedBackend::new(vec!["nope", "still nope", "never asked"]);
+        let output = validate_with_repair(&backend, &request(), &schema(), "{}".into())
+            .await
+            .unwrap();
+        assert!(!output.is_valid());
+        assert_eq!(output.repair_attempts, MAX_REPAIR_ATTEMPTS);
+        assert_eq!(output.text, "still nope");
+        assert_eq!(
+            backend.requests.lock().unwrap().len(),
+            MAX_REPAIR_ATTEMPTS as usize
+        );
+    }
+}
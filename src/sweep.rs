@@ -0,0 +1,455 @@
+//! Parameter sweeps: one recipe composed for every combination of a few param
+//! values (or a seeded random sample of them), deduped by prompt sha256 and
+//! optionally run through the batch runner.
+//!
+//! A sweep is saved as one run group under `runs/groups/<group_id>/`:
+//! `group.json` holds the [`SweepReport`], `table.md` the comparison table and,
+//! when the variants ran, `results.jsonl` the batch results. Each run's
+//! `run.json` carries the same `group_id`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::Local;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::batch::{run_batch, BatchResult, BatchRow, PreparedBatch};
+use crate::compose::{compose_prompt_in, data_sandbox, ComposeResult};
+use crate::ollama_stream::EventSink;
+use crate::runs::{new_run_id, runs_root, RunStatus, GROUPS_DIR};
+
+/// Larger sweeps must pass `sample`.
+pub const MAX_SWEEP_VARIANTS: usize = 256;
+/// Longest response excerpt shown in `table.md`.
+const TABLE_RESPONSE_CHARS: usize = 80;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SweepRequest {
+    pub recipe: String,
+    /// Values to try per param, e.g. `{"tone": ["calm", "dry"], "steps": [3, 5]}`.
+    pub axes: BTreeMap<String, Vec<Value>>,
+    /// Params shared by every variant; axis values override them.
+    #[serde(default)]
+    pub params: Map<String, Value>,
+    /// Compose only this many combinations, picked at random.
+    #[serde(default)]
+    pub sample: Option<usize>,
+    /// Seed for `sample`; a random one is recorded when omitted.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Also run every distinct variant.
+    #[serde(default)]
+    pub run: bool,
+    /// Profile or model overriding the recipe's.
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+}
+
+/// One distinct prompt of the sweep.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SweepVariant {
+    pub variant_id: String,
+    /// The axis values of the first combination that produced this prompt.
+    pub params: Map<String, Value>,
+    pub sha256: String,
+    /// Other combinations that composed to the same prompt.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<Map<String, Value>>,
+    #[serde(default)]
+    pub status: Option<RunStatus>,
+    #[serde(default)]
+    pub run_id: Option<String>,
+    #[serde(default)]
+    pub response: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Saved as `group.json` and returned by [`compose_sweep`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SweepReport {
+    pub group_id: String,
+    pub recipe: String,
+    pub axes: BTreeMap<String, Vec<Value>>,
+    /// Size of the full cartesian product.
+    pub combinations: usize,
+    /// Combinations composed (all of them unless sampled).
+    pub composed: usize,
+    /// Set when the combinations were sampled.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    pub variants: Vec<SweepVariant>,
+    pub group_dir: String,
+    pub created_at: String,
+}
+
+/// The composed half of a sweep, before anything runs.
+#[derive(Debug)]
+pub struct SweepPlan {
+    pub combinations: usize,
+    pub composed: usize,
+    pub seed: Option<u64>,
+    pub variants: Vec<SweepVariant>,
+    prompts: Vec<ComposeResult>,
+}
+
+/// Size of the cartesian product of `axes`.
+pub fn count_combinations(axes: &BTreeMap<String, Vec<Value>>) -> Result<usize, String> {
+    if axes.is_empty() {
+        return Err("a sweep needs at least one axis".into());
+    }
+    axes.iter().try_fold(1usize, |total, (key, values)| {
+        if values.is_empty() {
+            return Err(format!("axis `{}` has no values", key));
+        }
+        total
+            .checked_mul(values.len())
+            .ok_or_else(|| "too many combinations".to_string())
+    })
+}
+
+/// The `index`-th combination, counting with the last axis changing fastest.
+fn combination(axes: &BTreeMap<String, Vec<Value>>, mut index: usize) -> Map<String, Value> {
+    let mut params = Map::new();
+    for (key, values) in axes.iter().rev() {
+        params.insert(key.clone(), values[index % values.len()].clone());
+        index /= values.len();
+    }
+    params
+}
+
+/// Picks the combinations to compose: all of them, or `sample` distinct ones
+/// in product order.
+fn combination_indices(
+    total: usize,
+    sample: Option<usize>,
+    seed: Option<u64>,
+) -> Result<(Vec<usize>, Option<u64>), String> {
+    match sample {
+        Some(0) => Err("sample must be at least 1".into()),
+        Some(amount) if amount < total => {
+            if amount > MAX_SWEEP_VARIANTS {
+                return Err(format!("sample is capped at {}", MAX_SWEEP_VARIANTS));
+            }
+            let seed = seed.unwrap_or_else(rand::random);
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut indices = rand::seq::index::sample(&mut rng, total, amount).into_vec();
+            indices.sort_unstable();
+            Ok((indices, Some(seed)))
+        }
+        _ if total > MAX_SWEEP_VARIANTS => Err(format!(
+            "{} combinations exceed the limit of {}; pass a sample size",
+            total, MAX_SWEEP_VARIANTS
+        )),
+        _ => Ok(((0..total).collect(), None)),
+    }
+}
+
+/// Composes the chosen combinations against `data_dir` and folds the ones that
+/// produce the same prompt into one variant. A combination that fails to
+/// compose fails the whole sweep.
+pub fn plan_sweep(request: &SweepRequest, data_dir: &Path) -> Result<SweepPlan, String> {
+    let combinations = count_combinations(&request.axes)?;
+    let (indices, seed) = combination_indices(combinations, request.sample, request.seed)?;
+    let width = indices.len().to_string().len().max(2);
+    let mut variants: Vec<SweepVariant> = Vec::new();
+    let mut prompts = Vec::new();
+    let mut by_sha: HashMap<String, usize> = HashMap::new();
+    for &index in &indices {
+        let axis_params = combination(&request.axes, index);
+        let mut params = request.params.clone();
+        params.extend(axis_params.clone());
+        let composed = compose_prompt_in(data_dir, &request.recipe, Some(Value::Object(params)))
+            .map_err(|e| format!("{}: {}", Value::Object(axis_params.clone()), e))?;
+        if let Some(&existing) = by_sha.get(&composed.sha256) {
+            variants[existing].duplicates.push(axis_params);
+            continue;
+        }
+        by_sha.insert(composed.sha256.clone(), variants.len());
+        variants.push(SweepVariant {
+            variant_id: format!("v{:0width$}", variants.len() + 1, width = width),
+            params: axis_params,
+            sha256: composed.sha256.clone(),
+            duplicates: Vec::new(),
+            status: None,
+            run_id: None,
+            response: None,
+            error: None,
+        });
+        prompts.push(composed);
+    }
+    Ok(SweepPlan {
+        combinations,
+        composed: indices.len(),
+        seed,
+        variants,
+        prompts,
+    })
+}
+
+fn table_cell(value: &str) -> String {
+    value
+        .replace('|', "\\|")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn excerpt(text: &str) -> String {
+    let text = table_cell(text);
+    match text.char_indices().nth(TABLE_RESPONSE_CHARS) {
+        Some((at, _)) => format!("{}…", &text[..at]),
+        None => text,
+    }
+}
+
+/// Markdown comparison table: one row per variant with its prompt hash, axis
+/// values and (excerpted) response.
+pub fn render_table(report: &SweepReport) -> String {
+    let mut header = vec!["variant".to_string(), "sha256".to_string()];
+    header.extend(report.axes.keys().cloned());
+    header.extend(["status".to_string(), "response".to_string()]);
+    let mut out = format!(
+        "# {} — {}\n\n| {} |\n|{}\n",
+        report.group_id,
+        report.recipe,
+        header.join(" | "),
+        " --- |".repeat(header.len())
+    );
+    for variant in &report.variants {
+        let mut cells = vec![variant.variant_id.clone(), variant.sha256[..12].to_string()];
+        for key in report.axes.keys() {
+            cells.push(match variant.params.get(key) {
+                Some(Value::String(s)) => table_cell(s),
+                Some(value) => table_cell(&value.to_string()),
+                None => String::new(),
+            });
+        }
+        cells.push(match variant.status {
+            Some(status) => serde_json::to_value(status)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_default(),
+            None => "-".into(),
+        });
+        cells.push(match (&variant.response, &variant.error) {
+            (Some(response), _) if !response.is_empty() => excerpt(response),
+            (_, Some(error)) => excerpt(error),
+            _ => "-".into(),
+        });
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    let duplicates: usize = report.variants.iter().map(|v| v.duplicates.len()).sum();
+    if duplicates > 0 {
+        out.push_str(&format!(
+            "\n{} combination(s) composed to an already listed prompt; see group.json.\n",
+            duplicates
+        ));
+    }
+    out
+}
+
+/// Plans the sweep, runs the variants when asked and writes the group folder
+/// under `runs_root/groups/`. Runs report progress as `batch:progress` with the
+/// group id as batch id.
+pub async fn run_sweep(
+    sink: Arc<dyn EventSink>,
+    request: &SweepRequest,
+    data_dir: PathBuf,
+    runs_root: PathBuf,
+) -> Result<SweepReport, String> {
+    let plan = plan_sweep(request, &data_dir)?;
+    let group_id = format!("sweep-{}", new_run_id());
+    let group_dir = runs_root.join(GROUPS_DIR).join(&group_id);
+    fs::create_dir_all(&group_dir).map_err(|e| e.to_string())?;
+
+    let mut variants = plan.variants;
+    if request.run {
+        let mut batch = PreparedBatch::new(
+            group_id.clone(),
+            group_dir.join("results.jsonl"),
+            request.recipe.clone(),
+            data_dir,
+        );
+        batch.model = request.model.clone();
+        if let Some(concurrency) = request.concurrency {
+            batch.concurrency = concurrency.max(1);
+        }
+        batch.runs_root = runs_root.clone();
+        batch.group_id = Some(group_id.clone());
+        for (variant, composed) in variants.iter().zip(plan.prompts) {
+            let mut params = request.params.clone();
+            params.extend(variant.params.clone());
+            let row = BatchRow {
+                id: variant.variant_id.clone(),
+                params,
+            };
+            batch.push_row(row, composed);
+        }
+        run_batch(sink, batch).await?;
+        let results =
+            fs::read_to_string(group_dir.join("results.jsonl")).map_err(|e| e.to_string())?;
+        for result in results
+            .lines()
+            .filter_map(|line| serde_json::from_str::<BatchResult>(line).ok())
+        {
+            if let Some(variant) = variants.iter_mut().find(|v| v.variant_id == result.row_id) {
+                variant.status = Some(result.status);
+                variant.run_id = result.run_id;
+                variant.response = Some(result.response);
+                variant.error = result.error;
+            }
+        }
+    }
+
+    let report = SweepReport {
+        group_id,
+        recipe: request.recipe.clone(),
+        axes: request.axes.clone(),
+        combinations: plan.combinations,
+        composed: plan.composed,
+        seed: plan.seed,
+        variants,
+        group_dir: group_dir.display().to_string(),
+        created_at: Local::now().to_rfc3339(),
+    };
+    let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+    fs::write(group_dir.join("group.json"), json).map_err(|e| e.to_string())?;
+    fs::write(group_dir.join("table.md"), render_table(&report)).map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+/// [`run_sweep`] against the configured data and runs directories.
+pub async fn compose_sweep(
+    sink: Arc<dyn EventSink>,
+    request: SweepRequest,
+) -> Result<SweepReport, String> {
+    run_sweep(sink, &request, data_sandbox(), runs_root()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_ollama::{MockConfig, MockOllama};
+    use crate::ollama_stream::ChannelSink;
+    use crate::runs::read_run_meta;
+    use crate::test_support::{data_fixture, write};
+    use serde_json::json;
+    use tempfile::tempdir;
+    use tokio::sync::mpsc;
+
+    /// A recipe that uses `tone` and `steps` but ignores `unused`.
+    fn data_dir(base: &Path, base_url: &str) -> PathBuf {
+        let data = data_fixture(base, base_url);
+        write(
+            &data,
+            "fragments/task/plan.yaml",
+            "id: task.plan\nkind: task\ncontent: \"Plan {{steps}} shots in a {{tone}} tone.\"\n",
+        );
+        write(
+            &data,
+            "recipes/plan.yaml",
+            "profile: mock\nfragments:\n  - task.plan\nparams:\n  steps: 3\n",
+        );
+        data
+    }
+
+    fn request(axes: Value) -> SweepRequest {
+        SweepRequest {
+            recipe: "recipes/plan.yaml".into(),
+            axes: serde_json::from_value(axes).unwrap(),
+            params: Map::new(),
+            sample: None,
+            seed: None,
+            run: false,
+            model: None,
+            concurrency: None,
+        }
+    }
+
+    #[test]
+    fn composes_product_samples_and_dedupes() {
+        let temp = tempdir().unwrap();
+        let data = data_dir(temp.path(), "http://127.0.0.1:9");
+
+        let plan = plan_sweep(
+            &request(json!({"tone": ["calm", "dry"], "unused": [1, 2]})),
+            &data,
+        )
+        .unwrap();
+        assert_eq!((plan.combinations, plan.composed), (4, 4));
+        assert_eq!(plan.variants.len(), 2);
+        assert_eq!(
+            Value::Object(plan.variants[0].params.clone()),
+            json!({"tone": "calm", "unused": 1})
+        );
+        assert_eq!(
+            plan.variants[0].duplicates,
+            vec![json!({"tone": "calm", "unused": 2})
+                .as_object()
+                .unwrap()
+                .clone()]
+        );
+
+        let sampled = SweepRequest {
+            sample: Some(3),
+            seed: Some(7),
+            ..request(json!({"tone": ["calm", "dry", "warm"], "steps": [3, 5]}))
+        };
+        let first = plan_sweep(&sampled, &data).unwrap();
+        let again = plan_sweep(&sampled, &data).unwrap();
+        assert_eq!((first.combinations, first.composed), (6, 3));
+        assert_eq!(first.seed, Some(7));
+        assert_eq!(first.variants, again.variants);
+
+        assert!(plan_sweep(&request(json!({"tone": []})), &data).is_err());
+        let wide: Vec<usize> = (0..MAX_SWEEP_VARIANTS + 1).collect();
+        assert!(plan_sweep(&request(json!({ "steps": wide })), &data)
+            .unwrap_err()
+            .contains("sample"));
+    }
+
+    #[tokio::test]
+    async fn runs_variants_as_one_group() {
+        let temp = tempdir().unwrap();
+        let mock = MockOllama::start(MockConfig::default()).await.unwrap();
+        let data = data_dir(temp.path(), &mock.url());
+        let runs_root = temp.path().join("runs");
+        let (tx, _events) = mpsc::unbounded_channel();
+        let request = SweepRequest {
+            run: true,
+            ..request(json!({"tone": ["calm", "dry"], "steps": [3, 5]}))
+        };
+
+        let report = run_sweep(Arc::new(ChannelSink(tx)), &request, data, runs_root.clone())
+            .await
+            .unwrap();
+        assert_eq!(report.variants.len(), 4);
+        assert_eq!(mock.requests_to("/api/chat").len(), 4);
+        for variant in &report.variants {
+            assert_eq!(variant.status, Some(RunStatus::Completed));
+            assert_eq!(variant.response.as_deref(), Some("This is a mock reply."));
+            let meta = read_run_meta(&runs_root, variant.run_id.as_deref().unwrap()).unwrap();
+            assert_eq!(meta.group_id.as_deref(), Some(report.group_id.as_str()));
+        }
+
+        let group_dir = PathBuf::from(&report.group_dir);
+        let saved: SweepReport =
+            serde_json::from_str(&fs::read_to_string(group_dir.join("group.json")).unwrap())
+                .unwrap();
+        assert_eq!(saved, report);
+        let table = fs::read_to_string(group_dir.join("table.md")).unwrap();
+        assert!(table.contains("| variant | sha256 | steps | tone | status | response |"));
+        assert!(table.contains(&format!(
+            "| v01 | {} | 3 | calm | completed | This is a mock reply. |",
+            &report.variants[0].sha256[..12]
+        )));
+    }
+}
@@ -0,0 +1,107 @@
+//! Helpers shared by unit and integration tests; not part of the public API.
+
+use std::ffi::OsString;
+use std::path::Path;
+
+pub use crate::compose::compose_prompt;
+
+/// Fixtures for the crate's own unit tests.
+#[cfg(test)]
+mod unit {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use chrono::Local;
+    use serde_json::Value;
+
+    use crate::runs::StreamRunRequest;
+
+    /// Writes `content` to `base/rel`, creating the folders on the way.
+    pub fn write(base: &Path, rel: &str, content: &str) {
+        let path = base.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// A data folder at `base/data` with `recipes/echo.yaml`: profile `mock`
+    /// (`llama3:8b` served at `base_url`) and one `task.echo` fragment,
+    /// "Answer in a {{tone}} tone." with `tone: plain` by default.
+    pub fn data_fixture(base: &Path, base_url: &str) -> PathBuf {
+        let data = base.join("data");
+        write(
+            &data,
+            "fragments/task/echo.yaml",
+            "id: task.echo\nkind: task\ncontent: \"Answer in a {{tone}} tone.\"\n",
+        );
+        write(
+            &data,
+            "recipes/echo.yaml",
+            "profile: mock\nfragments:\n  - task.echo\nparams:\n  tone: plain\n",
+        );
+        write(
+            &data,
+            "profiles/mock.yaml",
+            &format!("model: llama3:8b\nbase_url: {}\n", base_url),
+        );
+        data
+    }
+
+    impl StreamRunRequest {
+        /// A raw `llama3:8b` prompt started now, with nothing else recorded.
+        pub fn test_default() -> Self {
+            StreamRunRequest {
+                model: "llama3:8b".into(),
+                recipe_path: None,
+                system_text: String::new(),
+                user_text: String::new(),
+                images: Vec::new(),
+                output_schema: None,
+                started_at: Local::now().to_rfc3339(),
+                group_id: None,
+                profile: None,
+                params: Value::Null,
+                fragments: Vec::new(),
+                ollama_version: None,
+                replay_of: None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub use unit::{data_fixture, write};
+
+/// Points `PROMPTFORGE_DATA_DIR` at `path` until dropped.
+pub struct DataDirGuard {
+    prev: Option<OsString>,
+}
+
+impl DataDirGuard {
+    pub fn set(path: &Path) -> Self {
+        let prev = std::env::var_os("PROMPTFORGE_DATA_DIR");
+        std::env::set_var("PROMPTFORGE_DATA_DIR", path);
+        Self { prev }
+    }
+}
+
+impl Drop for DataDirGuard {
+    fn drop(&mut self) {
+        if let Some(ref value) = self.prev {
+            std::env::set_var("PROMPTFORGE_DATA_DIR", value);
+        } else {
+            std::env::remove_var("PROMPTFORGE_DATA_DIR");
+        }
+    }
+}
+
+pub mod project_test_support {
+    pub use crate::files::{read_project_file, write_project_file, FileContent};
+}
+
+pub mod project_io_test_support {
+    pub use crate::files::{list_project_files, read_project_file, write_project_file};
+}
+
+pub mod prompt_test_support {
+    pub use crate::files::{list_prompt_files, read_prompt_file, FileContent, PromptFileEntry};
+}
@@ -1,4 +1,3 @@
-use std::ffi::OsString;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -6,29 +5,7 @@ use hex::encode;
 use sha2::{Digest, Sha256};
 use tempfile::{tempdir, tempdir_in};
 
-pub use super::_compose_prompt;
-
-pub struct DataDirGuard {
-    prev: Option<OsString>,
-}
-
-impl DataDirGuard {
-    pub fn set(path: &Path) -> Self {
-        let prev = std::env::var_os("PROMPTFORGE_DATA_DIR");
-        std::env::set_var("PROMPTFORGE_DATA_DIR", path);
-        Self { prev }
-    }
-}
-
-impl Drop for DataDirGuard {
-    fn drop(&mut self) {
-        if let Some(ref value) = self.prev {
-            std::env::set_var("PROMPTFORGE_DATA_DIR", value);
-        } else {
-            std::env::remove_var("PROMPTFORGE_DATA_DIR");
-        }
-    }
-}
+use crate::txt_excerpt::load_txt_excerpt;
 
 fn ensure_corpus_dir() -> PathBuf {
     let base = PathBuf::from("corpus");
@@ -38,6 +15,16 @@ fn ensure_corpus_dir() -> PathBuf {
     base
 }
 
+/// `tempdir_in` anchors relative dirs at the working directory.
+fn corpus_relative(base: &Path, path: &Path) -> String {
+    let absolute_base = std::env::current_dir().expect("current dir").join(base);
+    let rel = path
+        .strip_prefix(&absolute_base)
+        .or_else(|_| path.strip_prefix(base))
+        .expect("strip corpus prefix");
+    to_forward_slash(rel)
+}
+
 fn to_forward_slash(path: &Path) -> String {
     path.to_string_lossy().replace('\\', "/")
 }
@@ -50,10 +37,9 @@ fn load_txt_excerpt_without_limit_returns_full_content() {
     let content = "Alpha\nBeta\nGamma";
     fs::write(&file_path, content).expect("failed to write sample");
 
-    let rel = file_path.strip_prefix(&base).expect("strip corpus prefix");
-    let rel_str = to_forward_slash(rel);
+    let rel_str = corpus_relative(&base, &file_path);
 
-    let result = super::load_txt_excerpt(rel_str.clone(), None).expect("load excerpt");
+    let result = load_txt_excerpt(&rel_str, None).expect("load excerpt");
     let canonical = file_path.canonicalize().expect("canonicalize file");
 
     assert_eq!(result.path, canonical.to_string_lossy());
@@ -77,11 +63,10 @@ fn load_txt_excerpt_applies_head_tail_with_limit() {
     let content = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
     fs::write(&file_path, content).expect("failed to write long file");
 
-    let rel = file_path.strip_prefix(&base).expect("strip corpus prefix");
-    let rel_str = to_forward_slash(rel);
+    let rel_str = corpus_relative(&base, &file_path);
 
     let max_bytes = 20;
-    let result = super::load_txt_excerpt(rel_str, Some(max_bytes)).expect("load excerpt");
+    let result = load_txt_excerpt(&rel_str, Some(max_bytes)).expect("load excerpt");
     assert!(result.truncated);
     assert_eq!(result.used_bytes, max_bytes as u64);
 
@@ -107,7 +92,7 @@ fn load_txt_excerpt_returns_not_found_for_missing_file() {
     let base = ensure_corpus_dir();
     let rel = Path::new("missing.txt");
     let rel_str = to_forward_slash(rel);
-    let err = super::load_txt_excerpt(rel_str, None).expect_err("expected error");
+    let err = load_txt_excerpt(&rel_str, None).expect_err("expected error");
     assert_eq!(err, "file not found");
     // keep base alive
     drop(base);
@@ -116,13 +101,14 @@ fn load_txt_excerpt_returns_not_found_for_missing_file() {
 #[test]
 fn load_txt_excerpt_rejects_out_of_sandbox() {
     let base = ensure_corpus_dir();
-    let err = super::load_txt_excerpt("../src/main.rs".into(), None).expect_err("expected error");
+    let err = load_txt_excerpt("../src/main.rs", None).expect_err("expected error");
     assert_eq!(err, "path out of sandbox");
     drop(base);
 }
 
 mod compose_prompt_sandbox {
-    use super::{DataDirGuard, _compose_prompt};
+    use super::tempdir;
+    use crate::test_support::{compose_prompt, DataDirGuard};
     use std::fs;
     use std::path::Path;
 
@@ -155,7 +141,7 @@ mod compose_prompt_sandbox {
 
         let outside = temp.path().join("../escape.yaml");
         let err =
-            _compose_prompt(outside.to_string_lossy().as_ref(), None).expect_err("expected error");
+            compose_prompt(outside.to_string_lossy().as_ref(), None).expect_err("expected error");
         assert_eq!(err.to_string(), "path out of sandbox");
     }
 
@@ -174,7 +160,7 @@ mod compose_prompt_sandbox {
         .expect("failed to write recipe");
 
         let _guard = DataDirGuard::set(temp.path());
-        let err = _compose_prompt(recipe_path.to_string_lossy().as_ref(), None)
+        let err = compose_prompt(recipe_path.to_string_lossy().as_ref(), None)
             .expect_err("expected error");
         assert_eq!(err.to_string(), "path out of sandbox");
     }
@@ -185,7 +171,182 @@ mod compose_prompt_sandbox {
         let recipe_path = write_valid_fixture(temp.path());
         let _guard = DataDirGuard::set(temp.path());
 
-        let result = _compose_prompt(&recipe_path, None).expect("compose prompt");
+        let result = compose_prompt(&recipe_path, None).expect("compose prompt");
         assert!(result.final_prompt.contains("Hello"));
     }
 }
+
+mod chat_stream_e2e {
+    use crate::chat::{start_chat_stream, ChatStreamJob};
+    use crate::llm_backend::{ChatMessage, ChatRequest, OllamaBackend};
+    use crate::llm_replay::SessionMode;
+    use crate::mock_ollama::{MockConfig, MockOllama, MockReply};
+    use crate::ollama_client::{OllamaClientConfig, OllamaErrorKind, OllamaTimeouts, RetryPolicy};
+    use crate::ollama_stream::{ChannelSink, EventSink, StreamState};
+    use crate::runs::{RunStatus, StreamRunRequest};
+    use serde_json::{json, Value};
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+    use tokio::sync::mpsc;
+
+    struct Harness {
+        mock: MockOllama,
+        state: StreamState,
+        events: mpsc::UnboundedReceiver<(String, Value)>,
+        sink: Arc<dyn EventSink>,
+    }
+
+    impl Harness {
+        async fn new() -> Self {
+            let (tx, events) = mpsc::unbounded_channel();
+            Self {
+                mock: MockOllama::start(MockConfig::default()).await.unwrap(),
+                state: StreamState::with_limit(2),
+                events,
+                sink: Arc::new(ChannelSink(tx)),
+            }
+        }
+
+        async fn start(&self, runs_root: &Path) -> usize {
+            let config = OllamaClientConfig {
+                base_url: self.mock.url(),
+                timeouts: OllamaTimeouts {
+                    connect: Duration::from_millis(200),
+                    first_token: Duration::from_millis(300),
+                    idle: Duration::from_millis(300),
+                    request: Duration::from_millis(500),
+                },
+                retry: RetryPolicy {
+                    max_retries: 0,
+                    ..RetryPolicy::default()
+                },
+            };
+            let job = ChatStreamJob {
+                backend: Some(Box::new(OllamaBackend::new(config))),
+                mode: SessionMode::Live,
+                request: ChatRequest {
+                    model: "llama3:8b".into(),
+                    messages: vec![ChatMessage::new("user", "hi")],
+                    options: json!({}),
+                    format: None,
+                },
+                run_request: StreamRunRequest {
+                    user_text: "hi".into(),
+                    started_at: "2024-01-01T00:00:00+00:00".into(),
+                    ..StreamRunRequest::test_default()
+                },
+                runs_root: runs_root.to_path_buf(),
+            };
+            start_chat_stream(self.sink.clone(), &self.state, job)
+                .await
+                .expect("stream slot")
+        }
+
+        /// Collects events up to and including `last`.
+        async fn events_until(&mut self, last: &str) -> Vec<(String, Value)> {
+            let mut seen = Vec::new();
+            while let Some((event, payload)) =
+                tokio::time::timeout(Duration::from_secs(5), self.events.recv())
+                    .await
+                    .expect("event in time")
+            {
+                let done = event == last;
+                seen.push((event, payload));
+                if done {
+                    break;
+                }
+            }
+            seen
+        }
+    }
+
+    fn names(events: &[(String, Value)]) -> Vec<&str> {
+        events.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn streams_to_completion_and_saves_the_run() {
+        let mut harness = Harness::new().await;
+        harness
+            .mock
+            .push("/api/chat", MockReply::tokens(["Hel", "lo"]).delay(20));
+        let runs = tempdir().unwrap();
+        harness.start(runs.path()).await;
+
+        let events = harness.events_until("ollama:saved").await;
+        assert_eq!(
+            names(&events),
+            vec!["ollama:chunk", "ollama:chunk", "ollama:end", "ollama:saved"]
+        );
+        let saved = &events[3].1;
+        assert_eq!(saved["status"], "completed");
+        assert_eq!(saved["partial_text"], "Hello");
+        let run_dir = runs.path().join(saved["run_id"].as_str().unwrap());
+        assert_eq!(
+            std::fs::read_to_string(run_dir.join("response.txt")).unwrap(),
+            "Hello"
+        );
+        let meta: Value =
+            serde_json::from_str(&std::fs::read_to_string(run_dir.join("run.json")).unwrap())
+                .unwrap();
+        assert_eq!(meta["ollama_version"], "0.5.7");
+        assert!(harness.state.list().await.is_empty());
+        assert_eq!(
+            harness.mock.requests_to("/api/chat")[0].body["stream"],
+            true
+        );
+    }
+
+    #[tokio::test]
+    async fn abort_keeps_partial_text() {
+        let mut harness = Harness::new().await;
+        harness
+            .mock
+            .push("/api/chat", MockReply::tokens(["Hel"]).delay(5_000));
+        let runs = tempdir().unwrap();
+        let id = harness.start(runs.path()).await;
+        harness.events_until("ollama:chunk").await;
+
+        let receiver = harness.state.abort(id).await.expect("running");
+        let outcome = receiver.await.expect("outcome");
+        assert_eq!(outcome.status, RunStatus::Aborted);
+        assert_eq!(outcome.partial_text, "Hel");
+        assert!(outcome.run_id.is_some());
+    }
+
+    #[tokio::test]
+    async fn timeouts_disconnects_and_error_chunks_fail_the_run() {
+        let mut harness = Harness::new().await;
+        let cases = [
+            (
+                MockReply::tokens(["a"]).hang(),
+                Some(OllamaErrorKind::Timeout),
+            ),
+            // reqwest reports a truncated chunked body as a decode error.
+            (MockReply::tokens(["a"]).disconnect(), None),
+            (
+                MockReply::tokens(["a"]).error_chunk("oom"),
+                Some(OllamaErrorKind::Model),
+            ),
+        ];
+        for (reply, kind) in cases {
+            harness.mock.push("/api/chat", reply);
+            let runs = tempdir().unwrap();
+            harness.start(runs.path()).await;
+            let events = harness.events_until("ollama:saved").await;
+            let error = events
+                .iter()
+                .find(|(name, _)| name == "ollama:error")
+                .map(|(_, payload)| payload)
+                .expect("error event");
+            if let Some(kind) = kind {
+                assert_eq!(error["kind"], json!(kind));
+            }
+            let saved = &events.last().unwrap().1;
+            assert_eq!(saved["status"], "failed");
+            assert_eq!(saved["partial_text"], "a");
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::fs;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 use serde::Serialize;
 use sha2::{Digest, Sha256};
@@ -18,7 +18,15 @@ pub struct TxtExcerpt {
 }
 
 pub fn load_txt_excerpt(path: &str, max_bytes: Option<u64>) -> Result<TxtExcerpt, String> {
-    let base = PathBuf::from("corpus");
+    load_txt_excerpt_in(Path::new("corpus"), path, max_bytes)
+}
+
+/// [`load_txt_excerpt`] for the corpus folder `base`.
+pub fn load_txt_excerpt_in(
+    base: &Path,
+    path: &str,
+    max_bytes: Option<u64>,
+) -> Result<TxtExcerpt, String> {
     let input_path = Path::new(path);
     let target = if input_path.is_absolute() {
         input_path.to_path_buf()
@@ -30,7 +38,7 @@ pub fn load_txt_excerpt(path: &str, max_bytes: Option<u64>) -> Result<TxtExcerpt
         return Err("file not found".into());
     }
 
-    ensure_under(&base, &target).map_err(|e| e.to_string())?;
+    ensure_under(base, &target).map_err(|e| e.to_string())?;
 
     let metadata = fs::metadata(&target).map_err(|e| e.to_string())?;
     let size_bytes = metadata.len();
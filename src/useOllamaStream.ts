@@ -5,9 +5,22 @@ import { getCurrentWindow } from '@tauri-apps/api/window'
 
 type UnlistenFn = () => void | Promise<void>
 
-type StreamArgs = { model: string; systemText: string; userText: string }
+type StreamArgs = { model: string; systemText: string; userText: string; recipePath?: string; images?: string[]; outputSchema?: unknown; params?: unknown }
+
+export type GenerationStats = {
+  total_duration?: number | null
+  load_duration?: number | null
+  prompt_eval_count?: number | null
+  prompt_eval_duration?: number | null
+  eval_count?: number | null
+  eval_duration?: number | null
+  tokens_per_second?: number | null
+  time_to_first_token_ms?: number | null
+}
+
+type StreamEventPayload = { stream_id?: number; text?: string; message?: string; stats?: GenerationStats }
 
-type StreamHandlers = { onChunk?: (chunk: string) => void; onEnd?: () => void; onError?: (message: string) => void }
+type StreamHandlers = { onChunk?: (chunk: string) => void; onEnd?: (stats?: GenerationStats) => void; onError?: (message: string) => void }
 
 type StreamState = { startStream: (args: StreamArgs) => Promise<void>; abortStream: () => Promise<void>; appendChunk: (chunk: string) => void; isStreaming: boolean }
 
@@ -16,6 +29,7 @@ export const useOllamaStream = (handlers: StreamHandlers = {}): StreamState => {
   const handlerRef = useRef(handlers)
   const unlistenRef = useRef<UnlistenFn[] | null>(null)
   const streamingRef = useRef(false)
+  const streamIdRef = useRef<number | null>(null)
 
   useEffect(() => { handlerRef.current = handlers }, [handlers])
 
@@ -28,12 +42,13 @@ export const useOllamaStream = (handlers: StreamHandlers = {}): StreamState => {
     await Promise.allSettled(current.map(fn => Promise.resolve(fn())))
   }, [])
 
-  const finalize = useCallback((kind: 'end' | 'error', reason?: unknown) => {
+  const finalize = useCallback((kind: 'end' | 'error', reason?: unknown, stats?: GenerationStats) => {
     if (!streamingRef.current) return
     streamingRef.current = false
+    streamIdRef.current = null
     setIsStreaming(false)
     void clearListeners()
-    if (kind === 'end') handlerRef.current.onEnd?.()
+    if (kind === 'end') handlerRef.current.onEnd?.(stats)
     else handlerRef.current.onError?.(reason instanceof Error ? reason.message : String(reason ?? ''))
   }, [clearListeners])
 
@@ -43,14 +58,20 @@ export const useOllamaStream = (handlers: StreamHandlers = {}): StreamState => {
     setIsStreaming(true)
     const unlisteners: UnlistenFn[] = []
     const window = getCurrentWindow()
-    const register = async (name: string, cb: (event: unknown) => void) =>
-      unlisteners.push(await window.listen(name, cb as (event: unknown) => void))
-    await register('ollama:chunk', (event: { payload: string }) => appendChunk(event.payload))
-    await register('ollama:end', () => finalize('end'))
-    await register('ollama:error', event => finalize('error', (event as { payload?: unknown }).payload))
+    // Other streams (tabs, background jobs) share the event names; only follow our own id.
+    const isOwn = (payload?: StreamEventPayload) =>
+      streamIdRef.current === null || payload?.stream_id === undefined || payload.stream_id === streamIdRef.current
+    const register = async (name: string, cb: (payload?: StreamEventPayload) => void) =>
+      unlisteners.push(await window.listen(name, (event: { payload?: StreamEventPayload }) => {
+        if (isOwn(event.payload)) cb(event.payload)
+      }))
+    await register('ollama:chunk', payload => appendChunk(payload?.text ?? ''))
+    await register('ollama:end', payload => finalize('end', undefined, payload?.stats))
+    await register('ollama:error', payload => finalize('error', payload?.message ?? payload))
     unlistenRef.current = unlisteners
     try {
-      await invoke('run_ollama_stream', args)
+      const streamId = await invoke<number>('run_ollama_stream', args)
+      if (streamingRef.current && typeof streamId === 'number') streamIdRef.current = streamId
     } catch (error) {
       finalize('error', error)
       throw error
@@ -60,7 +81,9 @@ export const useOllamaStream = (handlers: StreamHandlers = {}): StreamState => {
   const abortStream = useCallback(async () => {
     if (!streamingRef.current) return
     try {
-      await invoke('abort_current_stream')
+      const streamId = streamIdRef.current
+      if (streamId === null) await invoke('abort_current_stream')
+      else await invoke('abort_stream', { streamId })
     } finally {
       finalize('end')
     }
@@ -83,7 +106,7 @@ if (import.meta.vitest) {
     let listeners: Record<string, (event: HandlerPayload) => void>
     let resolveRun: (() => void) | null
     let rejectRun: ((error: unknown) => void) | null
-    let runOverride: (() => Promise<void>) | null
+    let runOverride: (() => Promise<unknown>) | null
 
     const mount = (handlers?: StreamHandlers) => {
       const root = createRoot(document.createElement('div'))
@@ -115,20 +138,20 @@ if (import.meta.vitest) {
           if (runOverride) return await runOverride()
           return await new Promise<void>((resolve, reject) => { resolveRun = resolve; rejectRun = reject })
         }
-        if (cmd === 'abort_current_stream') resolveRun?.()
+        if (cmd === 'abort_current_stream' || cmd === 'abort_stream') resolveRun?.()
         return undefined
       })
     })
 
     it('appends chunks and resolves on end events', async () => {
-      const chunks: string[] = []; const ends: number[] = []
-      const { result, unmount } = mount({ onChunk: chunk => chunks.push(chunk), onEnd: () => ends.push(1) })
+      const chunks: string[] = []; const ends: Array<GenerationStats | undefined> = []
+      const { result, unmount } = mount({ onChunk: chunk => chunks.push(chunk), onEnd: stats => ends.push(stats) })
       const startPromise = result.current?.startStream({ model: 'm', systemText: 's', userText: 'u' }) ?? Promise.resolve()
       await act(async () => { await Promise.resolve() })
       expect(result.current?.isStreaming).toBe(true)
-      listeners['ollama:chunk']?.({ payload: 'hello' }); expect(chunks).toEqual(['hello'])
-      await act(async () => { listeners['ollama:end']?.({}); resolveRun?.(); await startPromise })
-      expect(result.current?.isStreaming).toBe(false); expect(ends.length).toBe(1)
+      listeners['ollama:chunk']?.({ payload: { stream_id: 1, text: 'hello' } }); expect(chunks).toEqual(['hello'])
+      await act(async () => { listeners['ollama:end']?.({ payload: { stream_id: 1, stats: { eval_count: 4, tokens_per_second: 2 } } }); resolveRun?.(); await startPromise })
+      expect(result.current?.isStreaming).toBe(false); expect(ends).toEqual([{ eval_count: 4, tokens_per_second: 2 }])
       unmount()
     })
 
@@ -139,7 +162,7 @@ if (import.meta.vitest) {
       await act(async () => { await Promise.resolve() })
       expect(result.current?.isStreaming).toBe(true)
       result.current?.appendChunk('manual')
-      listeners['ollama:error']?.({ payload: new Error('boom') })
+      listeners['ollama:error']?.({ payload: { stream_id: 1, message: 'boom' } })
       await act(async () => { rejectRun?.(new Error('boom')); await startPromise.catch(() => {}) })
       expect(errors[0]).toBe('boom'); expect(result.current?.isStreaming).toBe(false)
       unmount()
@@ -152,10 +175,24 @@ if (import.meta.vitest) {
       const startPromise = result.current?.startStream({ model: 'm', systemText: 's', userText: 'u' }) ?? Promise.resolve()
       await act(async () => { await Promise.resolve() })
       expect(result.current?.isStreaming).toBe(true)
-      listeners['ollama:chunk']?.({ payload: 'late' })
+      listeners['ollama:chunk']?.({ payload: { stream_id: 1, text: 'late' } })
       expect(chunks).toEqual(['late'])
       await act(async () => { listeners['ollama:end']?.({}); await startPromise })
       unmount()
     })
+
+    it('ignores events from other streams once the id is known', async () => {
+      const chunks: string[] = []
+      runOverride = async () => 7
+      const { result, unmount } = mount({ onChunk: chunk => chunks.push(chunk) })
+      const startPromise = result.current?.startStream({ model: 'm', systemText: 's', userText: 'u' }) ?? Promise.resolve()
+      await act(async () => { await startPromise })
+      listeners['ollama:chunk']?.({ payload: { stream_id: 3, text: 'other' } })
+      listeners['ollama:chunk']?.({ payload: { stream_id: 7, text: 'mine' } })
+      expect(chunks).toEqual(['mine'])
+      await act(async () => { listeners['ollama:end']?.({ payload: { stream_id: 7 } }) })
+      expect(result.current?.isStreaming).toBe(false)
+      unmount()
+    })
   })
 }
@@ -144,5 +144,15 @@ describe('useSetupCheck', () => {
     expect(missingHook.result.current.guidance).toBe('install from backend')
 
     await missingHook.unmount()
+
+    vi.mocked(invoke).mockResolvedValueOnce({ status: 'protocol_error' })
+
+    const protocolHook = await renderHook('phi')
+    await flushEffects()
+
+    expect(protocolHook.result.current.status).toBe('protocol-error')
+    expect(protocolHook.result.current.guidance).toContain('Ollama')
+
+    await protocolHook.unmount()
   })
 })
@@ -2,9 +2,24 @@ import { useCallback, useEffect, useRef, useState } from 'react'
 
 import { invoke } from '@tauri-apps/api/core'
 
-export type SetupStatus = 'ok' | 'offline' | 'missing-model'
-
-type BackendSetupStatus = SetupStatus | 'ready' | 'server_unavailable' | 'model_missing'
+export type SetupStatus =
+  | 'ok'
+  | 'offline'
+  | 'missing-model'
+  | 'protocol-error'
+  | 'unsupported-version'
+  | 'context-mismatch'
+  | 'probe-failed'
+
+type BackendSetupStatus =
+  | SetupStatus
+  | 'ready'
+  | 'server_unavailable'
+  | 'model_missing'
+  | 'protocol_error'
+  | 'version_unsupported'
+  | 'context_mismatch'
+  | 'probe_failed'
 
 type SetupCheckResponse = {
   status: BackendSetupStatus
@@ -19,30 +34,33 @@ type SetupState = {
 const DEFAULT_GUIDANCE: Record<SetupStatus, string> = {
   ok: '',
   'missing-model': 'Required model is not available. Please install the recommended model and retry.',
-  offline: 'Ollama service is not reachable. Please start Ollama and retry.'
+  offline: 'Ollama service is not reachable. Please start Ollama and retry.',
+  'protocol-error': 'The server response could not be understood. Check that the URL points to Ollama.',
+  'unsupported-version': 'The Ollama server is too old. Please upgrade Ollama and retry.',
+  'context-mismatch': "The profile's num_ctx exceeds the model's context length.",
+  'probe-failed': 'The test generation failed. Please check the Ollama logs and retry.'
 }
 
-const isBackendSetupStatus = (value: unknown): value is BackendSetupStatus =>
-  value === 'ok' ||
-  value === 'offline' ||
-  value === 'missing-model' ||
-  value === 'ready' ||
-  value === 'server_unavailable' ||
-  value === 'model_missing'
-
-const normalizeStatus = (status: BackendSetupStatus): SetupStatus => {
-  switch (status) {
-    case 'ready':
-      return 'ok'
-    case 'server_unavailable':
-      return 'offline'
-    case 'model_missing':
-      return 'missing-model'
-    default:
-      return status
-  }
+const BACKEND_STATUS: Record<Exclude<BackendSetupStatus, SetupStatus>, SetupStatus> = {
+  ready: 'ok',
+  server_unavailable: 'offline',
+  model_missing: 'missing-model',
+  protocol_error: 'protocol-error',
+  version_unsupported: 'unsupported-version',
+  context_mismatch: 'context-mismatch',
+  probe_failed: 'probe-failed'
 }
 
+const hasKey = (record: object, key: string): boolean => Object.prototype.hasOwnProperty.call(record, key)
+
+const isBackendSetupStatus = (value: unknown): value is BackendSetupStatus =>
+  typeof value === 'string' && (hasKey(DEFAULT_GUIDANCE, value) || hasKey(BACKEND_STATUS, value))
+
+const normalizeStatus = (status: BackendSetupStatus): SetupStatus =>
+  hasKey(BACKEND_STATUS, status)
+    ? BACKEND_STATUS[status as keyof typeof BACKEND_STATUS]
+    : (status as SetupStatus)
+
 const normalizeResponse = (response: unknown): SetupState => {
   if (
     response &&
@@ -0,0 +1,63 @@
+//! Editor state persisted between sessions as `workspace.json`, with the
+//! previous copy kept as `workspace.bak`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+pub const WORKSPACE_FILE: &str = "workspace.json";
+pub const WORKSPACE_BACKUP_FILE: &str = "workspace.bak";
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Workspace {
+    pub version: u32,
+    pub left_text: String,
+    pub right_text: String,
+    pub recipe_path: String,
+    pub model: String,
+    pub params: serde_json::Value,
+    pub project_path: Option<String>,
+    pub updated_at: String,
+}
+
+/// `None` when nothing has been saved at `path` yet.
+pub fn read_workspace(path: &Path) -> Result<Option<Workspace>, String> {
+    match fs::read_to_string(path) {
+        Ok(s) => {
+            let ws: Workspace = serde_json::from_str(&s).map_err(|e| e.to_string())?;
+            Ok(Some(ws))
+        }
+        Err(err) => {
+            if err.kind() == io::ErrorKind::NotFound {
+                Ok(None)
+            } else {
+                Err(err.to_string())
+            }
+        }
+    }
+}
+
+/// Writes `ws` to `path`, first copying the old file to `workspace.bak`. A
+/// failed backup does not block the save; it is appended to the returned path
+/// as a warning.
+pub fn write_workspace(path: &Path, ws: &Workspace) -> Result<String, String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let backup_path = path.with_file_name(WORKSPACE_BACKUP_FILE);
+    let mut warning: Option<String> = None;
+    if path.exists() {
+        if let Err(err) = fs::copy(path, &backup_path) {
+            warning = Some(format!("failed to create workspace backup: {}", err));
+        }
+    }
+    let s = serde_json::to_string_pretty(ws).map_err(|e| e.to_string())?;
+    fs::write(path, s).map_err(|e| e.to_string())?;
+    if let Some(warning) = warning {
+        Ok(format!("{} (warning: {})", path.display(), warning))
+    } else {
+        Ok(path.display().to_string())
+    }
+}
@@ -2,7 +2,7 @@ use std::env;
 use std::fs;
 use std::sync::{Mutex, MutexGuard, OnceLock};
 
-use promptforge::tests::{DataDirGuard, _compose_prompt};
+use promptforge::test_support::{compose_prompt, DataDirGuard};
 
 fn env_lock() -> MutexGuard<'static, ()> {
     static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
@@ -50,7 +50,7 @@ content: |
 
     let recipe_path = recipes_dir.join("demo.yaml");
 
-    let result = _compose_prompt(recipe_path.to_str().unwrap(), None).expect("compose prompt");
+    let result = compose_prompt(recipe_path.to_str().unwrap(), None).expect("compose prompt");
 
     insta::assert_snapshot!(result.final_prompt);
 